@@ -2,6 +2,9 @@
 //! Gemini, custom) with a unified interface for command building, output parsing,
 //! and auth-error detection.
 
+use serde::{Deserialize, Serialize};
+use crate::runtime::Usage;
+
 /// Known agent provider types. Matches the `agentProvider` field from the frontend.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ProviderKind {
@@ -24,11 +27,48 @@ impl ProviderKind {
     }
 }
 
+/// Model and sampling parameters for a run. Unset fields fall back to the
+/// provider's defaults. CLIs only take the model; the direct API and
+/// OpenClaw use every field.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ModelConfig {
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub stop_sequences: Vec<String>,
+}
+
+impl ModelConfig {
+    /// This config with every field set in `overrides` replaced.
+    pub fn merged(&self, overrides: &ModelConfig) -> ModelConfig {
+        ModelConfig {
+            model: overrides.model.clone().or_else(|| self.model.clone()),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            stop_sequences: if overrides.stop_sequences.is_empty() {
+                self.stop_sequences.clone()
+            } else {
+                overrides.stop_sequences.clone()
+            },
+        }
+    }
+
+    /// The model name, unless empty.
+    pub fn model_name(&self) -> Option<&str> {
+        self.model.as_deref().map(str::trim).filter(|m| !m.is_empty())
+    }
+}
+
 /// Configuration for a specific agent provider invocation.
 pub struct ProviderConfig {
     pub kind: ProviderKind,
     /// For custom providers: the command template with `{prompt}` placeholder.
     pub custom_command: Option<String>,
+    /// Passed as `--model` to the Claude, Codex and Gemini CLIs.
+    pub model: Option<String>,
 }
 
 /// Result of building a provider command.
@@ -57,28 +97,46 @@ done"#.to_string()
 // ── Command builders ──────────────────────────────────────────────────
 
 impl ProviderConfig {
+    /// ` --model <name>` for CLIs that take one, or empty. Model names are
+    /// inlined into the shell command, so only plain identifiers are allowed.
+    fn model_flag(&self) -> String {
+        let Some(model) = self.model.as_deref().filter(|m| !m.is_empty()) else {
+            return String::new();
+        };
+        if !matches!(self.kind, ProviderKind::Claude | ProviderKind::Codex | ProviderKind::Gemini) {
+            return String::new();
+        }
+        if model.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':' | '/' | '@')) {
+            format!(" --model {model}")
+        } else {
+            tracing::warn!("Invalid model name rejected (possible injection): {:?}", model);
+            String::new()
+        }
+    }
+
     /// Build the CLI command for one-shot (non-streaming) execution.
     pub fn build_oneshot_cmd(&self) -> ProviderCommand {
         let env = build_shell_env();
+        let model = self.model_flag();
         match self.kind {
             ProviderKind::Claude => ProviderCommand {
                 bash_command: format!(
-                    "{}\nclaude --print --dangerously-skip-permissions \"$JAIBBER_PROMPT\"",
-                    env
+                    "{}\nclaude --print{} --dangerously-skip-permissions \"$JAIBBER_PROMPT\"",
+                    env, model
                 ),
                 api_key_env_var: Some("ANTHROPIC_API_KEY"),
             },
             ProviderKind::Codex => ProviderCommand {
                 bash_command: format!(
-                    "{}\ncodex --quiet --full-auto \"$JAIBBER_PROMPT\"",
-                    env
+                    "{}\ncodex --quiet --full-auto{} \"$JAIBBER_PROMPT\"",
+                    env, model
                 ),
                 api_key_env_var: Some("OPENAI_API_KEY"),
             },
             ProviderKind::Gemini => ProviderCommand {
                 bash_command: format!(
-                    "{}\ngemini -p \"$JAIBBER_PROMPT\"",
-                    env
+                    "{}\ngemini{} -p \"$JAIBBER_PROMPT\"",
+                    env, model
                 ),
                 api_key_env_var: Some("GOOGLE_API_KEY"),
             },
@@ -111,8 +169,10 @@ impl ProviderConfig {
         has_system_prompt: bool,
         session_id: Option<&str>,
         continue_session: bool,
+        images: usize,
     ) -> ProviderCommand {
         let env = build_shell_env();
+        let model = self.model_flag();
         match self.kind {
             ProviderKind::Claude => {
                 // Build session flags (only for Claude)
@@ -130,19 +190,26 @@ impl ProviderConfig {
                 } else {
                     String::new()
                 };
+                // With images, the prompt and image blocks arrive on stdin as a
+                // stream-json user message (see `claude_stream_input`)
+                let (input_flag, prompt_arg) = if images > 0 {
+                    (" --input-format stream-json", "")
+                } else {
+                    ("", " \"$JAIBBER_PROMPT\"")
+                };
 
                 let cmd = if has_system_prompt {
                     format!(
-                        "{}\nclaude --print --verbose --output-format stream-json{} \
+                        "{}\nclaude --print --verbose{} --output-format stream-json{}{} \
                          --append-system-prompt \"$JAIBBER_SYSTEM\" \
-                         --dangerously-skip-permissions \"$JAIBBER_PROMPT\"",
-                        env, session_flag
+                         --dangerously-skip-permissions{}",
+                        env, input_flag, session_flag, model, prompt_arg
                     )
                 } else {
                     format!(
-                        "{}\nclaude --print --verbose --output-format stream-json{} \
-                         --dangerously-skip-permissions \"$JAIBBER_PROMPT\"",
-                        env, session_flag
+                        "{}\nclaude --print --verbose{} --output-format stream-json{}{} \
+                         --dangerously-skip-permissions{}",
+                        env, input_flag, session_flag, model, prompt_arg
                     )
                 };
                 ProviderCommand {
@@ -151,14 +218,19 @@ impl ProviderConfig {
                 }
             }
             ProviderKind::Codex => {
-                // Codex CLI streams to stdout by default. System prompt via -i flag.
+                // Codex CLI streams to stdout by default. It has no system prompt
+                // flag, so the system prompt is prepended. Images go last, since
+                // -i takes several values and would swallow the prompt.
+                let image_flags: String = (1..=images)
+                    .map(|n| format!(" -i \"$JAIBBER_IMAGE_{n}\""))
+                    .collect();
                 let cmd = if has_system_prompt {
                     format!(
-                        "{}\ncodex --quiet --full-auto -i \"$JAIBBER_SYSTEM\" \"$JAIBBER_PROMPT\"",
-                        env
+                        "{}\ncodex --quiet --full-auto{} \"$JAIBBER_SYSTEM\n\n$JAIBBER_PROMPT\"{}",
+                        env, model, image_flags
                     )
                 } else {
-                    format!("{}\ncodex --quiet --full-auto \"$JAIBBER_PROMPT\"", env)
+                    format!("{}\ncodex --quiet --full-auto{} \"$JAIBBER_PROMPT\"{}", env, model, image_flags)
                 };
                 ProviderCommand {
                     bash_command: cmd,
@@ -170,11 +242,11 @@ impl ProviderConfig {
                 // System prompt via env var or prepended to prompt.
                 let cmd = if has_system_prompt {
                     format!(
-                        "{}\ngemini -p \"$JAIBBER_SYSTEM\n\n$JAIBBER_PROMPT\"",
-                        env
+                        "{}\ngemini{} -p \"$JAIBBER_SYSTEM\n\n$JAIBBER_PROMPT\"",
+                        env, model
                     )
                 } else {
-                    format!("{}\ngemini -p \"$JAIBBER_PROMPT\"", env)
+                    format!("{}\ngemini{} -p \"$JAIBBER_PROMPT\"", env, model)
                 };
                 ProviderCommand {
                     bash_command: cmd,
@@ -239,14 +311,40 @@ impl ProviderConfig {
     }
 }
 
+// ── Input ─────────────────────────────────────────────────────────────
+
+/// Stdin for `claude --input-format stream-json`: one user message with the
+/// prompt and base64 image blocks, as (MIME type, bytes) pairs.
+pub fn claude_stream_input(prompt: &str, images: &[(&str, &[u8])]) -> String {
+    use base64::Engine;
+    let mut content = vec![serde_json::json!({ "type": "text", "text": prompt })];
+    content.extend(images.iter().map(|(mime, bytes)| serde_json::json!({
+        "type": "image",
+        "source": {
+            "type": "base64",
+            "media_type": mime,
+            "data": base64::engine::general_purpose::STANDARD.encode(bytes),
+        },
+    })));
+    let message = serde_json::json!({
+        "type": "user",
+        "message": { "role": "user", "content": content },
+    });
+    format!("{message}\n")
+}
+
 // ── Output parsing ────────────────────────────────────────────────────
 
 /// Parsed result from a stream output line.
 pub struct ParsedLine {
     /// Text content to display (empty if line should be skipped).
     pub text: String,
+    /// Extended thinking text (Claude only), kept out of `text`.
+    pub thinking: String,
     /// Session ID if found in this line (Claude only — from initial message event).
     pub session_id: Option<String>,
+    /// Token usage and cost (Claude only — from the final result event).
+    pub usage: Option<Usage>,
 }
 
 /// Extract text content from a stream output line, based on the provider.
@@ -261,50 +359,71 @@ pub fn extract_text_from_line(kind: &ProviderKind, line: &str) -> ParsedLine {
             } else {
                 format!("{}\n", line)
             },
+            thinking: String::new(),
             session_id: None,
+            usage: None,
         },
     }
 }
 
 /// Claude-specific: parse stream-json event format.
-/// Extracts text content and session_id (from initial system/result events).
+/// Extracts text content, thinking, and session_id (from initial system/result events).
 fn extract_text_claude(line: &str) -> ParsedLine {
+    let mut parsed = ParsedLine { text: String::new(), thinking: String::new(), session_id: None, usage: None };
     if let Ok(json) = serde_json::from_str::<serde_json::Value>(line) {
         // Extract session_id if present (appears in init/system/result messages)
-        let session_id = json.get("session_id")
+        parsed.session_id = json.get("session_id")
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
 
         // Partial content block delta: {"type":"content_block_delta","delta":{"type":"text_delta","text":"..."}}
+        // or {"delta":{"type":"thinking_delta","thinking":"..."}}
         if let Some(delta) = json.get("delta") {
             if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
-                return ParsedLine { text: text.to_string(), session_id };
+                parsed.text = text.to_string();
+                return parsed;
+            }
+            if let Some(thinking) = delta.get("thinking").and_then(|t| t.as_str()) {
+                parsed.thinking = thinking.to_string();
+                return parsed;
             }
         }
 
         // Complete message: {"type":"message","content":[{"type":"text","text":"..."}]}
-        // or: {"message":{"content":[{"type":"text","text":"..."}]}}
+        // or: {"message":{"content":[{"type":"thinking","thinking":"..."},{"type":"text","text":"..."}]}}
         let content = json.get("content")
             .or_else(|| json.get("message").and_then(|m| m.get("content")));
         if let Some(serde_json::Value::Array(items)) = content {
-            let mut text = String::new();
             for item in items {
-                if item.get("type").and_then(|t| t.as_str()) == Some("text") {
-                    if let Some(t) = item.get("text").and_then(|t| t.as_str()) {
-                        text.push_str(t);
+                match item.get("type").and_then(|t| t.as_str()) {
+                    Some("text") => {
+                        if let Some(t) = item.get("text").and_then(|t| t.as_str()) {
+                            parsed.text.push_str(t);
+                        }
+                    }
+                    Some("thinking") => {
+                        if let Some(t) = item.get("thinking").and_then(|t| t.as_str()) {
+                            parsed.thinking.push_str(t);
+                        }
                     }
+                    _ => {}
                 }
             }
-            return ParsedLine { text, session_id };
+            return parsed;
         }
 
-        ParsedLine { text: String::new(), session_id }
+        // Final result: {"type":"result","total_cost_usd":0.01,"usage":{"input_tokens":..}}
+        if json.get("type").and_then(|t| t.as_str()) == Some("result") {
+            parsed.usage = json.get("usage").map(|u| Usage {
+                cost_usd: json.get("total_cost_usd").and_then(|c| c.as_f64()),
+                ..Usage::from_api(u)
+            });
+        }
     } else if !line.trim().is_empty() {
         // Non-JSON line from Claude — emit as raw text (fallback)
-        ParsedLine { text: format!("{}\n", line), session_id: None }
-    } else {
-        ParsedLine { text: String::new(), session_id: None }
+        parsed.text = format!("{}\n", line);
     }
+    parsed
 }
 
 // ── Auth error detection ──────────────────────────────────────────────
@@ -0,0 +1,916 @@
+//! Typed async client for the Jaibber REST API at `AppSettings.api_base_url`.
+//!
+//! Mirrors the endpoints the frontend uses (`src/lib/*Api.ts`) so backend
+//! features can talk to the server directly instead of relaying through the
+//! webview.
+//!
+//! - Auth: the bearer token comes from `login()` when called, otherwise from
+//!   the frontend's `auth` store entry. A 401 first retries with a newer
+//!   stored token (the user may have signed in again), then refreshes the
+//!   JWT once. The server has no refresh endpoint, so `refresh()` signs in
+//!   again through `POST /api/auth/token` with the credentials `login()` was
+//!   given; without them a 401 is final.
+//! - Retries: connection errors, 429 and 5xx are retried with exponential
+//!   backoff and jitter. POSTs are only retried when they carry an
+//!   idempotency key or the request never reached the server.
+//! - Rate limiting: at most `MAX_IN_FLIGHT` requests on the wire; a 429
+//!   pauses every request until its `Retry-After` passes. Waiting requests
+//!   don't hold a slot.
+
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use futures_util::StreamExt;
+use reqwest::{Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+use crate::error::JaibberError;
+
+const MAX_RETRIES: u32 = 3;
+const BASE_BACKOFF_MS: u64 = 500;
+/// Longest `Retry-After` we are willing to honor for a single attempt.
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+const MAX_IN_FLIGHT: usize = 4;
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+// ── Types ─────────────────────────────────────────────────────────────
+
+/// Result of `POST /api/auth/token`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthSession {
+    pub token: String,
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub username: String,
+}
+
+/// Result of `GET /api/auth/me`.
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub user_id: String,
+    pub username: String,
+}
+
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    #[serde(default)]
+    pub org_id: Option<String>,
+    #[serde(default)]
+    pub ably_channel_name: String,
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: String,
+    pub project_id: String,
+    #[serde(default)]
+    pub sender_id: String,
+    /// `"user"`, `"agent"` or `"api"`.
+    pub sender_type: String,
+    pub sender_name: String,
+    /// `"message"`, `"response"`, `"error"` or `"system"`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+    #[serde(default)]
+    pub parent_message_id: Option<String>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+    pub created_at: String,
+}
+
+/// Body of `POST /api/projects/{id}/messages`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMessage {
+    pub id: String,
+    pub sender_type: String,
+    pub sender_name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_message_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    pub id: String,
+    pub project_id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `submitted`, `working`, `input-required`, `completed`, `failed` or `cancelled`.
+    pub status: String,
+    #[serde(default)]
+    pub priority: Option<String>,
+    #[serde(default)]
+    pub assigned_agent_name: Option<String>,
+    #[serde(default)]
+    pub source_message_id: Option<String>,
+    #[serde(default)]
+    pub parent_task_id: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    /// Worker holding the task (set by the native task worker on claim).
+    #[serde(default)]
+    pub lease_owner: Option<String>,
+    /// RFC 3339 time the lease lapses unless renewed.
+    #[serde(default)]
+    pub lease_expires_at: Option<String>,
+}
+
+/// Filters for `GET /api/projects/{id}/tasks`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter<'a> {
+    pub status: Option<&'a str>,
+    pub assigned_agent_name: Option<&'a str>,
+    pub limit: Option<u32>,
+    pub before: Option<&'a str>,
+}
+
+/// Body of `POST /api/projects/{id}/tasks`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewTask {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assigned_agent_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_message_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_task_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_by_name: Option<String>,
+}
+
+/// A server-side agent registration (`/api/agent-registrations`).
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentRegistration {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub project_id: String,
+    pub agent_name: String,
+    #[serde(default)]
+    pub agent_instructions: String,
+    #[serde(default)]
+    pub agent_provider: String,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    #[serde(default)]
+    pub machine_name: Option<String>,
+}
+
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileAttachment {
+    pub id: String,
+    pub project_id: String,
+    #[serde(default)]
+    pub message_id: Option<String>,
+    pub filename: String,
+    pub mime_type: String,
+    pub file_size: u64,
+    pub blob_url: String,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+/// Body of `POST /api/projects/{id}/attachments` (metadata for an uploaded blob).
+#[allow(dead_code)] // No backend caller yet
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAttachment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    pub filename: String,
+    pub mime_type: String,
+    pub file_size: u64,
+    pub blob_url: String,
+}
+
+/// One page of a cursor-paginated listing.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub cursor: Option<String>,
+    pub has_more: bool,
+}
+
+#[derive(Deserialize)]
+struct Data<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    #[serde(default)]
+    cursor: Option<String>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+// ── Client ────────────────────────────────────────────────────────────
+
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: RwLock<String>,
+    /// Session from `login()`/`refresh()`; overrides the stored token.
+    session: RwLock<Option<Session>>,
+    /// Store file holding the frontend's `auth` entry.
+    store_path: PathBuf,
+    in_flight: Semaphore,
+    /// Set by a 429: every request waits until this instant.
+    paused_until: RwLock<Option<Instant>>,
+}
+
+/// A token obtained by this client, with the credentials to renew it.
+struct Session {
+    token: String,
+    username: String,
+    password: String,
+}
+
+/// Options for a single request.
+#[derive(Default)]
+struct RequestOptions<'a> {
+    query: Vec<(&'a str, String)>,
+    body: Option<Value>,
+    /// Sent as `Idempotency-Key`; makes POSTs safe to retry.
+    idempotency_key: Option<&'a str>,
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str, store_path: PathBuf) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .build()
+            .unwrap_or_default();
+        Self {
+            http,
+            base_url: RwLock::new(base_url.trim_end_matches('/').to_string()),
+            session: RwLock::new(None),
+            store_path,
+            in_flight: Semaphore::new(MAX_IN_FLIGHT),
+            paused_until: RwLock::new(None),
+        }
+    }
+
+    /// Point the client at a different server (called when settings change).
+    pub fn set_base_url(&self, base_url: &str) {
+        *self.base_url.write().unwrap() = base_url.trim_end_matches('/').to_string();
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url.read().unwrap().clone()
+    }
+
+    /// The bearer token currently in use, if signed in.
+    pub fn token(&self) -> Option<String> {
+        if let Some(session) = self.session.read().unwrap().as_ref() {
+            return Some(session.token.clone());
+        }
+        self.stored_token()
+    }
+
+    pub fn is_signed_in(&self) -> bool {
+        self.token().is_some()
+    }
+
+    fn stored_token(&self) -> Option<String> {
+        crate::local_store::load_auth(&self.store_path)
+            .map(|a| a.token)
+            .filter(|t| !t.is_empty())
+    }
+
+    // ── Auth ──────────────────────────────────────────────────────────
+
+    /// Log in with username/password. The token is used for later calls and
+    /// the credentials are kept in memory for `refresh()`.
+    pub async fn login(&self, username: &str, password: &str) -> Result<AuthSession, JaibberError> {
+        let session = self.issue_token(username, password).await?;
+        *self.session.write().unwrap() = Some(Session {
+            token: session.token.clone(),
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        Ok(session)
+    }
+
+    /// Get a fresh JWT by signing in again with the `login()` credentials.
+    pub async fn refresh(&self) -> Result<String, JaibberError> {
+        let credentials = self.session.read().unwrap()
+            .as_ref()
+            .map(|s| (s.username.clone(), s.password.clone()));
+        let (username, password) = credentials.ok_or(JaibberError::NotSignedIn)?;
+        Ok(self.login(&username, &password).await?.token)
+    }
+
+    /// `POST /api/auth/token`. Sent directly rather than through `request()`,
+    /// which calls back into `refresh()` on a 401.
+    async fn issue_token(&self, username: &str, password: &str) -> Result<AuthSession, JaibberError> {
+        let res = self.http
+            .post(format!("{}/api/auth/token", self.base_url()))
+            .json(&json!({ "username": username, "password": password }))
+            .send()
+            .await
+            .map_err(|e| JaibberError::Http(e.to_string()))?;
+        if !res.status().is_success() {
+            return Err(api_error(res).await);
+        }
+        res.json().await.map_err(|e| JaibberError::Http(format!("Invalid response from /api/auth/token: {e}")))
+    }
+
+    #[allow(dead_code)]
+    /// The signed-in user (`/api/auth/me`).
+    pub async fn me(&self) -> Result<User, JaibberError> {
+        self.request_json(Method::GET, "/api/auth/me", RequestOptions::default()).await
+    }
+
+    // ── Projects ──────────────────────────────────────────────────────
+
+    #[allow(dead_code)]
+    pub async fn list_projects(&self) -> Result<Vec<Project>, JaibberError> {
+        #[derive(Deserialize)]
+        struct Projects {
+            projects: Vec<Project>,
+        }
+        let res: Projects = self.request_json(Method::GET, "/api/projects", RequestOptions::default()).await?;
+        Ok(res.projects)
+    }
+
+    // ── Messages ──────────────────────────────────────────────────────
+
+    #[allow(dead_code)]
+    pub async fn list_messages(
+        &self,
+        project_id: &str,
+        limit: Option<u32>,
+        before: Option<&str>,
+    ) -> Result<Page<Message>, JaibberError> {
+        #[derive(Deserialize)]
+        struct MessagePage {
+            data: Vec<Message>,
+            pagination: PageInfo,
+        }
+        let mut query = Vec::new();
+        if let Some(limit) = limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(before) = before {
+            query.push(("before", before.to_string()));
+        }
+        let page: MessagePage = self.request_json(
+            Method::GET,
+            &format!("/api/projects/{project_id}/messages"),
+            RequestOptions { query, ..Default::default() },
+        ).await?;
+        Ok(Page { items: page.data, cursor: page.pagination.cursor, has_more: page.pagination.has_more })
+    }
+
+    /// Persist a message. The message ID doubles as the idempotency key, so
+    /// retries never duplicate it.
+    pub async fn create_message(&self, project_id: &str, message: &NewMessage) -> Result<(), JaibberError> {
+        self.request(Method::POST, &format!("/api/projects/{project_id}/messages"), RequestOptions {
+            body: Some(serde_json::to_value(message)?),
+            idempotency_key: Some(&message.id),
+            ..Default::default()
+        }).await.map(|_| ())
+    }
+
+    // ── Tasks ─────────────────────────────────────────────────────────
+
+    pub async fn list_tasks(&self, project_id: &str, filter: &TaskFilter<'_>) -> Result<Page<Task>, JaibberError> {
+        #[derive(Deserialize)]
+        struct TaskPage {
+            data: Vec<Task>,
+            meta: PageInfo,
+        }
+        let mut query = Vec::new();
+        if let Some(status) = filter.status {
+            query.push(("status", status.to_string()));
+        }
+        if let Some(name) = filter.assigned_agent_name {
+            query.push(("assignedAgentName", name.to_string()));
+        }
+        if let Some(limit) = filter.limit {
+            query.push(("limit", limit.to_string()));
+        }
+        if let Some(before) = filter.before {
+            query.push(("before", before.to_string()));
+        }
+        let page: TaskPage = self.request_json(
+            Method::GET,
+            &format!("/api/projects/{project_id}/tasks"),
+            RequestOptions { query, ..Default::default() },
+        ).await?;
+        Ok(Page { items: page.data, cursor: page.meta.cursor, has_more: page.meta.has_more })
+    }
+
+    pub async fn create_task(
+        &self,
+        project_id: &str,
+        task: &NewTask,
+        idempotency_key: Option<&str>,
+    ) -> Result<Task, JaibberError> {
+        let res: Data<Task> = self.request_json(Method::POST, &format!("/api/projects/{project_id}/tasks"), RequestOptions {
+            body: Some(serde_json::to_value(task)?),
+            idempotency_key,
+            ..Default::default()
+        }).await?;
+        Ok(res.data)
+    }
+
+    /// Partially update a task (`status`, `title`, ... plus any extra fields
+    /// the server accepts).
+    pub async fn update_task(
+        &self,
+        task_id: &str,
+        updates: Value,
+        idempotency_key: Option<&str>,
+    ) -> Result<Task, JaibberError> {
+        let res: Data<Task> = self.request_json(Method::PATCH, &format!("/api/tasks/{task_id}"), RequestOptions {
+            body: Some(updates),
+            idempotency_key,
+            ..Default::default()
+        }).await?;
+        Ok(res.data)
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_task(&self, task_id: &str) -> Result<(), JaibberError> {
+        self.request(Method::DELETE, &format!("/api/tasks/{task_id}"), RequestOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    // ── Agents ────────────────────────────────────────────────────────
+
+    #[allow(dead_code)]
+    pub async fn list_agent_registrations(&self) -> Result<Vec<AgentRegistration>, JaibberError> {
+        let res: Data<Vec<AgentRegistration>> = self.request_json(
+            Method::GET,
+            "/api/agent-registrations",
+            RequestOptions::default(),
+        ).await?;
+        Ok(res.data)
+    }
+
+    #[allow(dead_code)]
+    /// Create or update the registration for (project, agent).
+    pub async fn put_agent_registration(&self, registration: &AgentRegistration) -> Result<(), JaibberError> {
+        self.request(Method::PUT, "/api/agent-registrations", RequestOptions {
+            body: Some(serde_json::to_value(registration)?),
+            ..Default::default()
+        }).await.map(|_| ())
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_agent_registration(&self, project_id: &str, agent_name: Option<&str>) -> Result<(), JaibberError> {
+        let mut query = vec![("projectId", project_id.to_string())];
+        if let Some(name) = agent_name {
+            query.push(("agentName", name.to_string()));
+        }
+        self.request(Method::DELETE, "/api/agent-registrations", RequestOptions {
+            query,
+            ..Default::default()
+        }).await.map(|_| ())
+    }
+
+    // ── Attachments ───────────────────────────────────────────────────
+
+    #[allow(dead_code)]
+    pub async fn list_attachments(&self, project_id: &str, message_id: Option<&str>) -> Result<Vec<FileAttachment>, JaibberError> {
+        let query = message_id.map(|id| vec![("messageId", id.to_string())]).unwrap_or_default();
+        let res: Data<Vec<FileAttachment>> = self.request_json(
+            Method::GET,
+            &format!("/api/projects/{project_id}/attachments"),
+            RequestOptions { query, ..Default::default() },
+        ).await?;
+        Ok(res.data)
+    }
+
+    #[allow(dead_code)]
+    /// Record metadata for a blob that has already been uploaded.
+    pub async fn create_attachment(&self, project_id: &str, attachment: &NewAttachment) -> Result<FileAttachment, JaibberError> {
+        let res: Data<FileAttachment> = self.request_json(
+            Method::POST,
+            &format!("/api/projects/{project_id}/attachments"),
+            RequestOptions { body: Some(serde_json::to_value(attachment)?), ..Default::default() },
+        ).await?;
+        Ok(res.data)
+    }
+
+    #[allow(dead_code)]
+    pub async fn link_attachment(&self, project_id: &str, attachment_id: &str, message_id: &str) -> Result<(), JaibberError> {
+        self.request(
+            Method::PATCH,
+            &format!("/api/projects/{project_id}/attachments/{attachment_id}"),
+            RequestOptions { body: Some(json!({ "messageId": message_id })), ..Default::default() },
+        ).await.map(|_| ())
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_attachment(&self, project_id: &str, attachment_id: &str) -> Result<(), JaibberError> {
+        self.request(
+            Method::DELETE,
+            &format!("/api/projects/{project_id}/attachments/{attachment_id}"),
+            RequestOptions::default(),
+        ).await.map(|_| ())
+    }
+
+    /// Download an attachment's bytes, failing once the body exceeds
+    /// `max_bytes`. The bearer token is only sent when the blob is served by
+    /// the Jaibber API itself.
+    pub async fn download_attachment(&self, blob_url: &str, max_bytes: u64) -> Result<Vec<u8>, JaibberError> {
+        let mut req = self.http.get(blob_url);
+        if self.is_api_url(blob_url) {
+            if let Some(token) = self.token() {
+                req = req.bearer_auth(token);
+            }
+        }
+        let res = req.send().await.map_err(|e| JaibberError::Http(e.to_string()))?;
+        if !res.status().is_success() {
+            return Err(api_error(res).await);
+        }
+        let too_large = || JaibberError::Other(format!("Attachment is larger than {max_bytes} bytes"));
+        if res.content_length().is_some_and(|len| len > max_bytes) {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        let mut stream = res.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| JaibberError::Http(e.to_string()))?;
+            if bytes.len() as u64 + chunk.len() as u64 > max_bytes {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+
+    /// Whether `url` has the same scheme, host and port as the API, i.e. may
+    /// be sent the user's token.
+    pub fn is_api_url(&self, url: &str) -> bool {
+        let (Ok(url), Ok(base)) = (reqwest::Url::parse(url), reqwest::Url::parse(&self.base_url())) else {
+            return false;
+        };
+        url.scheme() == base.scheme()
+            && url.host_str().is_some()
+            && url.host_str() == base.host_str()
+            && url.port_or_known_default() == base.port_or_known_default()
+    }
+
+    // ── Transport ─────────────────────────────────────────────────────
+
+    async fn request_json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        options: RequestOptions<'_>,
+    ) -> Result<T, JaibberError> {
+        let res = self.request(method, path, options).await?;
+        res.json::<T>().await.map_err(|e| JaibberError::Http(format!("Invalid response from {path}: {e}")))
+    }
+
+    /// Send a request with auth, rate limiting and retries. Returns the
+    /// successful response or the mapped error.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        options: RequestOptions<'_>,
+    ) -> Result<reqwest::Response, JaibberError> {
+        let retry_post = method != Method::POST || options.idempotency_key.is_some();
+        let mut auth_retried = false;
+        let mut attempt = 0;
+
+        loop {
+            self.wait_if_paused().await;
+
+            let token = self.token().ok_or(JaibberError::NotSignedIn)?;
+            let mut req = self.http
+                .request(method.clone(), format!("{}{path}", self.base_url()))
+                .query(&options.query)
+                .bearer_auth(&token);
+            if let Some(key) = options.idempotency_key {
+                req = req.header("Idempotency-Key", key);
+            }
+            if let Some(ref body) = options.body {
+                req = req.json(body);
+            }
+
+            // Hold a slot only while the request is on the wire, never
+            // across the sleeps below.
+            let sent = {
+                let _permit = self.in_flight.acquire().await
+                    .map_err(|e| JaibberError::Other(e.to_string()))?;
+                req.send().await
+            };
+            let res = match sent {
+                Ok(res) => res,
+                Err(e) => {
+                    // Connection failures never reached the server, so any method may retry
+                    let safe = retry_post || e.is_connect();
+                    if safe && attempt < MAX_RETRIES && (e.is_connect() || e.is_timeout() || e.is_request()) {
+                        attempt += 1;
+                        tokio::time::sleep(backoff(attempt)).await;
+                        continue;
+                    }
+                    return Err(JaibberError::Http(e.to_string()));
+                }
+            };
+
+            let status = res.status();
+            if status.is_success() {
+                return Ok(res);
+            }
+
+            if status == StatusCode::UNAUTHORIZED && !auth_retried {
+                auth_retried = true;
+                if self.recover_auth(&token).await {
+                    continue;
+                }
+                return Err(api_error(res).await);
+            }
+
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if retryable && retry_post && attempt < MAX_RETRIES {
+                attempt += 1;
+                let wait = crate::retry::retry_after(res.headers())
+                    .map(|d| d.min(Duration::from_secs(MAX_RETRY_AFTER_SECS)))
+                    .unwrap_or_else(|| backoff(attempt));
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    *self.paused_until.write().unwrap() = Some(Instant::now() + wait);
+                    tracing::warn!("[api] Rate limited on {path}; pausing {}ms", wait.as_millis());
+                } else {
+                    tokio::time::sleep(wait).await;
+                }
+                continue;
+            }
+
+            return Err(api_error(res).await);
+        }
+    }
+
+    /// After a 401: switch to a newer stored token if the user signed in
+    /// again, otherwise refresh the JWT. Returns true when a retry makes sense.
+    async fn recover_auth(&self, used: &str) -> bool {
+        let has_session = self.session.read().unwrap().is_some();
+        if !has_session && self.stored_token().is_some_and(|stored| stored != used) {
+            return true;
+        }
+        match self.refresh().await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::debug!("[api] Token refresh failed: {e}");
+                false
+            }
+        }
+    }
+
+    async fn wait_if_paused(&self) {
+        let until = *self.paused_until.read().unwrap();
+        if let Some(until) = until {
+            let now = Instant::now();
+            if until > now {
+                tokio::time::sleep(until - now).await;
+            }
+        }
+    }
+}
+
+/// Exponential backoff with jitter: 0.5s, 1s, 2s ... ±25%.
+fn backoff(attempt: u32) -> Duration {
+    let base = BASE_BACKOFF_MS * 2u64.pow(attempt.saturating_sub(1));
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter = nanos % (base / 2 + 1);
+    Duration::from_millis(base - base / 4 + jitter)
+}
+
+/// Map an error response to `JaibberError::Api`, using the server's
+/// `{ "error": "..." }` message when present.
+async fn api_error(res: reqwest::Response) -> JaibberError {
+    let status = res.status();
+    let text = res.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<Value>(&text)
+        .ok()
+        .and_then(|v| {
+            v.get("error")
+                .and_then(|e| e.as_str().map(|s| s.to_string()).or_else(|| e.get("message")?.as_str().map(|s| s.to_string())))
+                .or_else(|| v.get("message")?.as_str().map(|s| s.to_string()))
+        })
+        .unwrap_or_else(|| {
+            if text.is_empty() { status.canonical_reason().unwrap_or("Request failed").to_string() } else { text }
+        });
+    JaibberError::Api { status: status.as_u16(), message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use axum::extract::State;
+    use axum::http::HeaderMap;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+
+    #[test]
+    fn serializes_request_bodies_in_camel_case() {
+        let message = NewMessage {
+            id: "m1".into(),
+            sender_type: "agent".into(),
+            sender_name: "Coder".into(),
+            kind: "response".into(),
+            text: "done".into(),
+            parent_message_id: None,
+        };
+        assert_eq!(serde_json::to_value(&message).unwrap(), json!({
+            "id": "m1",
+            "senderType": "agent",
+            "senderName": "Coder",
+            "type": "response",
+            "text": "done",
+        }));
+
+        let task = NewTask {
+            title: "Fix it".into(),
+            assigned_agent_name: Some("Coder".into()),
+            ..Default::default()
+        };
+        assert_eq!(serde_json::to_value(&task).unwrap(), json!({ "title": "Fix it", "assignedAgentName": "Coder" }));
+
+        let registration = AgentRegistration {
+            id: String::new(),
+            project_id: "p1".into(),
+            agent_name: "Coder".into(),
+            agent_instructions: String::new(),
+            agent_provider: "claude".into(),
+            custom_command: None,
+            machine_name: Some("laptop".into()),
+        };
+        let value = serde_json::to_value(&registration).unwrap();
+        assert!(value.get("id").is_none());
+        assert_eq!(value["projectId"], "p1");
+        assert_eq!(value["machineName"], "laptop");
+
+        let attachment = NewAttachment {
+            message_id: None,
+            filename: "a.png".into(),
+            mime_type: "image/png".into(),
+            file_size: 10,
+            blob_url: "https://blob/a.png".into(),
+        };
+        let value = serde_json::to_value(&attachment).unwrap();
+        assert!(value.get("messageId").is_none());
+        assert_eq!(value["mimeType"], "image/png");
+        assert_eq!(value["fileSize"], 10);
+    }
+
+    #[test]
+    fn parses_responses_with_missing_optional_fields() {
+        let session: AuthSession = serde_json::from_value(json!({ "token": "t" })).unwrap();
+        assert_eq!(session.token, "t");
+        assert!(session.user_id.is_empty());
+
+        let project: Project = serde_json::from_value(json!({ "id": "p1", "name": "Site" })).unwrap();
+        assert_eq!(project.ably_channel_name, "");
+        assert!(project.role.is_none());
+
+        let message: Message = serde_json::from_value(json!({
+            "id": "m1",
+            "projectId": "p1",
+            "senderType": "user",
+            "senderName": "ana",
+            "type": "message",
+            "text": "hi",
+            "createdAt": "2026-01-01T00:00:00Z",
+        })).unwrap();
+        assert_eq!(message.kind, "message");
+        assert!(message.parent_message_id.is_none());
+
+        let task: Task = serde_json::from_value(json!({
+            "id": "t1",
+            "projectId": "p1",
+            "title": "Fix it",
+            "status": "submitted",
+            "assignedAgentName": "Coder",
+        })).unwrap();
+        assert_eq!(task.assigned_agent_name.as_deref(), Some("Coder"));
+
+        let attachment: FileAttachment = serde_json::from_value(json!({
+            "id": "a1",
+            "projectId": "p1",
+            "filename": "a.png",
+            "mimeType": "image/png",
+            "fileSize": 10,
+            "blobUrl": "https://blob/a.png",
+        })).unwrap();
+        assert_eq!(attachment.file_size, 10);
+    }
+
+    /// Issues `token-1`, `token-2`, ... and only accepts the latest one.
+    #[derive(Default)]
+    struct AuthServer {
+        issued: AtomicUsize,
+        project_calls: AtomicUsize,
+    }
+
+    async fn issue(State(server): State<Arc<AuthServer>>) -> Json<Value> {
+        let n = server.issued.fetch_add(1, Ordering::SeqCst) + 1;
+        Json(json!({ "token": format!("token-{n}"), "userId": "u1", "username": "bot" }))
+    }
+
+    async fn projects(State(server): State<Arc<AuthServer>>, headers: HeaderMap) -> (StatusCode, Json<Value>) {
+        server.project_calls.fetch_add(1, Ordering::SeqCst);
+        let latest = format!("Bearer token-{}", server.issued.load(Ordering::SeqCst));
+        if headers.get("authorization").and_then(|v| v.to_str().ok()) == Some(latest.as_str()) {
+            (StatusCode::OK, Json(json!({ "projects": [{ "id": "p1", "name": "Site" }] })))
+        } else {
+            (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Token expired" })))
+        }
+    }
+
+    async fn serve() -> (Arc<AuthServer>, String) {
+        let server = Arc::new(AuthServer::default());
+        let app = Router::new()
+            .route("/api/auth/token", post(issue))
+            .route("/api/projects", get(projects))
+            .with_state(server.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.ok() });
+        (server, format!("http://{addr}"))
+    }
+
+    fn client(base_url: &str) -> ApiClient {
+        ApiClient::new(base_url, std::env::temp_dir().join("jaibber-api-test-missing.json"))
+    }
+
+    #[tokio::test]
+    async fn refreshes_the_token_after_a_401() {
+        let (server, base_url) = serve().await;
+        let api = client(&base_url);
+        api.login("bot", "secret").await.unwrap();
+        // Another login elsewhere invalidates token-1
+        server.issued.fetch_add(1, Ordering::SeqCst);
+
+        let projects = api.list_projects().await.unwrap();
+        assert_eq!(projects[0].id, "p1");
+        assert_eq!(server.project_calls.load(Ordering::SeqCst), 2);
+        assert_eq!(api.token().as_deref(), Some("token-3"));
+    }
+
+    #[tokio::test]
+    async fn fails_a_401_without_credentials_to_refresh_with() {
+        let (server, base_url) = serve().await;
+        let api = client(&base_url);
+        assert!(matches!(api.list_projects().await, Err(JaibberError::NotSignedIn)));
+
+        // A token saved by the frontend can't be refreshed from here
+        let store = std::env::temp_dir().join(format!("jaibber-api-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&store, json!({ "auth": { "token": "expired" } }).to_string()).unwrap();
+        let api = ApiClient::new(&base_url, store.clone());
+        let result = api.list_projects().await;
+        std::fs::remove_file(&store).ok();
+        assert!(matches!(result, Err(JaibberError::Api { status: 401, .. })));
+        assert_eq!(server.project_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(server.issued.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,472 @@
+//! Loading attachment contents for providers.
+//!
+//! An attachment comes from a blob URL or from a file on this machine
+//! (`local_path`, which must resolve inside the run's project directory).
+//! Blobs served by the Jaibber API are fetched with the signed-in user's
+//! bearer token, so private attachments work too.
+//!
+//! CLI agents can't take attachments over the wire, so for them the files
+//! are saved under `.jaibber/attachments/<run>/` in the project and listed
+//! in the prompt; directories are swept once the retention period is over.
+//!
+//! Files uploaded to the Anthropic Files API are remembered in
+//! `anthropic_files.json` next to the store, keyed by content hash (and by
+//! blob URL, so history can point back at them without downloading again).
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::api_client::ApiClient;
+use crate::runtime::now_ms;
+use crate::state::{AppState, AttachmentInfo};
+
+const FILE_CACHE_FILE: &str = "anthropic_files.json";
+/// Where CLI runs get their attachments, relative to the project directory.
+const STAGING_DIR: &str = ".jaibber/attachments";
+/// Largest attachment we download or read.
+pub const MAX_ATTACHMENT_BYTES: u64 = 100 * 1024 * 1024;
+/// Largest image sent inline (Anthropic's 5 MB limit applies after base64).
+pub const MAX_INLINE_IMAGE_BYTES: usize = 3_750_000;
+const MAX_CACHED_FILES: usize = 1_000;
+/// Characters of a text or extracted attachment included in the prompt.
+const MAX_TEXT_CHARS: usize = 100_000;
+
+/// Loads attachment bytes for one run.
+pub struct AttachmentLoader {
+    api: Arc<ApiClient>,
+    project_dir: PathBuf,
+    pub file_ids: Arc<FileIdCache>,
+}
+
+impl AttachmentLoader {
+    pub fn new(state: &AppState, project_dir: &str) -> Self {
+        Self {
+            api: state.api.clone(),
+            project_dir: PathBuf::from(project_dir),
+            file_ids: state.file_ids.clone(),
+        }
+    }
+
+    /// Read the attachment's bytes from its local path or blob URL.
+    pub async fn load(&self, att: &AttachmentInfo) -> Result<Vec<u8>, String> {
+        match att.local_path.as_deref().filter(|p| !p.is_empty()) {
+            Some(path) => self.read_local(path).await,
+            None if att.blob_url.is_empty() => Err("Attachment has neither a URL nor a local path".to_string()),
+            None => {
+                if att.file_size > MAX_ATTACHMENT_BYTES {
+                    return Err(format!("{} is too large ({} bytes)", att.filename, att.file_size));
+                }
+                self.api.download_attachment(&att.blob_url, MAX_ATTACHMENT_BYTES).await
+                    .map_err(|e| format!("Could not fetch {}: {e}", att.filename))
+            }
+        }
+    }
+
+    async fn read_local(&self, path: &str) -> Result<Vec<u8>, String> {
+        let full = resolve_local(&self.project_dir, path)?;
+        let size = tokio::fs::metadata(&full).await
+            .map_err(|e| format!("Cannot read {path}: {e}"))?
+            .len();
+        if size > MAX_ATTACHMENT_BYTES {
+            return Err(format!("{path} is too large ({size} bytes)"));
+        }
+        tokio::fs::read(&full).await.map_err(|e| format!("Cannot read {path}: {e}"))
+    }
+
+    /// The attachment as a prompt section (see `text_section`). Only for
+    /// `has_text` files.
+    pub async fn load_as_text(&self, att: &AttachmentInfo) -> Result<String, String> {
+        let bytes = self.load(att).await?;
+        text_section(att, bytes).await
+    }
+
+    /// The attachments as text for providers that only take a prompt.
+    /// Text and documents are inlined; other files are listed by name.
+    pub async fn prompt_section(&self, attachments: &[AttachmentInfo]) -> String {
+        let mut sections = Vec::new();
+        for att in attachments {
+            let section = if has_text(att) {
+                self.load_as_text(att).await.unwrap_or_else(|e| {
+                    tracing::warn!("[attachments] {e}");
+                    format!("[File: {} ({} bytes) — could not read content: {e}]", att.filename, att.file_size)
+                })
+            } else {
+                format!("[Attached file: {} ({}, {} bytes)]", att.filename, mime_type(att), att.file_size)
+            };
+            sections.push(section);
+        }
+        sections.join("\n\n")
+    }
+
+    /// Save the attachments for a CLI run under `.jaibber/attachments/<run>/`
+    /// in the project and describe them for the prompt. Files that already
+    /// live in the project are referenced in place. Run directories older
+    /// than the retention period are swept first.
+    pub async fn stage(&self, run_id: &str, attachments: &[AttachmentInfo], retention_hours: u64) -> StagedAttachments {
+        let root = self.project_dir.join(STAGING_DIR);
+        sweep(&root, retention_hours).await;
+
+        let mut staged = StagedAttachments { dir: None, images: Vec::new(), prompt: String::new() };
+        let mut listing = Vec::new();
+        let mut sections = Vec::new();
+        for att in attachments {
+            let mime = mime_type(att);
+            let bytes = match self.load(att).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    tracing::warn!("[attachments] {e}");
+                    listing.push(format!("- {}: could not be downloaded ({e})", att.filename));
+                    continue;
+                }
+            };
+            let path = match att.local_path.as_deref().filter(|p| !p.is_empty()) {
+                Some(path) => path.to_string(),
+                None => {
+                    let dir = root.join(run_id);
+                    match save(&dir, &att.filename, &bytes).await {
+                        Ok(name) => {
+                            staged.dir = Some(dir);
+                            format!("{STAGING_DIR}/{run_id}/{name}")
+                        }
+                        Err(e) => {
+                            tracing::warn!("[attachments] Could not save {}: {e}", att.filename);
+                            listing.push(format!("- {}: could not be saved ({e})", att.filename));
+                            continue;
+                        }
+                    }
+                }
+            };
+            listing.push(format!("- {path} ({mime}, {} bytes)", bytes.len()));
+            if has_text(att) {
+                sections.push(text_section(att, bytes).await.unwrap_or_else(|e| {
+                    format!("[File: {} — could not read content: {e}]", att.filename)
+                }));
+            } else if may_be_image(att) && crate::images::sniff(&bytes).is_some() {
+                match crate::images::prepare(bytes).await {
+                    Ok(image) if !image.converted => {
+                        staged.images.push(StagedImage { path, mime: image.mime.to_string(), bytes: image.bytes });
+                    }
+                    // Keep the original and save a viewable copy beside it
+                    Ok(image) => {
+                        let dir = root.join(run_id);
+                        let ext = if image.mime == "image/png" { "png" } else { "jpg" };
+                        match save(&dir, &format!("{}.{ext}", att.filename), &image.bytes).await {
+                            Ok(name) => {
+                                staged.dir = Some(dir);
+                                let path = format!("{STAGING_DIR}/{run_id}/{name}");
+                                listing.push(format!("  converted for viewing: {path}"));
+                                staged.images.push(StagedImage { path, mime: image.mime.to_string(), bytes: image.bytes });
+                            }
+                            Err(e) => tracing::warn!("[attachments] Could not save converted {}: {e}", att.filename),
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("[attachments] {}: {e}", att.filename);
+                        listing.push(format!("  (could not be converted for viewing: {e})"));
+                    }
+                }
+            }
+        }
+
+        staged.prompt = format!("Attached files (relative to the project directory):\n{}", listing.join("\n"));
+        if !sections.is_empty() {
+            staged.prompt = format!("{}\n\n{}", staged.prompt, sections.join("\n\n"));
+        }
+        staged
+    }
+
+    /// Whether `url` is served by the Jaibber API, i.e. private to its users
+    /// and not fetchable by third parties.
+    pub fn is_private_url(&self, url: &str) -> bool {
+        self.api.is_api_url(url)
+    }
+}
+
+/// Resolve a local attachment path inside `project_dir`. Relative paths are
+/// taken from the project root; anything that ends up outside is refused.
+fn resolve_local(project_dir: &Path, path: &str) -> Result<PathBuf, String> {
+    if project_dir.as_os_str().is_empty() {
+        return Err("Local attachments need a project directory".to_string());
+    }
+    let root = project_dir.canonicalize()
+        .map_err(|e| format!("Project directory is not accessible: {e}"))?;
+    let full = root.join(path).canonicalize()
+        .map_err(|e| format!("Cannot read {path}: {e}"))?;
+    if !full.starts_with(&root) {
+        return Err(format!("{path} is outside the project directory"));
+    }
+    Ok(full)
+}
+
+/// The attachment's MIME type, guessed from its name when missing.
+pub fn mime_type(att: &AttachmentInfo) -> &str {
+    if att.mime_type.is_empty() { mime_from_filename(&att.filename) } else { &att.mime_type }
+}
+
+/// Whether the attachment is a file on this machine rather than a blob.
+pub fn is_local(att: &AttachmentInfo) -> bool {
+    att.local_path.as_deref().is_some_and(|p| !p.is_empty())
+}
+
+/// An attachment as prompt text: text files as a code block, documents and
+/// archives through `extract`. Parsing runs on the blocking pool.
+async fn text_section(att: &AttachmentInfo, bytes: Vec<u8>) -> Result<String, String> {
+    let att = att.clone();
+    tokio::task::spawn_blocking(move || text_section_blocking(&att, &bytes))
+        .await
+        .map_err(|e| format!("Extraction failed: {e}"))?
+}
+
+fn text_section_blocking(att: &AttachmentInfo, bytes: &[u8]) -> Result<String, String> {
+    let mime = mime_type(att);
+    if crate::extract::is_extractable(&att.filename, mime) {
+        match crate::extract::extract(&att.filename, mime, bytes) {
+            Ok(text) => return Ok(format!(
+                "[File: {} — extracted contents]\n{}\n[End of {}]",
+                att.filename, truncate_chars(&text, MAX_TEXT_CHARS), att.filename,
+            )),
+            // HTML or CSV that didn't parse is still worth showing raw
+            Err(e) if !is_text(att) => return Err(e),
+            Err(e) => tracing::warn!("[attachments] {e}"),
+        }
+    }
+    let text = String::from_utf8_lossy(bytes);
+    Ok(format!("[File: {}]\n```\n{}\n```", att.filename, truncate_chars(&text, MAX_TEXT_CHARS)))
+}
+
+/// Whether the attachment may be an image, to be sniffed and converted by
+/// `images::prepare`. Files without a useful type are checked too.
+pub fn may_be_image(att: &AttachmentInfo) -> bool {
+    let mime = mime_type(att);
+    (mime.starts_with("image/") || mime == "application/octet-stream") && !has_text(att)
+}
+
+/// Image formats the providers accept natively.
+pub fn is_native_image_mime(mime: &str) -> bool {
+    matches!(mime, "image/jpeg" | "image/png" | "image/gif" | "image/webp")
+}
+
+/// Whether the attachment can be shown as text (directly or extracted).
+pub fn has_text(att: &AttachmentInfo) -> bool {
+    is_text(att) || crate::extract::is_extractable(&att.filename, mime_type(att))
+}
+
+fn is_text(att: &AttachmentInfo) -> bool {
+    is_text_mime(mime_type(att)) || is_text_filename(&att.filename)
+}
+
+fn is_text_mime(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime == "application/json"
+        || mime == "application/xml"
+        || mime == "application/javascript"
+        || mime == "application/typescript"
+        || mime == "application/x-yaml"
+        || mime == "application/toml"
+}
+
+pub fn is_text_filename(filename: &str) -> bool {
+    let ext = filename.rsplit('.').next().unwrap_or("").to_lowercase();
+    matches!(ext.as_str(),
+        "txt" | "ts" | "tsx" | "js" | "jsx" | "py" | "json" | "md" | "log" | "csv"
+        | "yaml" | "yml" | "toml" | "rs" | "go" | "java" | "html" | "css" | "xml"
+        | "sql" | "sh" | "bash" | "zsh" | "env" | "cfg" | "ini" | "conf" | "diff"
+        | "patch" | "c" | "cpp" | "h" | "hpp" | "rb" | "php" | "swift" | "kt"
+    )
+}
+
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}... (truncated)", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+/// Best-effort MIME type from a file name, for local files without one.
+pub fn mime_from_filename(filename: &str) -> &'static str {
+    let ext = filename.rsplit('.').next().unwrap_or("").to_lowercase();
+    match ext.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "tif" | "tiff" => "image/tiff",
+        "ico" => "image/x-icon",
+        "heic" | "heif" => "image/heic",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "yaml" | "yml" => "application/x-yaml",
+        "toml" => "application/toml",
+        "js" => "application/javascript",
+        "ts" => "application/typescript",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "ipynb" => "application/x-ipynb+json",
+        "zip" => "application/zip",
+        "tar" => "application/x-tar",
+        "gz" | "tgz" => "application/gzip",
+        "txt" | "log" | "rs" | "py" | "go" | "java" | "c" | "h" | "cpp" | "hpp"
+        | "rb" | "php" | "swift" | "kt" | "sh" | "sql" | "css" | "diff" | "patch" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Hex SHA-256 of the content.
+pub fn content_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+// ── CLI staging ───────────────────────────────────────────────────────
+
+/// Attachments saved to disk for a CLI run.
+pub struct StagedAttachments {
+    /// The run's directory, when anything was copied into it.
+    pub dir: Option<PathBuf>,
+    /// Images the CLI may take natively.
+    pub images: Vec<StagedImage>,
+    /// Lists the files for the agent and inlines text and documents.
+    pub prompt: String,
+}
+
+pub struct StagedImage {
+    /// Relative to the project directory.
+    pub path: String,
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Write `bytes` into `dir` under a safe, unused version of `filename`.
+/// Returns the name used.
+async fn save(dir: &Path, filename: &str, bytes: &[u8]) -> std::io::Result<String> {
+    tokio::fs::create_dir_all(dir).await?;
+    // Keep staged files out of the project's git status
+    if let Some(root) = dir.parent() {
+        let ignore = root.join(".gitignore");
+        if !tokio::fs::try_exists(&ignore).await.unwrap_or(false) {
+            tokio::fs::write(&ignore, "*\n").await?;
+        }
+    }
+
+    let base = Path::new(filename).file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let base: String = base.chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ' ') { c } else { '_' })
+        .collect();
+    let base = match base.trim_start_matches('.') {
+        "" => "attachment".to_string(),
+        name => name.to_string(),
+    };
+    let mut name = base.clone();
+    let mut n = 1;
+    while tokio::fs::try_exists(dir.join(&name)).await.unwrap_or(false) {
+        n += 1;
+        name = format!("{n}-{base}");
+    }
+    tokio::fs::write(dir.join(&name), bytes).await?;
+    Ok(name)
+}
+
+/// Remove run directories older than the retention period. Even with no
+/// retention, leftovers are only swept after an hour, since other runs in
+/// the same project may still be using theirs.
+async fn sweep(root: &Path, retention_hours: u64) {
+    let max_age = std::time::Duration::from_secs(retention_hours.max(1) * 3600);
+    let Ok(mut entries) = tokio::fs::read_dir(root).await else { return };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let Ok(meta) = entry.metadata().await else { continue };
+        let expired = meta.modified().ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if meta.is_dir() && expired {
+            match tokio::fs::remove_dir_all(entry.path()).await {
+                Ok(()) => tracing::info!("[attachments] Removed expired {}", entry.path().display()),
+                Err(e) => tracing::warn!("[attachments] Could not remove {}: {e}", entry.path().display()),
+            }
+        }
+    }
+}
+
+// ── Files API cache ───────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedFile {
+    file_id: String,
+    uploaded_at: u64,
+}
+
+/// Anthropic file IDs by content hash and by source. File IDs belong to an
+/// API key's workspace, so keys are scoped by a key fingerprint.
+pub struct FileIdCache {
+    path: PathBuf,
+    files: Mutex<HashMap<String, CachedFile>>,
+}
+
+impl FileIdCache {
+    pub fn load(store_path: &Path) -> Self {
+        let path = store_path
+            .parent()
+            .map(|dir| dir.join(FILE_CACHE_FILE))
+            .unwrap_or_else(|| PathBuf::from(FILE_CACHE_FILE));
+        let files = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { path, files: Mutex::new(files) }
+    }
+
+    fn key(api_key: &str, kind: &str, id: &str) -> String {
+        let scope = hex::encode(&Sha256::digest(api_key.as_bytes())[..6]);
+        format!("{scope}:{kind}:{id}")
+    }
+
+    /// File ID for content with this hash.
+    pub fn by_hash(&self, api_key: &str, hash: &str) -> Option<String> {
+        self.get(&Self::key(api_key, "sha256", hash))
+    }
+
+    /// File ID for an attachment uploaded earlier from this blob URL.
+    pub fn by_url(&self, api_key: &str, url: &str) -> Option<String> {
+        self.get(&Self::key(api_key, "url", url))
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.files.lock().unwrap().get(key).map(|f| f.file_id.clone())
+    }
+
+    /// Remember an upload. Local files are only cached by hash, since their
+    /// contents can change under the same path.
+    pub fn put(&self, api_key: &str, hash: &str, url: Option<&str>, file_id: &str) {
+        let entry = CachedFile { file_id: file_id.to_string(), uploaded_at: now_ms() };
+        let mut files = self.files.lock().unwrap();
+        files.insert(Self::key(api_key, "sha256", hash), entry.clone());
+        if let Some(url) = url.filter(|u| !u.is_empty()) {
+            files.insert(Self::key(api_key, "url", url), entry);
+        }
+        while files.len() > MAX_CACHED_FILES {
+            let Some(oldest) = files.iter().min_by_key(|(_, f)| f.uploaded_at).map(|(k, _)| k.clone()) else { break };
+            files.remove(&oldest);
+        }
+        if let Err(e) = persist(&self.path, &files) {
+            tracing::error!("[attachments] Failed to save file cache: {e}");
+        }
+    }
+}
+
+fn persist(path: &Path, files: &HashMap<String, CachedFile>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(files).unwrap_or_default())?;
+    std::fs::rename(&tmp, path)
+}
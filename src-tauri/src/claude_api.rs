@@ -1,250 +1,717 @@
 //! Direct Anthropic Messages API integration — streams responses via SSE,
-//! supports multimodal content (images, PDFs via URL source).
+//! supports multimodal content (images and PDFs inline or via the Files API).
 
-use tauri::Emitter;
+use base64::Engine;
 use futures_util::StreamExt;
+use crate::attachments::{self, AttachmentLoader, MAX_INLINE_IMAGE_BYTES};
+use crate::images;
+use crate::runtime::{emit_chunk, emit_thinking, emit_usage, EventSink, Usage};
+use crate::agent_providers::ModelConfig;
+use crate::retry::{self, Retrier};
+use crate::context::{ContextMessage, Role};
 use crate::state::AttachmentInfo;
 
 const DEFAULT_MODEL: &str = "claude-sonnet-4-20250514";
 const MAX_TOKENS: u32 = 16384;
 const ANTHROPIC_API_URL: &str = "https://api.anthropic.com/v1/messages";
+const ANTHROPIC_MODELS_URL: &str = "https://api.anthropic.com/v1/models";
+const ANTHROPIC_FILES_URL: &str = "https://api.anthropic.com/v1/files";
 const ANTHROPIC_VERSION: &str = "2024-10-22";
+const FILES_API_BETA: &str = "files-api-2025-04-14";
+/// Largest PDF sent inline; bigger ones are uploaded to the Files API.
+const MAX_INLINE_PDF_BYTES: usize = 4_000_000;
+/// Text blocks shorter than this don't get their own cache breakpoint.
+const CACHE_MIN_CHARS: usize = 4_096;
+/// Smallest thinking budget the API accepts.
+const MIN_THINKING_BUDGET: u32 = 1024;
+/// Tool round-trips per run before the model must answer without tools.
+const MAX_TOOL_ITERATIONS: usize = 25;
+/// Characters of each tool result included in `agent-tool-result` events.
+const TOOL_RESULT_PREVIEW_CHARS: usize = 2_000;
 
-/// MIME types the Anthropic API accepts for image content blocks.
-fn is_api_image_mime(mime: &str) -> bool {
-    matches!(mime, "image/jpeg" | "image/png" | "image/gif" | "image/webp")
+/// List price in USD per million (input, output) tokens, for cost estimates.
+fn model_pricing(model: &str) -> Option<(f64, f64)> {
+    if model.contains("opus") {
+        Some((15.0, 75.0))
+    } else if model.contains("sonnet") {
+        Some((3.0, 15.0))
+    } else if model.contains("haiku") {
+        Some((0.8, 4.0))
+    } else {
+        None
+    }
+}
+
+/// Estimate the cost of a request from its token usage. Cache writes cost
+/// 1.25× the input price and cache reads 0.1×.
+pub fn estimate_cost(model: &str, usage: &Usage) -> Option<f64> {
+    let (input, output) = model_pricing(model)?;
+    let input_cost = usage.input_tokens as f64 * input
+        + usage.cache_write_tokens as f64 * input * 1.25
+        + usage.cache_read_tokens as f64 * input * 0.1;
+    Some((input_cost + usage.output_tokens as f64 * output) / 1_000_000.0)
+}
+
+/// Marks the end of a cacheable prompt prefix.
+fn cache_control() -> serde_json::Value {
+    serde_json::json!({ "type": "ephemeral" })
+}
+
+/// Whether a content block is big enough to be worth a cache breakpoint of
+/// its own (the API ignores prefixes under ~1024 tokens anyway).
+fn is_large_block(block: &serde_json::Value) -> bool {
+    match block.get("type").and_then(|t| t.as_str()) {
+        Some("image") | Some("document") => true,
+        Some("text") => block.get("text").and_then(|t| t.as_str()).is_some_and(|t| t.len() >= CACHE_MIN_CHARS),
+        _ => false,
+    }
 }
 
 fn is_pdf_mime(mime: &str) -> bool {
     mime == "application/pdf"
 }
 
-fn is_text_mime(mime: &str) -> bool {
-    mime.starts_with("text/")
-        || mime == "application/json"
-        || mime == "application/xml"
-        || mime == "application/javascript"
-        || mime == "application/typescript"
-        || mime == "application/x-yaml"
-        || mime == "application/toml"
+/// Content block type for attachments the API reads natively.
+fn block_kind(att: &AttachmentInfo) -> Option<&'static str> {
+    if attachments::may_be_image(att) {
+        Some("image")
+    } else if is_pdf_mime(attachments::mime_type(att)) {
+        Some("document")
+    } else {
+        None
+    }
 }
 
-fn is_text_filename(filename: &str) -> bool {
-    let ext = filename.rsplit('.').next().unwrap_or("").to_lowercase();
-    matches!(ext.as_str(),
-        "txt" | "ts" | "tsx" | "js" | "jsx" | "py" | "json" | "md" | "log" | "csv"
-        | "yaml" | "yml" | "toml" | "rs" | "go" | "java" | "html" | "css" | "xml"
-        | "sql" | "sh" | "bash" | "zsh" | "env" | "cfg" | "ini" | "conf" | "diff"
-        | "patch" | "c" | "cpp" | "h" | "hpp" | "rb" | "php" | "swift" | "kt"
-    )
+fn note_block(text: String) -> serde_json::Value {
+    serde_json::json!({ "type": "text", "text": text })
 }
 
-/// Build content blocks for attachments.
-/// Images and PDFs use URL source (blob URLs are public).
-/// Text files are fetched and inlined.
-async fn build_attachment_content_blocks(
-    attachments: &[AttachmentInfo],
-) -> Vec<serde_json::Value> {
-    let mut blocks = Vec::new();
-    let client = reqwest::Client::new();
+/// Turns attachments into content blocks. Images and PDFs are sent inline
+/// as base64 when small and uploaded to the Files API otherwise (reusing
+/// earlier uploads of the same content); text files and documents are
+/// inlined as text.
+struct AttachmentBlocks<'a> {
+    client: &'a reqwest::Client,
+    api_key: &'a str,
+    loader: &'a AttachmentLoader,
+}
 
-    for att in attachments {
-        if is_api_image_mime(&att.mime_type) {
-            blocks.push(serde_json::json!({
-                "type": "image",
-                "source": {
-                    "type": "url",
-                    "url": att.blob_url,
-                }
-            }));
-        } else if is_pdf_mime(&att.mime_type) {
-            blocks.push(serde_json::json!({
-                "type": "document",
-                "source": {
-                    "type": "url",
-                    "url": att.blob_url,
-                }
-            }));
-        } else if is_text_mime(&att.mime_type) || is_text_filename(&att.filename) {
-            match client.get(&att.blob_url).send().await {
-                Ok(resp) if resp.status().is_success() => {
-                    match resp.text().await {
-                        Ok(text) => {
-                            let truncated = if text.len() > 100_000 {
-                                format!("{}... (truncated)", &text[..100_000])
-                            } else {
-                                text
-                            };
-                            blocks.push(serde_json::json!({
-                                "type": "text",
-                                "text": format!(
-                                    "[File: {}]\n```\n{}\n```",
-                                    att.filename, truncated
-                                )
-                            }));
-                        }
-                        Err(_) => {
-                            blocks.push(serde_json::json!({
-                                "type": "text",
-                                "text": format!(
-                                    "[File: {} ({} bytes) — could not read content]",
-                                    att.filename, att.file_size
-                                )
-                            }));
-                        }
+impl AttachmentBlocks<'_> {
+    /// Blocks for the current message's attachments.
+    async fn current(&self, attachments: &[AttachmentInfo]) -> Vec<serde_json::Value> {
+        let mut blocks = Vec::new();
+        for att in attachments {
+            let mime = attachments::mime_type(att);
+            let Some(kind) = block_kind(att) else {
+                let text = if attachments::has_text(att) {
+                    self.loader.load_as_text(att).await.unwrap_or_else(|e| {
+                        tracing::warn!("[claude_api] {e}");
+                        format!("[File: {} ({} bytes) — could not read content: {e}]", att.filename, att.file_size)
+                    })
+                } else {
+                    format!("[File: {} ({} bytes, {}) — binary file, cannot display contents]", att.filename, att.file_size, mime)
+                };
+                blocks.push(note_block(text));
+                continue;
+            };
+            blocks.push(self.load_block(att, kind).await);
+        }
+        blocks
+    }
+
+    /// Blocks that point back at an earlier turn's attachments. Images and
+    /// PDFs are re-sent on user turns (assistant turns can't carry them):
+    /// by file ID when uploaded before, by URL when the blob is public, and
+    /// loaded again otherwise. Everything else is referenced by name.
+    async fn earlier(&self, attachments: &[AttachmentInfo], role: Role) -> Vec<serde_json::Value> {
+        let mut blocks = Vec::new();
+        for att in attachments {
+            let mime = attachments::mime_type(att);
+            let block = match block_kind(att).filter(|_| role == Role::User) {
+                Some(kind) if !attachments::is_local(att) => {
+                    match self.loader.file_ids.by_url(self.api_key, &att.blob_url) {
+                        Some(file_id) => file_block(kind, &file_id),
+                        None if self.fetchable_by_api(att) => url_block(kind, &att.blob_url),
+                        None => self.load_block(att, kind).await,
                     }
                 }
-                _ => {
-                    blocks.push(serde_json::json!({
-                        "type": "text",
-                        "text": format!(
-                            "[File: {} ({} bytes) — could not fetch content]",
-                            att.filename, att.file_size
-                        )
-                    }));
+                Some(kind) => self.load_block(att, kind).await,
+                None => note_block(format!(
+                    "[Earlier attachment: {} ({}, {} bytes) — {}]",
+                    att.filename, mime, att.file_size,
+                    att.local_path.as_deref().unwrap_or(&att.blob_url),
+                )),
+            };
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    /// Load an image or PDF and turn it into a block. Images go through
+    /// `images::prepare` first, whatever their declared type.
+    async fn load_block(&self, att: &AttachmentInfo, kind: &str) -> serde_json::Value {
+        let bytes = match self.loader.load(att).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::warn!("[claude_api] {e}");
+                return self.fallback(att, kind, &e);
+            }
+        };
+        if kind == "document" {
+            return self.binary(att, kind, "application/pdf", bytes).await;
+        }
+        if images::sniff(&bytes).is_none() {
+            return note_block(format!(
+                "[File: {} ({} bytes, {}) — binary file, cannot display contents]",
+                att.filename, bytes.len(), attachments::mime_type(att),
+            ));
+        }
+        match images::prepare(bytes).await {
+            Ok(image) => self.binary(att, kind, image.mime, image.bytes).await,
+            Err(e) => {
+                tracing::warn!("[claude_api] {}: {e}", att.filename);
+                note_block(format!("[Image: {} — could not be converted: {e}]", att.filename))
+            }
+        }
+    }
+
+    /// An image or PDF block: base64 under the inline limit, a Files API
+    /// reference above it.
+    async fn binary(&self, att: &AttachmentInfo, kind: &str, mime: &str, bytes: Vec<u8>) -> serde_json::Value {
+        let inline_limit = if kind == "image" { MAX_INLINE_IMAGE_BYTES } else { MAX_INLINE_PDF_BYTES };
+        if bytes.len() <= inline_limit {
+            return serde_json::json!({
+                "type": kind,
+                "source": {
+                    "type": "base64",
+                    "media_type": mime,
+                    "data": base64::engine::general_purpose::STANDARD.encode(&bytes),
                 }
+            });
+        }
+
+        let hash = attachments::content_hash(&bytes);
+        if let Some(file_id) = self.loader.file_ids.by_hash(self.api_key, &hash) {
+            return file_block(kind, &file_id);
+        }
+        tracing::info!("[claude_api] Uploading {} ({} bytes) to the Files API", att.filename, bytes.len());
+        match upload_file(self.client, self.api_key, bytes, &att.filename, mime).await {
+            Ok(file_id) => {
+                let url = (!attachments::is_local(att)).then_some(att.blob_url.as_str());
+                self.loader.file_ids.put(self.api_key, &hash, url, &file_id);
+                file_block(kind, &file_id)
+            }
+            Err(e) => {
+                tracing::warn!("[claude_api] Upload of {} failed: {e}", att.filename);
+                self.fallback(att, kind, &e)
             }
+        }
+    }
+
+    /// When the content can't be sent: let the API fetch a public blob
+    /// itself, or tell the model what went wrong.
+    fn fallback(&self, att: &AttachmentInfo, kind: &str, error: &str) -> serde_json::Value {
+        if self.fetchable_by_api(att) {
+            url_block(kind, &att.blob_url)
         } else {
-            blocks.push(serde_json::json!({
-                "type": "text",
-                "text": format!(
-                    "[File: {} ({} bytes, {}) — binary file, cannot display contents]",
-                    att.filename, att.file_size, att.mime_type
-                )
-            }));
+            note_block(format!("[File: {} ({} bytes) — could not read content: {error}]", att.filename, att.file_size))
+        }
+    }
+
+    /// Whether the API can fetch the blob itself: it's public and in a
+    /// format the API reads without our preprocessing.
+    fn fetchable_by_api(&self, att: &AttachmentInfo) -> bool {
+        let mime = attachments::mime_type(att);
+        !attachments::is_local(att)
+            && !att.blob_url.is_empty()
+            && !self.loader.is_private_url(&att.blob_url)
+            && (is_pdf_mime(mime) || attachments::is_native_image_mime(mime))
+    }
+}
+
+fn url_block(kind: &str, url: &str) -> serde_json::Value {
+    serde_json::json!({ "type": kind, "source": { "type": "url", "url": url } })
+}
+
+fn file_block(kind: &str, file_id: &str) -> serde_json::Value {
+    serde_json::json!({ "type": kind, "source": { "type": "file", "file_id": file_id } })
+}
+
+/// Whether any message refers to an uploaded file (which needs the Files
+/// API beta header).
+fn references_files(messages: &[serde_json::Value]) -> bool {
+    messages.iter()
+        .filter_map(|m| m.get("content").and_then(|c| c.as_array()))
+        .flatten()
+        .any(|block| block.pointer("/source/type").and_then(|t| t.as_str()) == Some("file"))
+}
+
+/// Upload a file to the Files API and return its ID.
+async fn upload_file(
+    client: &reqwest::Client,
+    api_key: &str,
+    bytes: Vec<u8>,
+    filename: &str,
+    mime: &str,
+) -> Result<String, String> {
+    let part = reqwest::multipart::Part::bytes(bytes)
+        .file_name(filename.to_string())
+        .mime_str(mime)
+        .map_err(|e| format!("Invalid MIME type {mime}: {e}"))?;
+    let response = client
+        .post(ANTHROPIC_FILES_URL)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .header("anthropic-beta", FILES_API_BETA)
+        .timeout(std::time::Duration::from_secs(300))
+        .multipart(reqwest::multipart::Form::new().part("file", part))
+        .send()
+        .await
+        .map_err(describe_send_error)?;
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(describe_http_error(status, &text));
+    }
+    serde_json::from_str::<serde_json::Value>(&text)
+        .ok()
+        .and_then(|json| json.get("id")?.as_str().map(|id| id.to_string()))
+        .ok_or_else(|| format!("Invalid Files API response: {text}"))
+}
+
+fn describe_send_error(e: reqwest::Error) -> String {
+    if e.is_connect() {
+        "Cannot connect to Anthropic API. Check your internet connection.".to_string()
+    } else if e.is_timeout() {
+        "Anthropic API request timed out.".to_string()
+    } else {
+        format!("Anthropic API request failed: {e}")
+    }
+}
+
+fn describe_http_error(status: reqwest::StatusCode, text: &str) -> String {
+    match status.as_u16() {
+        401 => "Invalid Anthropic API key. Check your API key in Settings.".to_string(),
+        429 => "Anthropic API rate limit exceeded. Please wait and try again.".to_string(),
+        529 => "Anthropic API is overloaded. Please try again later.".to_string(),
+        400 => serde_json::from_str::<serde_json::Value>(text)
+            .ok()
+            .and_then(|json| json.get("error")?.get("message")?.as_str().map(|m| m.to_string()))
+            .map(|msg| format!("Anthropic API error: {msg}"))
+            .unwrap_or_else(|| format!("Anthropic API returned {status}: {text}")),
+        _ => format!("Anthropic API returned {status}: {text}"),
+    }
+}
+
+/// Non-streaming completion for short internal jobs (e.g. summaries).
+/// Returns the concatenated text blocks.
+pub async fn complete(
+    api_key: &str,
+    model: &str,
+    system_prompt: &str,
+    prompt: &str,
+    max_tokens: u32,
+) -> Result<String, String> {
+    let body = serde_json::json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "system": system_prompt,
+        "messages": [{ "role": "user", "content": prompt }],
+    });
+    let response = reqwest::Client::new()
+        .post(ANTHROPIC_API_URL)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .header("content-type", "application/json")
+        .timeout(std::time::Duration::from_secs(120))
+        .json(&body)
+        .send()
+        .await
+        .map_err(describe_send_error)?;
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(describe_http_error(status, &text));
+    }
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid Anthropic API response: {e}"))?;
+    let content = json.get("content").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+    Ok(content.iter()
+        .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("text"))
+        .filter_map(|block| block.get("text").and_then(|t| t.as_str()))
+        .collect())
+}
+
+/// Models available to this API key, newest first.
+pub async fn list_models(api_key: &str) -> Result<Vec<crate::models::ModelInfo>, String> {
+    let response = reqwest::Client::new()
+        .get(ANTHROPIC_MODELS_URL)
+        .query(&[("limit", "1000")])
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(describe_send_error)?;
+
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        return Err(describe_http_error(status, &text));
+    }
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid Anthropic API response: {e}"))?;
+    let data = json.get("data").and_then(|d| d.as_array()).cloned().unwrap_or_default();
+    Ok(data.iter()
+        .filter_map(|m| Some(crate::models::ModelInfo {
+            id: m.get("id")?.as_str()?.to_string(),
+            display_name: m.get("display_name").and_then(|n| n.as_str()).map(|n| n.to_string()),
+        }))
+        .collect())
+}
+
+/// Everything that goes into a Messages API request besides the key.
+pub struct ApiRequest<'a> {
+    pub system_prompt: &'a str,
+    pub prompt: &'a str,
+    /// Flattened history, used only when `history` is empty.
+    pub conversation_context: &'a str,
+    /// Structured history, sent as alternating user/assistant turns.
+    pub history: &'a [ContextMessage],
+    /// Summary or omission note standing in for older history.
+    pub history_note: Option<&'a str>,
+    /// Attachments on the current message.
+    pub attachments: &'a [AttachmentInfo],
+    /// Reads attachment contents and tracks Files API uploads.
+    pub loader: &'a AttachmentLoader,
+    /// Local tools; None for plain chat.
+    pub tools: Option<&'a crate::claude_tools::ToolContext>,
+    /// Extended thinking budget in tokens; None disables thinking.
+    pub thinking_budget: Option<u32>,
+    /// Model and sampling settings; unset fields use the defaults.
+    pub model: &'a ModelConfig,
+}
+
+/// Build the `messages` array: the history as alternating turns, then the
+/// current prompt with its attachments. Consecutive messages with the same
+/// role are merged, and the array always starts with a user turn, as the
+/// API requires. Cache breakpoints go after the history and after the last
+/// large attachment.
+async fn build_messages(request: &ApiRequest<'_>, files: &AttachmentBlocks<'_>) -> Vec<serde_json::Value> {
+    fn push(turns: &mut Vec<(Role, Vec<serde_json::Value>)>, role: Role, blocks: Vec<serde_json::Value>) {
+        if blocks.is_empty() {
+            return;
+        }
+        match turns.last_mut() {
+            Some((last, content)) if *last == role => content.extend(blocks),
+            _ => turns.push((role, blocks)),
+        }
+    }
+    let mut turns = Vec::new();
+    let text_block = |text: String| serde_json::json!({ "type": "text", "text": text });
+
+    if let Some(note) = request.history_note {
+        push(&mut turns, Role::User, vec![text_block(note.to_string())]);
+    }
+    for message in request.history {
+        let mut blocks = files.earlier(&message.attachments, message.role).await;
+        if !message.text.trim().is_empty() {
+            // Several people share a chat, so user turns keep their author
+            let text = match message.role {
+                Role::User if !message.author.is_empty() => format!("{}: {}", message.author, message.text),
+                _ => message.text.clone(),
+            };
+            blocks.push(text_block(text));
         }
+        push(&mut turns, message.role, blocks);
+    }
+
+    // Cache breakpoint: the history is the same for every message in a busy
+    // thread until the budget window moves
+    if let Some(block) = turns.last_mut().and_then(|(_, content)| content.last_mut()) {
+        block["cache_control"] = cache_control();
+    }
+
+    let mut current = files.current(request.attachments).await;
+    // One breakpoint after the last large attachment covers all of them, so
+    // tool rounds and follow-ups reuse them instead of paying full price
+    if let Some(block) = current.iter_mut().rev().find(|b| is_large_block(b)) {
+        block["cache_control"] = cache_control();
     }
+    let prompt = if request.history.is_empty() {
+        crate::context::with_context(request.conversation_context, request.prompt)
+    } else {
+        request.prompt.to_string()
+    };
+    current.push(text_block(prompt));
+    push(&mut turns, Role::User, current);
 
-    blocks
+    if turns.first().is_some_and(|(role, _)| *role == Role::Assistant) {
+        turns.insert(0, (Role::User, vec![text_block("(Earlier messages omitted.)".to_string())]));
+    }
+    turns.into_iter()
+        .map(|(role, content)| serde_json::json!({ "role": role, "content": content }))
+        .collect()
 }
 
 /// Stream a response from the Anthropic Messages API via SSE.
 ///
-/// Emits `"agent-chunk"` Tauri events with the same schema as CLI providers:
-/// `{ responseId, chunk, done, error }`.
+/// Emits `"agent-chunk"` events to `sink` with the same schema as CLI providers:
+/// `{ responseId, chunk, done, error }`. With a thinking budget, reasoning
+/// is streamed separately as `"agent-thinking"` events.
+///
+/// With `tools` set, runs a tool-use loop: `tool_use` blocks are executed
+/// locally (`agent-tool-use` / `agent-tool-result` events) and their results
+/// sent back until the model answers without calling a tool.
 pub async fn stream_claude_api(
     api_key: &str,
-    system_prompt: &str,
-    prompt: &str,
-    conversation_context: &str,
-    attachments: &[AttachmentInfo],
+    request: &ApiRequest<'_>,
     response_id: &str,
-    window: &tauri::Window,
+    sink: &dyn EventSink,
 ) -> Result<(), String> {
     tracing::info!(
-        "[claude_api] Starting HTTP stream — {} attachments, prompt len={}, context len={}",
-        attachments.len(),
-        prompt.len(),
-        conversation_context.len(),
+        "[claude_api] Starting HTTP stream — {} attachments, prompt len={}, history={} messages",
+        request.attachments.len(),
+        request.prompt.len(),
+        request.history.len(),
     );
     let client = reqwest::Client::new();
+    let files = AttachmentBlocks { client: &client, api_key, loader: request.loader };
+    let mut messages = build_messages(request, &files).await;
+    let beta = references_files(&messages).then_some(FILES_API_BETA);
 
-    // Build user message content blocks
-    let mut user_content: Vec<serde_json::Value> = Vec::new();
+    // Chat-style instructions normally ride along with flattened history;
+    // with real turns they belong in the system prompt.
+    let system_prompt = if request.history.is_empty() {
+        request.system_prompt.to_string()
+    } else if request.system_prompt.is_empty() {
+        crate::context::CHAT_STYLE.to_string()
+    } else {
+        format!("{}\n\n{}", request.system_prompt, crate::context::CHAT_STYLE)
+    };
 
-    // 1. Attachment content blocks (images, PDFs, text files)
-    if !attachments.is_empty() {
-        let att_blocks = build_attachment_content_blocks(attachments).await;
-        user_content.extend(att_blocks);
-    }
+    let model = request.model.model_name().unwrap_or(DEFAULT_MODEL);
+    let max_tokens = request.model.max_tokens.unwrap_or(MAX_TOKENS);
 
-    // 2. Text prompt (conversation context + user message)
-    let mut full_text = String::new();
-    if !conversation_context.is_empty() {
-        full_text.push_str(
-            "Below is the recent conversation history for context. \
-             Respond ONLY to the final user message. \
-             Be conversational and concise — reply directly to the user as a chat participant. \
-             Do NOT narrate your thought process, planning steps, or internal reasoning. \
-             Do NOT describe actions you would take (e.g. \"I should...\", \"Let me...\", \"I will...\"). \
-             Just answer.\n\n"
-        );
-        full_text.push_str(conversation_context);
-        full_text.push_str("\n\n---\n\n");
-    }
-    full_text.push_str(prompt);
-
-    user_content.push(serde_json::json!({
-        "type": "text",
-        "text": full_text,
-    }));
-
-    // Build messages array
-    let messages = vec![
-        serde_json::json!({
-            "role": "user",
-            "content": user_content,
-        })
-    ];
-
-    // Build request body
-    let mut body = serde_json::json!({
-        "model": DEFAULT_MODEL,
-        "max_tokens": MAX_TOKENS,
-        "stream": true,
-        "messages": messages,
-    });
+    let mut usage = Usage::default();
+    // Index of the message holding the rolling tool-result breakpoint
+    let mut tool_breakpoint: Option<usize> = None;
+    for iteration in 0..=MAX_TOOL_ITERATIONS {
+        // Build request body
+        let mut body = serde_json::json!({
+            "model": model,
+            "max_tokens": max_tokens,
+            "stream": true,
+            "messages": messages,
+        });
+        // The system prompt breakpoint also caches the tool definitions,
+        // which come before it in the prompt
+        if !system_prompt.is_empty() {
+            body["system"] = serde_json::json!([{
+                "type": "text",
+                "text": system_prompt,
+                "cache_control": cache_control(),
+            }]);
+        }
+        if !request.model.stop_sequences.is_empty() {
+            body["stop_sequences"] = serde_json::json!(request.model.stop_sequences);
+        }
+        // max_tokens covers thinking and answer, so it must exceed the budget.
+        // Thinking doesn't allow custom sampling, so temperature/top_p only
+        // apply without it.
+        if let Some(budget) = request.thinking_budget {
+            let budget = budget.max(MIN_THINKING_BUDGET);
+            body["max_tokens"] = serde_json::json!(max_tokens + budget);
+            body["thinking"] = serde_json::json!({ "type": "enabled", "budget_tokens": budget });
+        } else {
+            if let Some(temperature) = request.model.temperature {
+                body["temperature"] = serde_json::json!(temperature);
+            }
+            if let Some(top_p) = request.model.top_p {
+                body["top_p"] = serde_json::json!(top_p);
+            }
+        }
+        // On the last iteration, withhold tools so the model has to answer
+        if let Some(tools) = request.tools.filter(|_| iteration < MAX_TOOL_ITERATIONS) {
+            if tools.policy.enabled {
+                body["tools"] = crate::claude_tools::definitions();
+            }
+        }
 
-    if !system_prompt.is_empty() {
-        body.as_object_mut().unwrap().insert(
-            "system".to_string(),
-            serde_json::json!(system_prompt),
+        tracing::info!(
+            "[claude_api] Sending request — model={}, messages={}, system_len={}",
+            model,
+            messages.len(),
+            system_prompt.len(),
         );
+        let turn = stream_turn(&client, api_key, beta, &body, response_id, sink).await?;
+        usage.accumulate(&turn.usage);
+
+        if let Some(error) = turn.error {
+            emit_chunk(sink, response_id, "", false, Some(&error));
+            return Ok(());
+        }
+        let tool_calls: Vec<&serde_json::Value> = turn.content.iter()
+            .filter(|block| block.get("type").and_then(|t| t.as_str()) == Some("tool_use"))
+            .collect();
+        let Some(tools) = request.tools.filter(|_| turn.stop_reason.as_deref() == Some("tool_use") && !tool_calls.is_empty()) else {
+            break;
+        };
+
+        // Run the requested tools and feed the results back
+        let mut results = Vec::with_capacity(tool_calls.len());
+        for call in &tool_calls {
+            let id = call.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+            let name = call.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            let input = call.get("input").cloned().unwrap_or_else(|| serde_json::json!({}));
+            sink.emit_event("agent-tool-use", serde_json::json!({
+                "responseId": response_id,
+                "toolUseId": id,
+                "name": name,
+                "input": input,
+            }));
+            let output = crate::claude_tools::execute(tools, response_id, id, name, &input, sink).await;
+            sink.emit_event("agent-tool-result", serde_json::json!({
+                "responseId": response_id,
+                "toolUseId": id,
+                "name": name,
+                "isError": output.is_error,
+                "output": output.content.chars().take(TOOL_RESULT_PREVIEW_CHARS).collect::<String>(),
+            }));
+            results.push(serde_json::json!({
+                "type": "tool_result",
+                "tool_use_id": id,
+                "content": output.content,
+                "is_error": output.is_error,
+            }));
+        }
+        // Move the rolling breakpoint to the newest tool results so the next
+        // round reads the whole conversation so far from the cache
+        if let Some(previous) = tool_breakpoint.and_then(|i| messages.get_mut(i)) {
+            if let Some(block) = previous["content"].as_array_mut().and_then(|c| c.last_mut()) {
+                if let Some(obj) = block.as_object_mut() {
+                    obj.remove("cache_control");
+                }
+            }
+        }
+        if let Some(last) = results.last_mut() {
+            last["cache_control"] = cache_control();
+        }
+        messages.push(serde_json::json!({ "role": "assistant", "content": turn.content }));
+        messages.push(serde_json::json!({ "role": "user", "content": results }));
+        tool_breakpoint = Some(messages.len() - 1);
     }
 
     tracing::info!(
-        "[claude_api] Sending request — model={}, content_blocks={}, system_len={}",
-        DEFAULT_MODEL,
-        user_content.len(),
-        system_prompt.len(),
+        "[claude_api] Usage — input={}, output={}, cache_read={}, cache_write={}",
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_read_tokens,
+        usage.cache_write_tokens,
     );
+    usage.cost_usd = estimate_cost(model, &usage);
+    emit_usage(sink, response_id, &usage);
+    emit_chunk(sink, response_id, "", true, None);
+    Ok(())
+}
 
-    // Send request
-    let response = client
-        .post(ANTHROPIC_API_URL)
-        .header("x-api-key", api_key)
-        .header("anthropic-version", ANTHROPIC_VERSION)
-        .header("content-type", "application/json")
-        .timeout(std::time::Duration::from_secs(300))
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            if e.is_connect() {
-                "Cannot connect to Anthropic API. Check your internet connection.".to_string()
-            } else if e.is_timeout() {
-                "Anthropic API request timed out.".to_string()
-            } else {
-                format!("Anthropic API request failed: {e}")
+/// One streamed assistant message.
+struct Turn {
+    /// Assistant content blocks (thinking, text and tool_use), for replaying the turn.
+    content: Vec<serde_json::Value>,
+    stop_reason: Option<String>,
+    usage: Usage,
+    /// Error event from the stream.
+    error: Option<String>,
+    /// The error is transient (overload, rate limit, server error).
+    retryable: bool,
+    /// Text or thinking was already sent to the sink.
+    streamed: bool,
+}
+
+/// Send one Messages request and stream its text to `sink`, collecting the
+/// content blocks so tool calls can be executed afterwards.
+///
+/// Rate limits, overload and server errors are retried (see `retry`) as
+/// long as nothing has been streamed for this request yet.
+async fn stream_turn(
+    client: &reqwest::Client,
+    api_key: &str,
+    beta: Option<&str>,
+    body: &serde_json::Value,
+    response_id: &str,
+    sink: &dyn EventSink,
+) -> Result<Turn, String> {
+    let mut retrier = Retrier::new("Anthropic API", response_id, sink);
+    loop {
+        let mut request = client
+            .post(ANTHROPIC_API_URL)
+            .header("x-api-key", api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("content-type", "application/json")
+            .timeout(std::time::Duration::from_secs(300))
+            .json(body);
+        if let Some(beta) = beta {
+            request = request.header("anthropic-beta", beta);
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let transient = e.is_connect() || e.is_timeout();
+                let error = describe_send_error(e);
+                if transient && retrier.wait(None, &error).await {
+                    continue;
+                }
+                return Err(error);
             }
-        })?;
+        };
 
-    tracing::info!("[claude_api] Response status: {}", response.status());
+        tracing::info!("[claude_api] Response status: {}", response.status());
 
-    // Check for HTTP errors
-    if !response.status().is_success() {
+        // Check for HTTP errors
         let status = response.status();
-        let text = response.text().await.unwrap_or_default();
-
-        if status.as_u16() == 401 {
-            return Err("Invalid Anthropic API key. Check your API key in Settings.".to_string());
-        } else if status.as_u16() == 429 {
-            return Err("Anthropic API rate limit exceeded. Please wait and try again.".to_string());
-        } else if status.as_u16() == 400 {
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
-                if let Some(msg) = json.get("error").and_then(|e| e.get("message")).and_then(|m| m.as_str()) {
-                    return Err(format!("Anthropic API error: {msg}"));
-                }
+        if !status.is_success() {
+            let hint = retry::retry_after(response.headers());
+            let text = response.text().await.unwrap_or_default();
+            let error = describe_http_error(status, &text);
+            if retry::is_retryable_status(status) && retrier.wait(hint, &error).await {
+                continue;
             }
-            return Err(format!("Anthropic API returned {status}: {text}"));
-        } else {
-            return Err(format!("Anthropic API returned {status}: {text}"));
+            return Err(error);
         }
+
+        let turn = read_turn(response, response_id, sink).await?;
+        // Overloaded mid-stream before anything reached the user: safe to retry
+        if let (Some(error), true, false) = (&turn.error, turn.retryable, turn.streamed) {
+            if retrier.wait(None, error).await {
+                continue;
+            }
+        }
+        return Ok(turn);
     }
+}
 
+/// Read one SSE response into a `Turn`, streaming text and thinking to `sink`.
+async fn read_turn(
+    response: reqwest::Response,
+    response_id: &str,
+    sink: &dyn EventSink,
+) -> Result<Turn, String> {
     // Read the SSE stream — same pattern as openclaw.rs
     let mut stream = response.bytes_stream();
     let mut buffer = String::new();
+    let mut turn = Turn {
+        content: Vec::new(),
+        stop_reason: None,
+        usage: Usage::default(),
+        error: None,
+        retryable: false,
+        streamed: false,
+    };
+    // tool_use input arrives as JSON fragments, keyed by block index
+    let mut partial_json: std::collections::HashMap<usize, String> = Default::default();
 
     while let Some(chunk_result) = stream.next().await {
         let chunk = chunk_result.map_err(|e| format!("Stream read error: {e}"))?;
@@ -255,78 +722,115 @@ pub async fn stream_claude_api(
             let line = buffer[..newline_pos].trim_end_matches('\r').to_string();
             buffer = buffer[newline_pos + 1..].to_string();
 
-            if line.is_empty() {
-                continue;
+            let Some(data) = line.strip_prefix("data: ") else { continue };
+            if data.trim() == "[DONE]" {
+                return Ok(finish_turn(turn, partial_json));
             }
+            let Ok(json) = serde_json::from_str::<serde_json::Value>(data) else { continue };
+            let index = json.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
 
-            if let Some(data) = line.strip_prefix("data: ") {
-                if data.trim() == "[DONE]" {
-                    let _ = window.emit("agent-chunk", serde_json::json!({
-                        "responseId": response_id,
-                        "chunk": "",
-                        "done": true,
-                        "error": serde_json::Value::Null,
-                    }));
-                    return Ok(());
+            match json.get("type").and_then(|t| t.as_str()).unwrap_or("") {
+                "message_start" => {
+                    if let Some(u) = json.get("message").and_then(|m| m.get("usage")) {
+                        turn.usage = Usage::from_api(u);
+                    }
                 }
-
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                    let event_type = json.get("type").and_then(|t| t.as_str()).unwrap_or("");
-
-                    match event_type {
-                        "content_block_delta" => {
-                            if let Some(text) = json
-                                .get("delta")
-                                .and_then(|d| d.get("text"))
-                                .and_then(|t| t.as_str())
-                            {
+                "content_block_start" => {
+                    if let Some(block) = json.get("content_block") {
+                        while turn.content.len() <= index {
+                            turn.content.push(serde_json::Value::Null);
+                        }
+                        turn.content[index] = block.clone();
+                    }
+                }
+                "content_block_delta" => {
+                    let Some(delta) = json.get("delta") else { continue };
+                    match delta.get("type").and_then(|t| t.as_str()) {
+                        Some("input_json_delta") => {
+                            if let Some(part) = delta.get("partial_json").and_then(|p| p.as_str()) {
+                                partial_json.entry(index).or_default().push_str(part);
+                            }
+                        }
+                        Some("thinking_delta") => {
+                            if let Some(text) = delta.get("thinking").and_then(|t| t.as_str()) {
                                 if !text.is_empty() {
-                                    let _ = window.emit("agent-chunk", serde_json::json!({
-                                        "responseId": response_id,
-                                        "chunk": text,
-                                        "done": false,
-                                        "error": serde_json::Value::Null,
-                                    }));
+                                    turn.streamed = true;
+                                    emit_thinking(sink, response_id, text);
+                                    append_to_block(&mut turn.content, index, "thinking", text);
                                 }
                             }
                         }
-                        "message_stop" => {
-                            let _ = window.emit("agent-chunk", serde_json::json!({
-                                "responseId": response_id,
-                                "chunk": "",
-                                "done": true,
-                                "error": serde_json::Value::Null,
-                            }));
-                            return Ok(());
+                        // Thinking blocks must be replayed with their signature
+                        // when tool results are sent back
+                        Some("signature_delta") => {
+                            if let Some(signature) = delta.get("signature").and_then(|t| t.as_str()) {
+                                append_to_block(&mut turn.content, index, "signature", signature);
+                            }
                         }
-                        "error" => {
-                            let err_msg = json
-                                .get("error")
-                                .and_then(|e| e.get("message"))
-                                .and_then(|m| m.as_str())
-                                .unwrap_or("Unknown API error");
-                            let _ = window.emit("agent-chunk", serde_json::json!({
-                                "responseId": response_id,
-                                "chunk": "",
-                                "done": false,
-                                "error": err_msg,
-                            }));
-                            return Ok(());
+                        _ => {
+                            if let Some(text) = delta.get("text").and_then(|t| t.as_str()) {
+                                if !text.is_empty() {
+                                    turn.streamed = true;
+                                    emit_chunk(sink, response_id, text, false, None);
+                                    append_to_block(&mut turn.content, index, "text", text);
+                                }
+                            }
                         }
-                        _ => {}
                     }
                 }
+                "message_delta" => {
+                    if let Some(reason) = json.get("delta").and_then(|d| d.get("stop_reason")).and_then(|r| r.as_str()) {
+                        turn.stop_reason = Some(reason.to_string());
+                    }
+                    if let Some(out) = json.get("usage")
+                        .and_then(|u| u.get("output_tokens"))
+                        .and_then(|t| t.as_u64())
+                    {
+                        turn.usage.output_tokens = out;
+                    }
+                }
+                "message_stop" => return Ok(finish_turn(turn, partial_json)),
+                "error" => {
+                    let error = json.get("error");
+                    let err_msg = error
+                        .and_then(|e| e.get("message"))
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("Unknown API error");
+                    turn.error = Some(err_msg.to_string());
+                    turn.retryable = matches!(
+                        error.and_then(|e| e.get("type")).and_then(|t| t.as_str()),
+                        Some("overloaded_error" | "rate_limit_error" | "api_error"),
+                    );
+                    return Ok(turn);
+                }
+                _ => {}
             }
         }
     }
 
-    // Stream ended without message_stop — still mark as complete
-    let _ = window.emit("agent-chunk", serde_json::json!({
-        "responseId": response_id,
-        "chunk": "",
-        "done": true,
-        "error": serde_json::Value::Null,
-    }));
+    // Stream ended without message_stop — treat what we have as complete
+    Ok(finish_turn(turn, partial_json))
+}
 
-    Ok(())
+/// Append streamed text to a string field of the content block at `index`.
+fn append_to_block(content: &mut [serde_json::Value], index: usize, field: &str, text: &str) {
+    if let Some(block) = content.get_mut(index) {
+        let existing = block.get(field).and_then(|t| t.as_str()).unwrap_or("");
+        block[field] = serde_json::json!(format!("{existing}{text}"));
+    }
+}
+
+/// Attach the accumulated tool inputs and drop empty placeholder blocks.
+fn finish_turn(mut turn: Turn, partial_json: std::collections::HashMap<usize, String>) -> Turn {
+    for (index, json) in partial_json {
+        if let Some(block) = turn.content.get_mut(index) {
+            block["input"] = serde_json::from_str(&json).unwrap_or_else(|_| serde_json::json!({}));
+        }
+    }
+    turn.content.retain(|block| match block.get("type").and_then(|t| t.as_str()) {
+        Some("text") => block.get("text").and_then(|t| t.as_str()).is_some_and(|t| !t.is_empty()),
+        Some(_) => true,
+        None => false,
+    });
+    turn
 }
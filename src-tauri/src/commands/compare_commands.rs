@@ -0,0 +1,44 @@
+use tauri::State;
+use std::sync::Arc;
+use crate::compare::{self, CompareRequest, CompareResult, CompareStarted, JudgeRequest};
+use crate::error::JaibberError;
+use crate::state::AppState;
+
+/// Send one prompt to several providers in parallel. Each candidate streams
+/// under its own sub-response ID; `agent-compare-complete` follows when all
+/// have finished.
+#[tauri::command]
+pub async fn run_agent_compare(
+    request: CompareRequest,
+    window: tauri::Window,
+    state: State<'_, Arc<AppState>>,
+) -> Result<CompareStarted, JaibberError> {
+    compare::run(state.inner().clone(), Arc::new(window), request).await
+}
+
+/// A finished comparison, including every candidate's full output.
+#[tauri::command]
+pub async fn get_agent_comparison(
+    response_id: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Option<CompareResult>, JaibberError> {
+    Ok(state.comparisons.get(&response_id))
+}
+
+#[tauri::command]
+pub async fn list_agent_comparisons(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<CompareResult>, JaibberError> {
+    Ok(state.comparisons.list())
+}
+
+/// Run several providers, then let a judge provider select or merge the best
+/// answer. Only the judged answer streams under `request.responseId`.
+#[tauri::command]
+pub async fn run_agent_judge(
+    request: JudgeRequest,
+    window: tauri::Window,
+    state: State<'_, Arc<AppState>>,
+) -> Result<CompareStarted, JaibberError> {
+    compare::run_judged(state.inner().clone(), Arc::new(window), request).await
+}
@@ -1,2 +1,9 @@
 pub mod settings_commands;
 pub mod process_commands;
+pub mod schedule_commands;
+pub mod watch_commands;
+pub mod outbox_commands;
+pub mod webhook_commands;
+pub mod pipeline_commands;
+pub mod compare_commands;
+pub mod model_commands;
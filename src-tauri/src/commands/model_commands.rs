@@ -0,0 +1,21 @@
+use tauri::State;
+use std::sync::Arc;
+use crate::error::JaibberError;
+use crate::models::{self, ProviderModels};
+use crate::state::AppState;
+
+/// List available models for one provider, or for every provider that can
+/// be queried. Providers that fail report an `error` instead of models.
+#[tauri::command]
+pub async fn list_models(
+    provider: Option<String>,
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<ProviderModels>, JaibberError> {
+    let settings = state.settings.read().await.clone();
+    let providers: Vec<String> = match provider {
+        Some(p) => vec![p.to_lowercase()],
+        None => models::PROVIDERS.iter().map(|p| p.to_string()).collect(),
+    };
+    let lookups = providers.iter().map(|p| models::list_for(p, &settings));
+    Ok(futures_util::future::join_all(lookups).await)
+}
@@ -0,0 +1,60 @@
+use tauri::State;
+use std::sync::Arc;
+use crate::api_client::NewMessage;
+use crate::error::JaibberError;
+use crate::outbox::OutboxStatus;
+use crate::state::AppState;
+
+/// Pending and failed outbox items, plus connectivity as last observed.
+#[tauri::command]
+pub async fn get_outbox_status(
+    state: State<'_, Arc<AppState>>,
+) -> Result<OutboxStatus, JaibberError> {
+    Ok(state.outbox.status())
+}
+
+/// Persist a chat message through the durable outbox.
+#[tauri::command]
+pub async fn outbox_send_message(
+    state: State<'_, Arc<AppState>>,
+    project_id: String,
+    message: NewMessage,
+) -> Result<(), JaibberError> {
+    state.outbox.send_message(&project_id, message)
+}
+
+/// Queue a task update (e.g. a final status) through the durable outbox.
+#[tauri::command]
+pub async fn outbox_update_task(
+    state: State<'_, Arc<AppState>>,
+    task_id: String,
+    updates: serde_json::Value,
+) -> Result<(), JaibberError> {
+    state.outbox.update_task(&task_id, updates)
+}
+
+/// Re-queue failed items (all, or one by ID) and flush now.
+/// Returns the number of items re-queued.
+#[tauri::command]
+pub async fn retry_outbox(
+    state: State<'_, Arc<AppState>>,
+    item_id: Option<String>,
+) -> Result<usize, JaibberError> {
+    state.outbox.retry_failed(item_id.as_deref())
+}
+
+/// Drop failed items (all, or one by ID). Returns the number removed.
+#[tauri::command]
+pub async fn discard_outbox_failed(
+    state: State<'_, Arc<AppState>>,
+    item_id: Option<String>,
+) -> Result<usize, JaibberError> {
+    state.outbox.discard_failed(item_id.as_deref())
+}
+
+/// Flush pending items now (e.g. when the webview sees the network return).
+#[tauri::command]
+pub async fn flush_outbox(state: State<'_, Arc<AppState>>) -> Result<(), JaibberError> {
+    state.outbox.wake();
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use tauri::State;
+use crate::error::JaibberError;
+use crate::local_store;
+use crate::pipeline::{self, PipelineDef, PipelineRun, PIPELINES_KEY};
+use crate::state::AppState;
+
+#[tauri::command]
+pub async fn list_pipelines(app: tauri::AppHandle) -> Result<Vec<PipelineDef>, JaibberError> {
+    Ok(local_store::read_list(&app, PIPELINES_KEY))
+}
+
+/// Create or update a pipeline. Rejects unknown dependencies and cycles.
+#[tauri::command]
+pub async fn save_pipeline(
+    app: tauri::AppHandle,
+    mut pipeline: PipelineDef,
+) -> Result<PipelineDef, JaibberError> {
+    pipeline::validate(&pipeline)?;
+    if pipeline.id.is_empty() {
+        pipeline.id = uuid::Uuid::new_v4().to_string();
+    }
+    let mut pipelines: Vec<PipelineDef> = local_store::read_list(&app, PIPELINES_KEY);
+    match pipelines.iter_mut().find(|p| p.id == pipeline.id) {
+        Some(existing) => *existing = pipeline.clone(),
+        None => pipelines.push(pipeline.clone()),
+    }
+    local_store::write_list(&app, PIPELINES_KEY, &pipelines)?;
+    Ok(pipeline)
+}
+
+#[tauri::command]
+pub async fn delete_pipeline(
+    app: tauri::AppHandle,
+    pipeline_id: String,
+) -> Result<bool, JaibberError> {
+    let mut pipelines: Vec<PipelineDef> = local_store::read_list(&app, PIPELINES_KEY);
+    let before = pipelines.len();
+    pipelines.retain(|p| p.id != pipeline_id);
+    if pipelines.len() == before {
+        return Ok(false);
+    }
+    local_store::write_list(&app, PIPELINES_KEY, &pipelines)?;
+    Ok(true)
+}
+
+/// Start a saved pipeline. Returns the pipeline run ID; progress arrives as
+/// `pipeline-step` / `pipeline-status` events.
+#[tauri::command]
+pub async fn run_pipeline(
+    app: tauri::AppHandle,
+    state: State<'_, Arc<AppState>>,
+    pipeline_id: String,
+    input: Option<String>,
+) -> Result<String, JaibberError> {
+    let def = local_store::read_list::<PipelineDef>(&app, PIPELINES_KEY)
+        .into_iter()
+        .find(|p| p.id == pipeline_id)
+        .ok_or_else(|| JaibberError::Other(format!("Pipeline not found: {pipeline_id}")))?;
+    pipeline::start(state.inner().clone(), Arc::new(app), def, input.unwrap_or_default())
+}
+
+#[tauri::command]
+pub async fn list_pipeline_runs(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<PipelineRun>, JaibberError> {
+    Ok(state.pipelines.list())
+}
+
+#[tauri::command]
+pub async fn get_pipeline_run(
+    state: State<'_, Arc<AppState>>,
+    run_id: String,
+) -> Result<Option<PipelineRun>, JaibberError> {
+    Ok(state.pipelines.get(&run_id))
+}
+
+/// Resolve an approval gate. Returns false if the step wasn't waiting.
+#[tauri::command]
+pub async fn approve_pipeline_step(
+    state: State<'_, Arc<AppState>>,
+    run_id: String,
+    step_id: String,
+    approved: bool,
+) -> Result<bool, JaibberError> {
+    Ok(state.pipelines.approve(&run_id, &step_id, approved))
+}
+
+#[tauri::command]
+pub async fn cancel_pipeline_run(
+    state: State<'_, Arc<AppState>>,
+    run_id: String,
+) -> Result<bool, JaibberError> {
+    Ok(state.pipelines.cancel(&run_id))
+}
@@ -1,9 +1,10 @@
-use tauri::{State, Emitter};
+use tauri::State;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, BufReader};
 use crate::state::{AppState, AttachmentInfo};
 use crate::error::JaibberError;
-use crate::agent_providers::{ProviderConfig, ProviderKind, extract_text_from_line, is_auth_error};
+use crate::agent_providers::{ModelConfig, ProviderConfig, ProviderKind, is_auth_error};
+use crate::context::ContextMessage;
+use crate::runtime::{self, RunInfo, RunRequest};
 
 /// Spawns an agent process via bash -c so that the user's full shell environment
 /// (nvm, PATH, etc.) is available. Prompt is passed via env var to avoid shell
@@ -14,6 +15,7 @@ pub async fn run_agent(
     project_dir: String,
     agent_provider: Option<String>,
     custom_command: Option<String>,
+    model: Option<String>,
     state: State<'_, Arc<AppState>>,
 ) -> Result<String, JaibberError> {
     if project_dir.is_empty() {
@@ -24,6 +26,7 @@ pub async fn run_agent(
     let provider = ProviderConfig {
         kind: ProviderKind::from_str(provider_str),
         custom_command,
+        model,
     };
 
     let pcmd = provider.build_oneshot_cmd();
@@ -100,10 +103,8 @@ async fn run_oneshot(
 /// its stdout as Tauri events. Uses the provider abstraction to support
 /// multiple backends (Claude, Codex, Gemini, custom).
 ///
-/// Auth fallback: if the CLI fails with an auth error and a fallback API key
-/// is configured for this provider, automatically retries with the key set
-/// as an env var and emits an `"agent-auth-fallback"` event so the frontend
-/// can show a subtle notice.
+/// The run itself is driven by `runtime::start_run`, which also tracks it in
+/// the run registry so it shows up in `list_agent_runs` and can be cancelled.
 #[tauri::command]
 pub async fn run_agent_stream(
     prompt: String,
@@ -116,491 +117,63 @@ pub async fn run_agent_stream(
     attachments: Option<Vec<AttachmentInfo>>,
     session_id: Option<String>,
     continue_session: Option<bool>,
+    agent_name: Option<String>,
+    context_messages: Option<Vec<ContextMessage>>,
+    thread_id: Option<String>,
+    thinking_budget: Option<u32>,
+    model_config: Option<ModelConfig>,
+    model_override: Option<ModelConfig>,
     window: tauri::Window,
     state: State<'_, Arc<AppState>>,
 ) -> Result<(), JaibberError> {
-    let provider_str = agent_provider.as_deref().unwrap_or("claude");
-    let provider = ProviderConfig {
-        kind: ProviderKind::from_str(provider_str),
-        custom_command: custom_command.clone(),
-    };
-
-    // OpenClaw uses HTTP — doesn't need a project_dir. CLI providers do.
-    if provider.kind != ProviderKind::OpenClaw && project_dir.is_empty() {
-        return Err(JaibberError::Other("project_dir must not be empty".into()));
-    }
-
-    // Read fallback keys from settings
-    let settings = state.settings.read().await;
-    let fallback_key = settings.fallback_key_for(provider_str).map(|s| s.to_string());
-    drop(settings);
-
-    // Build the user prompt with conversation context prepended.
-    let mut full_prompt = String::new();
-    if !conversation_context.is_empty() {
-        full_prompt.push_str(
-            "Below is the recent conversation history for context. \
-             Respond ONLY to the final user message. \
-             Be conversational and concise — reply directly to the user as a chat participant. \
-             Do NOT narrate your thought process, planning steps, or internal reasoning. \
-             Do NOT describe actions you would take (e.g. \"I should...\", \"Let me...\", \"I will...\"). \
-             Just answer.\n\n"
-        );
-        full_prompt.push_str(&conversation_context);
-        full_prompt.push_str("\n\n---\n\n");
-    }
-    full_prompt.push_str(&prompt);
-
-    // ── OpenClaw: HTTP path (no CLI process) ─────────────────────────
-    if provider.kind == ProviderKind::OpenClaw {
-        let oc_config = crate::openclaw::discover_openclaw()
-            .map_err(|e| JaibberError::Other(e))?;
-
-        let rid = response_id.clone();
-        let win = window.clone();
-        let sys = system_prompt.clone();
-        let fp = full_prompt.clone();
-        let rid2 = rid.clone();
-        let win2 = win.clone();
-        let handle = tokio::spawn(async move {
-            if let Err(e) = crate::openclaw::stream_openclaw(
-                &oc_config, &sys, &fp, &rid, &win,
-            ).await {
-                let _ = win.emit("agent-chunk", serde_json::json!({
-                    "responseId": rid,
-                    "chunk": "",
-                    "done": false,
-                    "error": e,
-                }));
-            }
-        });
-        tokio::spawn(async move {
-            if let Err(e) = handle.await {
-                let _ = win2.emit("agent-chunk", serde_json::json!({
-                    "responseId": rid2,
-                    "chunk": "",
-                    "done": true,
-                    "error": format!("Agent task panicked: {e}"),
-                }));
-            }
-        });
-        return Ok(());
-    }
-
-    // ── Claude: direct API (supports multimodal when API key is set) ──
-    if provider.kind == ProviderKind::Claude {
-        if let Some(ref api_key) = fallback_key {
-            let rid = response_id.clone();
-            let win = window.clone();
-            let sys = system_prompt.clone();
-            let conv = conversation_context.clone();
-            let prm = prompt.clone();
-            let atts = attachments.unwrap_or_default();
-            let key = api_key.clone();
-            let rid2 = rid.clone();
-            let win2 = win.clone();
-            let handle = tokio::spawn(async move {
-                if let Err(e) = crate::claude_api::stream_claude_api(
-                    &key, &sys, &prm, &conv, &atts, &rid, &win,
-                ).await {
-                    let _ = win.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid,
-                        "chunk": "",
-                        "done": false,
-                        "error": e,
-                    }));
-                }
-            });
-            tokio::spawn(async move {
-                if let Err(e) = handle.await {
-                    let _ = win2.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid2,
-                        "chunk": "",
-                        "done": true,
-                        "error": format!("Agent task panicked: {e}"),
-                    }));
-                }
-            });
-            return Ok(());
-        }
-        // No API key → fall through to CLI path
-    }
-
-    let pcmd = provider.build_stream_cmd(
-        !system_prompt.is_empty(),
-        session_id.as_deref(),
-        continue_session.unwrap_or(false),
-    );
-    let provider_kind = provider.kind.clone();
-    let reauth_hint = provider.reauth_hint().to_string();
-    let install_hint = provider.install_hint().to_string();
-
-    // Try to spawn the agent process
-    let spawn_result = spawn_agent_process(
-        &pcmd.bash_command,
-        &project_dir,
-        &full_prompt,
-        &system_prompt,
-        None, // No API key on first attempt — use CLI's own auth
-    );
-
-    let mut child = match spawn_result {
-        Ok(c) => c,
-        Err(e) => return Err(e),
+    let request = RunRequest {
+        prompt,
+        project_dir,
+        response_id,
+        system_prompt,
+        conversation_context,
+        agent_provider,
+        custom_command,
+        attachments: attachments.unwrap_or_default(),
+        session_id,
+        continue_session: continue_session.unwrap_or(false),
+        agent_name,
+        context_messages: context_messages.unwrap_or_default(),
+        thread_id,
+        thinking_budget,
+        model_config: model_config.unwrap_or_default(),
+        model_override: model_override.unwrap_or_default(),
+        ..Default::default()
     };
-
-    let stdout = child.stdout.take()
-        .ok_or_else(|| JaibberError::Shell("Failed to capture stdout".into()))?;
-    let stderr_pipe = child.stderr.take();
-
-    let rid = response_id.clone();
-    let win = window.clone();
-    let fallback_env_var = pcmd.api_key_env_var.map(|s| s.to_string());
-    let bash_cmd_for_retry = pcmd.bash_command.clone();
-    let full_prompt_for_retry = full_prompt.clone();
-    let system_prompt_for_retry = system_prompt.clone();
-    let project_dir_for_retry = project_dir.clone();
-
-    // Clones for the panic supervisor
-    let rid_supervisor = rid.clone();
-    let win_supervisor = win.clone();
-
-    // Spawn a background task to read stream lines and emit chunks
-    let handle = tokio::spawn(async move {
-        use tokio::time::{timeout, Duration};
-
-        // Collect stderr in parallel so we can include it in error messages
-        let stderr_handle = stderr_pipe.map(|pipe| {
-            tokio::spawn(async move {
-                let mut buf = String::new();
-                let reader = BufReader::new(pipe);
-                let mut lines = reader.lines();
-                while let Ok(Some(line)) = lines.next_line().await {
-                    buf.push_str(&line);
-                    buf.push('\n');
-                    if buf.len() > 4000 { break; } // cap stderr collection
-                }
-                buf
-            })
-        });
-
-        let reader = BufReader::new(stdout);
-        let mut lines = reader.lines();
-        let mut got_output = false;
-        let mut emitted_session_id = false;
-        let idle_timeout_initial = Duration::from_secs(300);
-        let idle_timeout_after_output = Duration::from_secs(60);
-
-        loop {
-            let wait = if got_output { idle_timeout_after_output } else { idle_timeout_initial };
-            match timeout(wait, lines.next_line()).await {
-                Ok(Ok(Some(line))) => {
-                    let parsed = extract_text_from_line(&provider_kind, &line);
-
-                    // Emit session ID once when first discovered in stream output
-                    if !emitted_session_id {
-                        if let Some(ref sid) = parsed.session_id {
-                            emitted_session_id = true;
-                            let _ = win.emit("agent-session", serde_json::json!({
-                                "responseId": rid,
-                                "sessionId": sid,
-                            }));
-                        }
-                    }
-
-                    if !parsed.text.is_empty() {
-                        got_output = true;
-                        let _ = win.emit("agent-chunk", serde_json::json!({
-                            "responseId": rid,
-                            "chunk": parsed.text,
-                            "done": false,
-                            "error": null,
-                        }));
-                    }
-                }
-                Ok(Ok(None)) => break, // EOF — process finished
-                Ok(Err(_)) => break,   // Read error
-                Err(_) => {
-                    // Idle timeout fired — kill the lingering process
-                    let _ = child.kill().await;
-                    if got_output {
-                        let _ = win.emit("agent-chunk", serde_json::json!({
-                            "responseId": rid,
-                            "chunk": "",
-                            "done": true,
-                            "error": null,
-                        }));
-                    } else {
-                        let _ = win.emit("agent-chunk", serde_json::json!({
-                            "responseId": rid,
-                            "chunk": "",
-                            "done": false,
-                            "error": "Agent timed out (no output for 5 minutes)",
-                        }));
-                    }
-                    return;
-                }
-            }
-        }
-
-        // Collect stderr for error reporting
-        let stderr_text = match stderr_handle {
-            Some(handle) => handle.await.unwrap_or_default(),
-            None => String::new(),
-        };
-
-        // Wait for exit status with a 30-second timeout
-        match timeout(Duration::from_secs(30), child.wait()).await {
-            Ok(Ok(status)) => {
-                if status.success() || got_output {
-                    let _ = win.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid,
-                        "chunk": "",
-                        "done": true,
-                        "error": null,
-                    }));
-                } else {
-                    // Process failed with no output — check for auth error
-                    if is_auth_error(&stderr_text) {
-                        if let (Some(ref env_var), Some(ref key)) = (&fallback_env_var, &fallback_key) {
-                            // ── AUTH FALLBACK: retry with API key ──
-                            let _ = win.emit("agent-auth-fallback", serde_json::json!({
-                                "responseId": rid,
-                                "provider": format!("{:?}", provider_kind),
-                                "message": format!(
-                                    "CLI auth expired — using fallback API key. {}",
-                                    reauth_hint
-                                ),
-                            }));
-
-                            // Spawn the retry
-                            retry_with_fallback(
-                                &bash_cmd_for_retry,
-                                &project_dir_for_retry,
-                                &full_prompt_for_retry,
-                                &system_prompt_for_retry,
-                                env_var,
-                                key,
-                                &rid,
-                                &provider_kind,
-                                &win,
-                            ).await;
-                            return;
-                        }
-                    }
-
-                    // Not an auth error, or no fallback available
-                    let code = status.code().map(|c| c.to_string()).unwrap_or_else(|| "?".into());
-                    let err_detail = if stderr_text.contains("command not found") || stderr_text.contains("not recognized") {
-                        install_hint.to_string()
-                    } else if is_auth_error(&stderr_text) {
-                        // Auth error but no fallback key configured
-                        format!(
-                            "Agent auth expired (exit code {code}). {} or add a fallback API key in Settings.\n{}",
-                            reauth_hint,
-                            stderr_text.trim()
-                        )
-                    } else if stderr_text.trim().is_empty() {
-                        format!("Agent process exited with code {code}")
-                    } else {
-                        format!("Agent process exited with code {code}\n{}", stderr_text.trim())
-                    };
-                    let _ = win.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid,
-                        "chunk": "",
-                        "done": false,
-                        "error": err_detail,
-                    }));
-                }
-            }
-            _ => {
-                let _ = child.kill().await;
-                if got_output {
-                    let _ = win.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid,
-                        "chunk": "",
-                        "done": true,
-                        "error": null,
-                    }));
-                } else {
-                    let _ = win.emit("agent-chunk", serde_json::json!({
-                        "responseId": rid,
-                        "chunk": "",
-                        "done": false,
-                        "error": "Agent process timed out",
-                    }));
-                }
-            }
-        }
-    });
-    tokio::spawn(async move {
-        if let Err(e) = handle.await {
-            let _ = win_supervisor.emit("agent-chunk", serde_json::json!({
-                "responseId": rid_supervisor,
-                "chunk": "",
-                "done": true,
-                "error": format!("Agent task panicked: {e}"),
-            }));
-        }
-    });
-
+    runtime::start_run(state.inner(), request, "chat", Some(Arc::new(window))).await?;
     Ok(())
 }
 
-/// Helper: spawn an agent CLI process with the given configuration.
-fn spawn_agent_process(
-    bash_cmd: &str,
-    project_dir: &str,
-    prompt: &str,
-    system_prompt: &str,
-    api_key_env: Option<(&str, &str)>,
-) -> Result<tokio::process::Child, JaibberError> {
-    let mut cmd = tokio::process::Command::new("bash");
-    cmd.arg("-c")
-       .arg(bash_cmd)
-       .current_dir(project_dir)
-       .env("JAIBBER_PROMPT", prompt)
-       .env("JAIBBER_SYSTEM", system_prompt)
-       .stdout(std::process::Stdio::piped())
-       .stderr(std::process::Stdio::piped());
-
-    if let Some((var, key)) = api_key_env {
-        cmd.env(var, key);
-    }
-
-    cmd.spawn()
-        .map_err(|e| JaibberError::Shell(format!("Failed to spawn bash: {e}")))
+/// List active and recently finished agent runs (from any source).
+#[tauri::command]
+pub async fn list_agent_runs(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<RunInfo>, JaibberError> {
+    Ok(state.runs.list())
 }
 
-/// Retry a streaming agent invocation with a fallback API key.
-/// This is called when the first attempt failed with an auth error.
-async fn retry_with_fallback(
-    bash_cmd: &str,
-    project_dir: &str,
-    full_prompt: &str,
-    system_prompt: &str,
-    env_var: &str,
-    api_key: &str,
-    response_id: &str,
-    provider_kind: &ProviderKind,
-    window: &tauri::Window,
-) {
-    use tokio::time::{timeout, Duration};
-
-    let spawn_result = spawn_agent_process(
-        bash_cmd,
-        project_dir,
-        full_prompt,
-        system_prompt,
-        Some((env_var, api_key)),
-    );
-
-    let mut child = match spawn_result {
-        Ok(c) => c,
-        Err(e) => {
-            let _ = window.emit("agent-chunk", serde_json::json!({
-                "responseId": response_id,
-                "chunk": "",
-                "done": false,
-                "error": format!("Auth fallback retry failed to spawn: {e}"),
-            }));
-            return;
-        }
-    };
-
-    let stdout = match child.stdout.take() {
-        Some(s) => s,
-        None => {
-            let _ = window.emit("agent-chunk", serde_json::json!({
-                "responseId": response_id,
-                "chunk": "",
-                "done": false,
-                "error": "Auth fallback retry: failed to capture stdout",
-            }));
-            return;
-        }
-    };
-
-    let reader = BufReader::new(stdout);
-    let mut lines = reader.lines();
-    let mut got_output = false;
-    let idle_timeout_initial = Duration::from_secs(300);
-    let idle_timeout_after_output = Duration::from_secs(60);
-
-    loop {
-        let wait = if got_output { idle_timeout_after_output } else { idle_timeout_initial };
-        match timeout(wait, lines.next_line()).await {
-            Ok(Ok(Some(line))) => {
-                let parsed = extract_text_from_line(provider_kind, &line);
-                if !parsed.text.is_empty() {
-                    got_output = true;
-                    let _ = window.emit("agent-chunk", serde_json::json!({
-                        "responseId": response_id,
-                        "chunk": parsed.text,
-                        "done": false,
-                        "error": null,
-                    }));
-                }
-            }
-            Ok(Ok(None)) => break,
-            Ok(Err(_)) => break,
-            Err(_) => {
-                let _ = child.kill().await;
-                if got_output {
-                    let _ = window.emit("agent-chunk", serde_json::json!({
-                        "responseId": response_id,
-                        "chunk": "",
-                        "done": true,
-                        "error": null,
-                    }));
-                } else {
-                    let _ = window.emit("agent-chunk", serde_json::json!({
-                        "responseId": response_id,
-                        "chunk": "",
-                        "done": false,
-                        "error": "Agent timed out on fallback retry",
-                    }));
-                }
-                return;
-            }
-        }
-    }
+/// Cancel a running agent run. Returns false if it was unknown or already finished.
+#[tauri::command]
+pub async fn cancel_agent_run(
+    response_id: String,
+    state: State<'_, Arc<AppState>>,
+) -> Result<bool, JaibberError> {
+    Ok(state.runs.cancel(&response_id))
+}
 
-    match timeout(Duration::from_secs(30), child.wait()).await {
-        Ok(Ok(status)) => {
-            if status.success() || got_output {
-                let _ = window.emit("agent-chunk", serde_json::json!({
-                    "responseId": response_id,
-                    "chunk": "",
-                    "done": true,
-                    "error": null,
-                }));
-            } else {
-                let _ = window.emit("agent-chunk", serde_json::json!({
-                    "responseId": response_id,
-                    "chunk": "",
-                    "done": false,
-                    "error": "Agent failed even with fallback API key",
-                }));
-            }
-        }
-        _ => {
-            let _ = child.kill().await;
-            if got_output {
-                let _ = window.emit("agent-chunk", serde_json::json!({
-                    "responseId": response_id,
-                    "chunk": "",
-                    "done": true,
-                    "error": null,
-                }));
-            } else {
-                let _ = window.emit("agent-chunk", serde_json::json!({
-                    "responseId": response_id,
-                    "chunk": "",
-                    "done": false,
-                    "error": "Agent process timed out on fallback retry",
-                }));
-            }
-        }
-    }
+/// Answer an `agent-tool-approval` prompt from a Claude API run.
+/// Returns false if the call was no longer waiting.
+#[tauri::command]
+pub async fn approve_tool_call(
+    response_id: String,
+    tool_use_id: String,
+    approved: bool,
+    state: State<'_, Arc<AppState>>,
+) -> Result<bool, JaibberError> {
+    Ok(state.tool_approvals.resolve(&response_id, &tool_use_id, approved))
 }
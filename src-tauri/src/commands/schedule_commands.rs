@@ -0,0 +1,52 @@
+use crate::error::JaibberError;
+use crate::scheduler::{self, JobRunRecord, JobStatus, ScheduledJob};
+
+/// All scheduled jobs with their next run time and last result.
+#[tauri::command]
+pub async fn list_scheduled_jobs(app: tauri::AppHandle) -> Result<Vec<JobStatus>, JaibberError> {
+    Ok(scheduler::job_statuses(&app))
+}
+
+/// Create or update a scheduled job. Returns the saved job (with its ID).
+#[tauri::command]
+pub async fn save_scheduled_job(
+    app: tauri::AppHandle,
+    job: ScheduledJob,
+) -> Result<ScheduledJob, JaibberError> {
+    scheduler::save_job(&app, job)
+}
+
+#[tauri::command]
+pub async fn delete_scheduled_job(
+    app: tauri::AppHandle,
+    job_id: String,
+) -> Result<bool, JaibberError> {
+    scheduler::delete_job(&app, &job_id)
+}
+
+/// Run a job immediately. Returns the response ID of the started run.
+#[tauri::command]
+pub async fn run_scheduled_job_now(
+    app: tauri::AppHandle,
+    job_id: String,
+) -> Result<String, JaibberError> {
+    let job = scheduler::load_jobs(&app)
+        .into_iter()
+        .find(|j| j.id == job_id)
+        .ok_or_else(|| JaibberError::Other(format!("Unknown scheduled job {job_id}")))?;
+    scheduler::run_job(app, job, "manual").await
+}
+
+/// Run history, newest first, optionally filtered to one job.
+#[tauri::command]
+pub async fn get_scheduled_job_history(
+    app: tauri::AppHandle,
+    job_id: Option<String>,
+) -> Result<Vec<JobRunRecord>, JaibberError> {
+    let mut history = scheduler::load_history(&app);
+    if let Some(id) = job_id {
+        history.retain(|r| r.job_id == id);
+    }
+    history.reverse();
+    Ok(history)
+}
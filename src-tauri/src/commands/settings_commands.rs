@@ -2,8 +2,24 @@ use tauri::State;
 use std::sync::Arc;
 use crate::state::{AppState, AppSettings};
 use crate::error::JaibberError;
+use crate::local_store::{SETTINGS_KEY, STORE_FILE};
 
-const SETTINGS_KEY: &str = "app_settings";
+/// Read settings from the persistent store, if present and valid. A local
+/// API token or webhook secret that is enabled but missing is generated and
+/// saved, so the services can start.
+pub fn load_stored_settings(app: &tauri::AppHandle) -> Option<AppSettings> {
+    use tauri_plugin_store::StoreExt;
+    let store = app.store(STORE_FILE).ok()?;
+    let value = store.get(SETTINGS_KEY)?;
+    let mut settings = serde_json::from_value::<AppSettings>(value.clone()).ok()?;
+    // Both must run, hence `|`
+    if settings.ensure_local_api_token() | settings.ensure_webhook_secret() {
+        if let Err(e) = persist_settings(app, &settings) {
+            tracing::error!("[settings] Failed to save generated secrets: {e}");
+        }
+    }
+    Some(settings)
+}
 
 /// Load settings from the persistent store into state, then return them.
 #[tauri::command]
@@ -11,30 +27,47 @@ pub async fn get_settings(
     state: State<'_, Arc<AppState>>,
     app: tauri::AppHandle,
 ) -> Result<AppSettings, JaibberError> {
-    use tauri_plugin_store::StoreExt;
-    if let Ok(store) = app.store("jaibber.json") {
-        if let Some(value) = store.get(SETTINGS_KEY) {
-            if let Ok(settings) = serde_json::from_value::<AppSettings>(value.clone()) {
-                *state.settings.write().await = settings.clone();
-                return Ok(settings);
-            }
-        }
+    if let Some(settings) = load_stored_settings(&app) {
+        state.set_settings(settings.clone()).await;
+        return Ok(settings);
     }
     Ok(state.settings.read().await.clone())
 }
 
+/// Write settings to the persistent store.
+pub fn persist_settings(app: &tauri::AppHandle, settings: &AppSettings) -> Result<(), JaibberError> {
+    use tauri_plugin_store::StoreExt;
+    let store = app.store(STORE_FILE)
+        .map_err(|e| JaibberError::Other(e.to_string()))?;
+    store.set(SETTINGS_KEY, serde_json::to_value(settings)?);
+    store.save()
+        .map_err(|e| JaibberError::Other(e.to_string()))
+}
+
+/// Save settings and return them as stored, including any generated local
+/// API token or webhook secret. A missing token or secret keeps the current
+/// one (the frontend's copy may predate it); an empty one generates a new one.
 #[tauri::command]
 pub async fn save_settings(
     state: State<'_, Arc<AppState>>,
     app: tauri::AppHandle,
-    settings: AppSettings,
-) -> Result<(), JaibberError> {
-    use tauri_plugin_store::StoreExt;
-    let store = app.store("jaibber.json")
-        .map_err(|e| JaibberError::Other(e.to_string()))?;
-    store.set(SETTINGS_KEY, serde_json::to_value(&settings)?);
-    store.save()
-        .map_err(|e| JaibberError::Other(e.to_string()))?;
-    *state.settings.write().await = settings;
-    Ok(())
+    mut settings: AppSettings,
+) -> Result<AppSettings, JaibberError> {
+    {
+        let current = state.settings.read().await;
+        if settings.local_api_token.is_none() {
+            settings.local_api_token = current.local_api_token.clone();
+        }
+        if settings.webhook_secret.is_none() {
+            settings.webhook_secret = current.webhook_secret.clone();
+        }
+    }
+    settings.ensure_local_api_token();
+    settings.ensure_webhook_secret();
+    persist_settings(&app, &settings)?;
+    state.set_settings(settings.clone()).await;
+    crate::local_api::apply_settings(state.inner()).await;
+    crate::task_worker::apply_settings(state.inner(), Arc::new(app.clone())).await;
+    crate::webhooks::apply_settings(state.inner(), Arc::new(app)).await;
+    Ok(settings)
 }
@@ -0,0 +1,49 @@
+use tauri::State;
+use std::sync::Arc;
+use serde::Serialize;
+use crate::error::JaibberError;
+use crate::state::AppState;
+use crate::watcher::{self, FileWatch};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileWatchStatus {
+    #[serde(flatten)]
+    pub watch: FileWatch,
+    /// Whether an OS watcher is currently attached (false if disabled or the
+    /// directory could not be watched).
+    pub active: bool,
+}
+
+#[tauri::command]
+pub async fn list_file_watches(
+    state: State<'_, Arc<AppState>>,
+    app: tauri::AppHandle,
+) -> Result<Vec<FileWatchStatus>, JaibberError> {
+    let active = watcher::active_watch_ids(state.inner());
+    Ok(watcher::load_watches(&app)
+        .into_iter()
+        .map(|watch| FileWatchStatus { active: active.contains(&watch.id), watch })
+        .collect())
+}
+
+/// Create or update a file watch and restart watchers. Returns the saved watch.
+#[tauri::command]
+pub async fn save_file_watch(
+    app: tauri::AppHandle,
+    watch: FileWatch,
+) -> Result<FileWatch, JaibberError> {
+    let saved = watcher::save_watch(&app, watch)?;
+    watcher::apply(&app);
+    Ok(saved)
+}
+
+#[tauri::command]
+pub async fn delete_file_watch(
+    app: tauri::AppHandle,
+    watch_id: String,
+) -> Result<bool, JaibberError> {
+    let deleted = watcher::delete_watch(&app, &watch_id)?;
+    watcher::apply(&app);
+    Ok(deleted)
+}
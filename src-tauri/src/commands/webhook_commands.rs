@@ -0,0 +1,53 @@
+use crate::error::JaibberError;
+use crate::local_store;
+use crate::webhooks::{WebhookRoute, WEBHOOK_ROUTES_KEY};
+
+#[tauri::command]
+pub async fn list_webhook_routes(app: tauri::AppHandle) -> Result<Vec<WebhookRoute>, JaibberError> {
+    Ok(local_store::read_list(&app, WEBHOOK_ROUTES_KEY))
+}
+
+/// Create or update a webhook route. Paths must be unique URL-safe slugs.
+#[tauri::command]
+pub async fn save_webhook_route(
+    app: tauri::AppHandle,
+    mut route: WebhookRoute,
+) -> Result<WebhookRoute, JaibberError> {
+    let valid_path = !route.path.is_empty()
+        && route.path.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_path {
+        return Err(JaibberError::Other("Webhook path may only contain letters, digits, '-' and '_'".into()));
+    }
+    if route.prompt.trim().is_empty() {
+        return Err(JaibberError::Other("Webhook prompt must not be empty".into()));
+    }
+    if route.id.is_empty() {
+        route.id = uuid::Uuid::new_v4().to_string();
+    }
+
+    let mut routes: Vec<WebhookRoute> = local_store::read_list(&app, WEBHOOK_ROUTES_KEY);
+    if routes.iter().any(|r| r.path == route.path && r.id != route.id) {
+        return Err(JaibberError::Other(format!("Another route already uses /hooks/{}", route.path)));
+    }
+    match routes.iter_mut().find(|r| r.id == route.id) {
+        Some(existing) => *existing = route.clone(),
+        None => routes.push(route.clone()),
+    }
+    local_store::write_list(&app, WEBHOOK_ROUTES_KEY, &routes)?;
+    Ok(route)
+}
+
+#[tauri::command]
+pub async fn delete_webhook_route(
+    app: tauri::AppHandle,
+    route_id: String,
+) -> Result<bool, JaibberError> {
+    let mut routes: Vec<WebhookRoute> = local_store::read_list(&app, WEBHOOK_ROUTES_KEY);
+    let before = routes.len();
+    routes.retain(|r| r.id != route_id);
+    if routes.len() == before {
+        return Ok(false);
+    }
+    local_store::write_list(&app, WEBHOOK_ROUTES_KEY, &routes)?;
+    Ok(true)
+}
@@ -0,0 +1,566 @@
+//! Fan-out runs: send one prompt to several providers in parallel and
+//! compare the answers.
+//!
+//! Each candidate is a normal runtime run with its own sub-response ID
+//! (`<responseId>-<index>`), so its `agent-chunk` events stream like any
+//! other run. When every candidate has finished, a single
+//! `agent-compare-complete` event reports per-candidate latency, length,
+//! cost and exit status. Results are kept in memory for later lookup.
+//!
+//! Judge mode runs the candidates silently, then asks a judge provider to
+//! select or merge the best answer. The judge runs in an empty scratch
+//! directory, never the project. Only the judged answer is streamed to
+//! chat (under the request's response ID); the candidates and the judge's
+//! rationale stay available through `get_agent_comparison`.
+//!
+//! With several candidates in a git repository, each one runs in its own
+//! detached worktree (HEAD plus uncommitted changes to tracked files) under
+//! the temp directory, so agents that edit files don't trample each other.
+//! The worktrees — and whatever the candidates changed in them — are removed
+//! once every candidate has finished. Outside a git repository candidates
+//! share the directory and should be used for read-only questions.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use crate::agent_providers::ModelConfig;
+use crate::error::JaibberError;
+use crate::runtime::{self, now_ms, EventSink, RunRequest, RunStatus, Usage};
+use crate::state::{AppState, AttachmentInfo};
+
+/// Finished comparisons kept in memory.
+const MAX_COMPARISONS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareCandidate {
+    /// `claude`, `codex`, `gemini`, `openclaw` or `custom`.
+    pub provider: String,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    /// Claude only — see `RunRequest::use_api`.
+    #[serde(default)]
+    pub use_api: Option<bool>,
+    /// Model and sampling settings, so one provider can be compared across
+    /// models. Passed as the run's `model_override`.
+    #[serde(default)]
+    pub model_config: ModelConfig,
+    /// Display name; defaults to the provider and model.
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+impl CompareCandidate {
+    fn display_label(&self) -> String {
+        if let Some(label) = self.label.as_deref().filter(|l| !l.trim().is_empty()) {
+            return label.to_string();
+        }
+        match self.model_config.model_name() {
+            Some(model) => format!("{} ({model})", self.provider),
+            None => self.provider.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub project_dir: String,
+    /// Generated when empty. Candidates use `<responseId>-<index>`.
+    #[serde(default)]
+    pub response_id: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub conversation_context: String,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentInfo>,
+    pub candidates: Vec<CompareCandidate>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateResult {
+    pub response_id: String,
+    pub label: String,
+    pub provider: String,
+    pub status: RunStatus,
+    pub error: Option<String>,
+    pub output: String,
+    pub output_chars: usize,
+    /// Time until the first streamed text.
+    pub first_token_ms: Option<u64>,
+    /// Time until the run finished.
+    pub latency_ms: u64,
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareResult {
+    pub response_id: String,
+    pub prompt: String,
+    pub candidates: Vec<CandidateResult>,
+    /// Set in judge mode once the judge has answered.
+    pub judgement: Option<Judgement>,
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+/// Returned by `run_agent_compare` as soon as every candidate has started.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareStarted {
+    pub response_id: String,
+    pub candidates: Vec<StartedCandidate>,
+    pub started_at: u64,
+    /// Removed by `gather` once the candidates are done.
+    #[serde(skip)]
+    worktrees: Option<Worktrees>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartedCandidate {
+    pub response_id: String,
+    pub label: String,
+    pub provider: String,
+    /// Set when the candidate failed to start; it is reported as failed.
+    pub error: Option<String>,
+}
+
+/// Finished comparisons, by response ID.
+#[derive(Default)]
+pub struct CompareRegistry {
+    results: Mutex<HashMap<String, CompareResult>>,
+}
+
+impl CompareRegistry {
+    pub fn get(&self, response_id: &str) -> Option<CompareResult> {
+        self.results.lock().unwrap().get(response_id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<CompareResult> {
+        let mut list: Vec<CompareResult> = self.results.lock().unwrap().values().cloned().collect();
+        list.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+        list
+    }
+
+    fn insert(&self, result: CompareResult) {
+        let mut results = self.results.lock().unwrap();
+        results.insert(result.response_id.clone(), result);
+        if results.len() > MAX_COMPARISONS {
+            if let Some(oldest) = results.values().min_by_key(|r| r.started_at).map(|r| r.response_id.clone()) {
+                results.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Start every candidate run. Failing to start one candidate doesn't stop
+/// the others.
+pub async fn start_candidates(
+    state: &Arc<AppState>,
+    sink: Option<Arc<dyn EventSink>>,
+    request: &mut CompareRequest,
+) -> Result<CompareStarted, JaibberError> {
+    if request.candidates.is_empty() {
+        return Err(JaibberError::Other("At least one candidate is required".into()));
+    }
+    if request.response_id.is_empty() {
+        request.response_id = uuid::Uuid::new_v4().to_string();
+    }
+
+    let worktrees = if request.candidates.len() > 1 && !request.project_dir.is_empty() {
+        let created = Worktrees::create(&request.project_dir, &request.response_id, request.candidates.len()).await;
+        if created.is_none() {
+            tracing::warn!("[compare] {} is not a git repository; candidates share it", request.project_dir);
+        }
+        created
+    } else {
+        None
+    };
+
+    let started_at = now_ms();
+    let mut started = Vec::with_capacity(request.candidates.len());
+    for (index, candidate) in request.candidates.iter().enumerate() {
+        let response_id = format!("{}-{}", request.response_id, index);
+        let run = RunRequest {
+            prompt: request.prompt.clone(),
+            project_dir: worktrees.as_ref()
+                .map(|w| w.dirs[index].clone())
+                .unwrap_or_else(|| request.project_dir.clone()),
+            response_id: response_id.clone(),
+            system_prompt: request.system_prompt.clone(),
+            conversation_context: request.conversation_context.clone(),
+            agent_provider: Some(candidate.provider.clone()),
+            custom_command: candidate.custom_command.clone(),
+            attachments: request.attachments.clone(),
+            use_api: candidate.use_api,
+            model_override: candidate.model_config.clone(),
+            ..Default::default()
+        };
+        let error = runtime::start_run(state, run, "compare", sink.clone()).await.err();
+        started.push(StartedCandidate {
+            response_id,
+            label: candidate.display_label(),
+            provider: candidate.provider.clone(),
+            error: error.map(|e| e.to_string()),
+        });
+    }
+    Ok(CompareStarted { response_id: request.response_id.clone(), candidates: started, started_at, worktrees })
+}
+
+/// Wait for every started candidate and collect the results.
+pub async fn gather(state: &Arc<AppState>, prompt: &str, started: &CompareStarted) -> CompareResult {
+    let started_at = started.started_at;
+    let candidates = futures_util::future::join_all(
+        started.candidates.iter().map(|c| watch_candidate(state, c, started_at)),
+    ).await;
+    if let Some(worktrees) = &started.worktrees {
+        worktrees.remove().await;
+    }
+    CompareResult {
+        response_id: started.response_id.clone(),
+        prompt: prompt.to_string(),
+        candidates,
+        judgement: None,
+        started_at,
+        finished_at: now_ms(),
+    }
+}
+
+/// Start a comparison and report it with `agent-compare-complete` when done.
+pub async fn run(
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    mut request: CompareRequest,
+) -> Result<CompareStarted, JaibberError> {
+    let started = start_candidates(&state, Some(sink.clone()), &mut request).await?;
+    let handle = started.clone();
+    tokio::spawn(async move {
+        let result = gather(&state, &request.prompt, &handle).await;
+        state.comparisons.insert(result.clone());
+        sink.emit_event("agent-compare-complete", serde_json::to_value(&result).unwrap_or_default());
+    });
+    Ok(started)
+}
+
+// ── Judge mode ────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JudgeMode {
+    /// Pick one candidate's answer verbatim.
+    #[default]
+    Select,
+    /// Write a new answer combining the best parts of the candidates.
+    Merge,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JudgeRequest {
+    #[serde(flatten)]
+    pub compare: CompareRequest,
+    pub judge: CompareCandidate,
+    #[serde(default)]
+    pub mode: JudgeMode,
+    /// Extra judging criteria appended to the judge's instructions.
+    #[serde(default)]
+    pub criteria: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Judgement {
+    pub judge_response_id: String,
+    pub judge_label: String,
+    pub mode: JudgeMode,
+    /// Index into `candidates` of the selected answer (select mode).
+    pub chosen: Option<usize>,
+    pub rationale: String,
+    pub answer: String,
+}
+
+/// Run the candidates without streaming them, have the judge decide, and
+/// emit only the final answer as `agent-chunk` events under the request's
+/// response ID. `agent-judge-complete` follows with the full comparison.
+pub async fn run_judged(
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    mut request: JudgeRequest,
+) -> Result<CompareStarted, JaibberError> {
+    let started = start_candidates(&state, None, &mut request.compare).await?;
+    let handle = started.clone();
+    tokio::spawn(async move {
+        let rid = handle.response_id.clone();
+        let mut result = gather(&state, &request.compare.prompt, &handle).await;
+        let judged = judge(&state, &request, &result).await;
+        result.finished_at = now_ms();
+        match judged {
+            Ok(judgement) => {
+                runtime::emit_chunk(sink.as_ref(), &rid, &judgement.answer, false, None);
+                runtime::emit_chunk(sink.as_ref(), &rid, "", true, None);
+                result.judgement = Some(judgement);
+            }
+            Err(e) => runtime::emit_chunk(sink.as_ref(), &rid, "", false, Some(&e)),
+        }
+        state.comparisons.insert(result.clone());
+        sink.emit_event("agent-judge-complete", serde_json::to_value(&result).unwrap_or_default());
+    });
+    Ok(started)
+}
+
+async fn judge(state: &Arc<AppState>, request: &JudgeRequest, result: &CompareResult) -> Result<Judgement, String> {
+    let answered: Vec<(usize, &CandidateResult)> = result.candidates.iter().enumerate()
+        .filter(|(_, c)| c.status == RunStatus::Completed && !c.output.trim().is_empty())
+        .collect();
+    let judge_label = request.judge.display_label();
+    let judge_response_id = format!("{}-judge", result.response_id);
+
+    match answered.as_slice() {
+        [] => {
+            let errors: Vec<String> = result.candidates.iter()
+                .map(|c| format!("{}: {}", c.label, c.error.as_deref().unwrap_or("no output")))
+                .collect();
+            return Err(format!("Every candidate failed:\n{}", errors.join("\n")));
+        }
+        // Nothing to judge with a single answer
+        [(index, only)] => {
+            return Ok(Judgement {
+                judge_response_id,
+                judge_label,
+                mode: request.mode,
+                chosen: Some(*index),
+                rationale: format!("Only {} produced an answer.", only.label),
+                answer: only.output.clone(),
+            });
+        }
+        _ => {}
+    }
+
+    // The judge only reads the answers in its prompt. It runs in an empty
+    // scratch directory, since CLI providers get full tool access wherever
+    // they start and must not touch the user's checkout.
+    let scratch = std::env::temp_dir().join(format!("jaibber-judge-{}", uuid::Uuid::new_v4().simple()));
+    tokio::fs::create_dir_all(&scratch).await
+        .map_err(|e| format!("Judge ({judge_label}) failed: could not create {}: {e}", scratch.display()))?;
+    let run = RunRequest {
+        prompt: judge_prompt(request, &answered),
+        project_dir: scratch.to_string_lossy().to_string(),
+        response_id: judge_response_id.clone(),
+        agent_provider: Some(request.judge.provider.clone()),
+        custom_command: request.judge.custom_command.clone(),
+        use_api: request.judge.use_api,
+        model_override: request.judge.model_config.clone(),
+        ..Default::default()
+    };
+    let output = runtime::run_to_completion(state, run, "compare").await;
+    if let Err(e) = tokio::fs::remove_dir_all(&scratch).await {
+        tracing::warn!("[compare] Could not remove {}: {e}", scratch.display());
+    }
+    let output = output.map_err(|e| format!("Judge ({judge_label}) failed: {e}"))?;
+
+    let rationale = tag_content(&output, "rationale").unwrap_or_default();
+    let (chosen, answer) = match request.mode {
+        JudgeMode::Select => {
+            let pick = tag_content(&output, "choice")
+                .and_then(|c| c.trim().parse::<usize>().ok())
+                .and_then(|n| answered.iter().find(|(i, _)| *i + 1 == n))
+                .ok_or_else(|| format!("Judge ({judge_label}) did not choose a valid candidate"))?;
+            (Some(pick.0), pick.1.output.clone())
+        }
+        JudgeMode::Merge => {
+            let answer = tag_content(&output, "answer")
+                .ok_or_else(|| format!("Judge ({judge_label}) did not return an answer"))?;
+            (None, answer)
+        }
+    };
+    Ok(Judgement { judge_response_id, judge_label, mode: request.mode, chosen, rationale, answer })
+}
+
+fn judge_prompt(request: &JudgeRequest, answered: &[(usize, &CandidateResult)]) -> String {
+    let mut prompt = String::from(
+        "Several assistants answered the same question. Evaluate their answers for \
+         correctness, completeness and risk.\n\n",
+    );
+    prompt.push_str("<question>\n");
+    prompt.push_str(&request.compare.prompt);
+    prompt.push_str("\n</question>\n\n");
+    for (index, candidate) in answered {
+        prompt.push_str(&format!("<candidate number=\"{}\">\n{}\n</candidate>\n\n", index + 1, candidate.output.trim()));
+    }
+    if let Some(criteria) = request.criteria.as_deref().filter(|c| !c.trim().is_empty()) {
+        prompt.push_str(&format!("Additional criteria: {criteria}\n\n"));
+    }
+    match request.mode {
+        JudgeMode::Select => prompt.push_str(
+            "Choose the single best candidate. Reply with <rationale>why it is best \
+             and what the others got wrong</rationale> followed by <choice>N</choice>, \
+             where N is the candidate number.",
+        ),
+        JudgeMode::Merge => prompt.push_str(
+            "Write the best possible answer, combining the strengths of the candidates \
+             and fixing their mistakes. Reply with <rationale>what you kept, dropped or \
+             corrected</rationale> followed by <answer>the final answer</answer>.",
+        ),
+    }
+    prompt
+}
+
+/// Text between `<tag>` and `</tag>` (the last occurrence, since models
+/// sometimes echo the instructions first).
+fn tag_content(text: &str, tag: &str) -> Option<String> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = text.rfind(&open)? + open.len();
+    let end = start + text[start..].find(&close)?;
+    Some(text[start..end].trim().to_string())
+}
+
+async fn watch_candidate(state: &Arc<AppState>, candidate: &StartedCandidate, started_at: u64) -> CandidateResult {
+    let mut result = CandidateResult {
+        response_id: candidate.response_id.clone(),
+        label: candidate.label.clone(),
+        provider: candidate.provider.clone(),
+        status: RunStatus::Failed,
+        error: candidate.error.clone(),
+        output: String::new(),
+        output_chars: 0,
+        first_token_ms: None,
+        latency_ms: 0,
+        usage: None,
+    };
+    if candidate.error.is_some() {
+        return result;
+    }
+    let Some(mut events) = state.runs.events(&candidate.response_id) else {
+        result.error = Some("Run is no longer tracked".into());
+        return result;
+    };
+
+    while let Some(event) = events.next().await {
+        if let Some(error) = event.missed_error() {
+            result.error = Some(error);
+            break;
+        }
+        match event.event.as_str() {
+            "agent-usage" => {
+                result.usage = serde_json::from_value(event.payload).ok();
+            }
+            "agent-chunk" => {
+                if let Some(chunk) = event.payload.get("chunk").and_then(|c| c.as_str()) {
+                    if !chunk.is_empty() && result.first_token_ms.is_none() {
+                        result.first_token_ms = Some(now_ms().saturating_sub(started_at));
+                    }
+                    result.output.push_str(chunk);
+                }
+                if let Some(error) = event.payload.get("error").and_then(|e| e.as_str()) {
+                    result.error = Some(error.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    result.latency_ms = now_ms().saturating_sub(started_at);
+    result.output_chars = result.output.chars().count();
+    result.status = state.runs.get(&candidate.response_id)
+        .map(|info| info.status)
+        .unwrap_or(if result.error.is_some() { RunStatus::Failed } else { RunStatus::Completed });
+    result
+}
+
+// ── Worktrees ─────────────────────────────────────────────────────────
+
+/// One detached git worktree per candidate.
+#[derive(Debug, Clone)]
+struct Worktrees {
+    /// Top level of the repository they belong to.
+    repo: String,
+    paths: Vec<PathBuf>,
+    /// Each candidate's working directory (the project's subdirectory
+    /// inside its worktree).
+    dirs: Vec<String>,
+}
+
+impl Worktrees {
+    /// Create `count` worktrees of the repository containing `project_dir`.
+    /// None when it isn't a git repository or a worktree can't be created.
+    async fn create(project_dir: &str, response_id: &str, count: usize) -> Option<Self> {
+        let repo = git(project_dir, &["rev-parse", "--show-toplevel"]).await?;
+        let subdir = Path::new(project_dir).canonicalize().ok()?
+            .strip_prefix(Path::new(&repo).canonicalize().ok()?)
+            .ok()?
+            .to_path_buf();
+        // `stash create` snapshots uncommitted changes without touching the
+        // checkout; it prints nothing when the tree is clean.
+        let snapshot = git(&repo, &["stash", "create"]).await.filter(|rev| !rev.is_empty());
+        let rev = snapshot.as_deref().unwrap_or("HEAD");
+
+        let root = std::env::temp_dir().join("jaibber-compare").join(response_id);
+        let mut worktrees = Self { repo, paths: Vec::new(), dirs: Vec::new() };
+        for index in 0..count {
+            let path = root.join(index.to_string());
+            let target = path.to_string_lossy().to_string();
+            if git(&worktrees.repo, &["worktree", "add", "--detach", &target, rev]).await.is_none() {
+                tracing::warn!("[compare] Failed to create worktree {target}");
+                worktrees.remove().await;
+                return None;
+            }
+            worktrees.dirs.push(path.join(&subdir).to_string_lossy().to_string());
+            worktrees.paths.push(path);
+        }
+        Some(worktrees)
+    }
+
+    async fn remove(&self) {
+        for path in &self.paths {
+            let target = path.to_string_lossy();
+            if git(&self.repo, &["worktree", "remove", "--force", &target]).await.is_none() {
+                tracing::warn!("[compare] Failed to remove worktree {target}");
+            }
+        }
+        git(&self.repo, &["worktree", "prune"]).await;
+        if let Some(root) = self.paths.first().and_then(|p| p.parent()) {
+            let _ = tokio::fs::remove_dir_all(root).await;
+        }
+    }
+}
+
+/// Run git in `dir`; trimmed stdout on success.
+async fn git(dir: &str, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candidates_can_differ_only_by_model() {
+        let request: CompareRequest = serde_json::from_value(serde_json::json!({
+            "prompt": "Explain the bug",
+            "candidates": [
+                { "provider": "claude", "modelConfig": { "model": "claude-opus-4-1" } },
+                { "provider": "claude", "modelConfig": { "model": "claude-sonnet-4-5", "temperature": 0.2 } },
+                { "provider": "codex", "label": "Codex" },
+            ],
+        })).unwrap();
+        let labels: Vec<String> = request.candidates.iter().map(|c| c.display_label()).collect();
+        assert_eq!(labels, ["claude (claude-opus-4-1)", "claude (claude-sonnet-4-5)", "Codex"]);
+        assert_eq!(request.candidates[1].model_config.temperature, Some(0.2));
+        assert_eq!(request.candidates[2].model_config, ModelConfig::default());
+    }
+}
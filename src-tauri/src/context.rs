@@ -0,0 +1,248 @@
+//! Conversation context for agent runs: structured chat history rendered
+//! into the prompt, trimmed to a token budget.
+//!
+//! The newest messages are kept and older ones dropped once the budget is
+//! reached, so a long thread can never push the actual question out of the
+//! model's window. Token counts are estimates (characters per token differ
+//! by model family) — close enough for budgeting, not for billing.
+
+use serde::{Deserialize, Serialize};
+use crate::state::AttachmentInfo;
+
+/// Introduces flattened history in front of the prompt.
+const HISTORY_INTRO: &str =
+    "Below is the recent conversation history for context. \
+     Respond ONLY to the final user message.";
+
+/// How agents should reply in chat, whether history is flattened or sent
+/// as separate turns.
+pub const CHAT_STYLE: &str =
+    "Be conversational and concise — reply directly to the user as a chat participant. \
+     Do NOT narrate your thought process, planning steps, or internal reasoning. \
+     Do NOT describe actions you would take (e.g. \"I should...\", \"Let me...\", \"I will...\"). \
+     Just answer.";
+
+/// Fixed per-message overhead (role markers, separators).
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// One message of chat history, as sent by the frontend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextMessage {
+    #[serde(default)]
+    pub author: String,
+    pub role: Role,
+    pub text: String,
+    /// ISO 8601, as stored on chat messages.
+    #[serde(default)]
+    pub timestamp: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentInfo>,
+}
+
+/// Rough characters-per-token ratio for a provider/model family.
+fn chars_per_token(provider: &str, model: Option<&str>) -> f64 {
+    let family = model.unwrap_or(provider).to_lowercase();
+    if family.contains("claude") || family.contains("sonnet") || family.contains("opus") || family.contains("haiku") {
+        3.5
+    } else if family.contains("gemini") || family.contains("gpt") || family.contains("codex") {
+        4.0
+    } else {
+        // Unknown models: assume the denser tokenizer so we err on the safe side
+        3.5
+    }
+}
+
+/// Estimate the token count of `text` for the given provider/model.
+pub fn estimate_tokens(text: &str, provider: &str, model: Option<&str>) -> usize {
+    (text.chars().count() as f64 / chars_per_token(provider, model)).ceil() as usize
+}
+
+fn render_message(message: &ContextMessage) -> String {
+    let speaker = match (message.role, message.author.is_empty()) {
+        (Role::User, true) => "User".to_string(),
+        (Role::User, false) => format!("User ({})", message.author),
+        (Role::Assistant, true) => "Assistant".to_string(),
+        (Role::Assistant, false) => format!("Assistant ({})", message.author),
+    };
+    let mut line = match message.timestamp.as_deref() {
+        Some(ts) if !ts.is_empty() => format!("[{ts}] {speaker}: {}", message.text),
+        _ => format!("{speaker}: {}", message.text),
+    };
+    if !message.attachments.is_empty() {
+        let names: Vec<&str> = message.attachments.iter().map(|a| a.filename.as_str()).collect();
+        line.push_str(&format!("\n[Attachments: {}]", names.join(", ")));
+    }
+    line
+}
+
+/// The newest messages that fit in `budget` tokens, oldest first, plus how
+/// many older messages were dropped.
+pub fn select_within_budget<'a>(
+    messages: &'a [ContextMessage],
+    budget: usize,
+    provider: &str,
+    model: Option<&str>,
+) -> (&'a [ContextMessage], usize) {
+    let mut used = 0;
+    let mut keep = 0;
+    for message in messages.iter().rev() {
+        let cost = estimate_tokens(&render_message(message), provider, model) + MESSAGE_OVERHEAD_TOKENS;
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        keep += 1;
+    }
+    let dropped = messages.len() - keep;
+    (&messages[dropped..], dropped)
+}
+
+/// Stand-in for the messages dropped by the budget: the rolling summary
+/// when available, otherwise a note that they were omitted.
+pub fn history_note(dropped: usize, summary: Option<&str>) -> Option<String> {
+    match summary {
+        _ if dropped == 0 => None,
+        Some(summary) => Some(format!("Summary of the {dropped} earlier messages:\n{summary}")),
+        None => Some(format!("({dropped} earlier messages omitted)")),
+    }
+}
+
+/// Render history into the flat context string used by CLI providers.
+pub fn render_history(messages: &[ContextMessage], note: Option<&str>) -> String {
+    let mut parts: Vec<String> = Vec::with_capacity(messages.len() + 1);
+    parts.extend(note.map(|n| n.to_string()));
+    parts.extend(messages.iter().map(render_message));
+    parts.join("\n\n")
+}
+
+/// Trim an already rendered context string to `budget` tokens by dropping
+/// whole paragraphs from the front.
+pub fn trim_to_budget(context: &str, budget: usize, provider: &str, model: Option<&str>) -> String {
+    if estimate_tokens(context, provider, model) <= budget {
+        return context.to_string();
+    }
+    let paragraphs: Vec<&str> = context.split("\n\n").collect();
+    let mut used = 0;
+    let mut start = paragraphs.len();
+    for (i, paragraph) in paragraphs.iter().enumerate().rev() {
+        let cost = estimate_tokens(paragraph, provider, model) + 1;
+        if used + cost > budget {
+            break;
+        }
+        used += cost;
+        start = i;
+    }
+    let mut out = String::from("(earlier history omitted)");
+    for paragraph in &paragraphs[start..] {
+        out.push_str("\n\n");
+        out.push_str(paragraph);
+    }
+    out
+}
+
+/// The user prompt with the flattened history (if any) and its preamble in front.
+pub fn with_context(conversation_context: &str, prompt: &str) -> String {
+    if conversation_context.is_empty() {
+        return prompt.to_string();
+    }
+    format!("{HISTORY_INTRO} {CHAT_STYLE}\n\n{conversation_context}\n\n---\n\n{prompt}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A user message that renders to 35 characters (10 Claude tokens).
+    fn message(n: usize) -> ContextMessage {
+        ContextMessage {
+            author: String::new(),
+            role: Role::User,
+            text: format!("message {n:0>21}"),
+            timestamp: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn estimates_by_model_family() {
+        let text = "x".repeat(700);
+        assert_eq!(estimate_tokens(&text, "claude", None), 200);
+        assert_eq!(estimate_tokens(&text, "codex", Some("gpt-5")), 175);
+        // The model wins over the provider; unknown families use the denser ratio
+        assert_eq!(estimate_tokens(&text, "openclaw", Some("gemini-2.5-pro")), 175);
+        assert_eq!(estimate_tokens(&text, "custom", None), 200);
+        assert_eq!(estimate_tokens("", "claude", None), 0);
+    }
+
+    #[test]
+    fn keeps_the_newest_messages_within_budget() {
+        let messages: Vec<ContextMessage> = (0..3).map(message).collect();
+        let cost = 10 + MESSAGE_OVERHEAD_TOKENS;
+
+        let (kept, dropped) = select_within_budget(&messages, 2 * cost, "claude", None);
+        assert_eq!(dropped, 1);
+        assert_eq!(kept[0].text, messages[1].text);
+        assert_eq!(kept[1].text, messages[2].text);
+
+        let (kept, dropped) = select_within_budget(&messages, 2 * cost - 1, "claude", None);
+        assert_eq!((kept.len(), dropped), (1, 2));
+
+        let (kept, dropped) = select_within_budget(&messages, 100, "claude", None);
+        assert_eq!((kept.len(), dropped), (3, 0));
+
+        let (kept, dropped) = select_within_budget(&messages, 0, "claude", None);
+        assert_eq!((kept.len(), dropped), (0, 3));
+    }
+
+    #[test]
+    fn notes_dropped_messages() {
+        assert_eq!(history_note(0, Some("ignored")), None);
+        assert_eq!(history_note(3, None).as_deref(), Some("(3 earlier messages omitted)"));
+        assert_eq!(
+            history_note(3, Some("They agreed on Rust.")).as_deref(),
+            Some("Summary of the 3 earlier messages:\nThey agreed on Rust."),
+        );
+    }
+
+    #[test]
+    fn renders_speakers_timestamps_and_attachments() {
+        let mut reply = message(1);
+        reply.role = Role::Assistant;
+        reply.author = "Coder".into();
+        reply.timestamp = Some("2026-01-01T09:00:00Z".into());
+        reply.text = "Done".into();
+        reply.attachments = serde_json::from_value(serde_json::json!([
+            { "id": "a1", "filename": "diff.patch", "mimeType": "text/x-diff", "fileSize": 10 },
+        ])).unwrap();
+        let rendered = render_history(&[message(0), reply], Some("(1 earlier messages omitted)"));
+        assert_eq!(rendered, "(1 earlier messages omitted)\n\nUser: message 000000000000000000000\n\n\
+                              [2026-01-01T09:00:00Z] Assistant (Coder): Done\n[Attachments: diff.patch]");
+    }
+
+    #[test]
+    fn trims_whole_paragraphs_from_the_front() {
+        let context = ["a".repeat(35), "b".repeat(35), "c".repeat(35)].join("\n\n");
+        assert_eq!(trim_to_budget(&context, 1000, "claude", None), context);
+
+        // Each paragraph costs 10 tokens plus one for the separator
+        let trimmed = trim_to_budget(&context, 22, "claude", None);
+        assert_eq!(trimmed, format!("(earlier history omitted)\n\n{}\n\n{}", "b".repeat(35), "c".repeat(35)));
+        assert_eq!(trim_to_budget(&context, 5, "claude", None), "(earlier history omitted)");
+    }
+
+    #[test]
+    fn puts_history_before_the_prompt() {
+        assert_eq!(with_context("", "Hi"), "Hi");
+        let prompt = with_context("User: earlier", "Hi");
+        assert!(prompt.starts_with(HISTORY_INTRO));
+        assert!(prompt.ends_with("User: earlier\n\n---\n\nHi"));
+    }
+}
@@ -17,6 +17,15 @@ pub enum JaibberError {
     #[error("No Anthropic API key configured")]
     NoApiKey,
 
+    #[error("Not signed in to Jaibber")]
+    NotSignedIn,
+
+    #[error("Jaibber API error ({status}): {message}")]
+    Api { status: u16, message: String },
+
+    #[error("Network error: {0}")]
+    Http(String),
+
     #[error("{0}")]
     Other(String),
 }
@@ -0,0 +1,649 @@
+//! Text extraction for attachments no provider reads natively: Word,
+//! Excel/CSV, PowerPoint, Jupyter notebooks, HTML and zip/tar archives.
+//!
+//! The result is markdown meant for the prompt — spreadsheets become a
+//! column schema plus sample rows, notebooks keep cell outputs, archives
+//! are listed with their small text members inlined.
+
+use std::io::{Cursor, Read};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+/// Rows of each sheet or CSV shown as a sample.
+const SAMPLE_ROWS: usize = 20;
+/// Rows scanned to guess column types.
+const SCHEMA_SCAN_ROWS: usize = 1_000;
+const MAX_SHEETS: usize = 10;
+const MAX_CELL_CHARS: usize = 100;
+/// Characters of each notebook cell output.
+const MAX_OUTPUT_CHARS: usize = 2_000;
+const MAX_ARCHIVE_ENTRIES: usize = 200;
+/// Archive members up to this size are inlined when they're text.
+const MAX_MEMBER_BYTES: u64 = 32 * 1024;
+/// Largest uncompressed XML part read from an Office document. Anything
+/// bigger is refused rather than inflated (zip bombs).
+const MAX_PART_BYTES: u64 = 20 * 1024 * 1024;
+/// Decompressed bytes walked when listing a .tar.gz.
+const MAX_TAR_GZ_BYTES: u64 = 1024 * 1024 * 1024;
+const MAX_INLINED_MEMBERS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Docx,
+    Spreadsheet,
+    Csv,
+    Tsv,
+    Pptx,
+    Notebook,
+    Html,
+    Zip,
+    Tar,
+    TarGz,
+}
+
+fn format_of(filename: &str, mime: &str) -> Option<Format> {
+    let name = filename.to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        return Some(Format::TarGz);
+    }
+    let ext = name.rsplit('.').next().unwrap_or("");
+    let format = match (ext, mime) {
+        ("docx", _) | (_, "application/vnd.openxmlformats-officedocument.wordprocessingml.document") => Format::Docx,
+        ("xlsx" | "xlsm" | "xlsb" | "xls" | "ods", _)
+        | (_, "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" | "application/vnd.ms-excel") => Format::Spreadsheet,
+        ("csv", _) | (_, "text/csv") => Format::Csv,
+        ("tsv", _) | (_, "text/tab-separated-values") => Format::Tsv,
+        ("pptx", _) | (_, "application/vnd.openxmlformats-officedocument.presentationml.presentation") => Format::Pptx,
+        ("ipynb", _) | (_, "application/x-ipynb+json") => Format::Notebook,
+        ("html" | "htm", _) | (_, "text/html") => Format::Html,
+        ("zip", _) | (_, "application/zip" | "application/x-zip-compressed") => Format::Zip,
+        ("tar", _) | (_, "application/x-tar") => Format::Tar,
+        (_, "application/gzip" | "application/x-gzip") if name.contains(".tar") => Format::TarGz,
+        _ => return None,
+    };
+    Some(format)
+}
+
+/// Whether `extract` knows this kind of file.
+pub fn is_extractable(filename: &str, mime: &str) -> bool {
+    format_of(filename, mime).is_some()
+}
+
+/// Extract the file's contents as markdown. CPU-bound; call it from a
+/// blocking task.
+pub fn extract(filename: &str, mime: &str, bytes: &[u8]) -> Result<String, String> {
+    let Some(format) = format_of(filename, mime) else {
+        return Err(format!("No extractor for {filename}"));
+    };
+    let text = match format {
+        Format::Docx => docx(bytes),
+        Format::Spreadsheet => spreadsheet(bytes),
+        Format::Csv => delimited(bytes, b','),
+        Format::Tsv => delimited(bytes, b'\t'),
+        Format::Pptx => pptx(bytes),
+        Format::Notebook => notebook(bytes),
+        Format::Html => Ok(html(&String::from_utf8_lossy(bytes))),
+        Format::Zip => zip_listing(bytes),
+        Format::Tar => tar_listing(bytes),
+        Format::TarGz => tar_listing(flate2::read::GzDecoder::new(bytes).take(MAX_TAR_GZ_BYTES)),
+    };
+    text.map(|t| t.trim().to_string())
+        .map_err(|e| format!("Could not extract {filename}: {e}"))
+}
+
+/// HTML as markdown, without scripts and styles.
+fn html(source: &str) -> String {
+    static NOISE: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+        regex::Regex::new(r"(?is)<script\b.*?</script>|<style\b.*?</style>|<!--.*?-->").unwrap()
+    });
+    html2md::parse_html(&NOISE.replace_all(source, ""))
+}
+
+// ── Office documents ──────────────────────────────────────────────────
+
+fn open_zip(bytes: &[u8]) -> Result<zip::ZipArchive<Cursor<&[u8]>>, String> {
+    zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| e.to_string())
+}
+
+fn read_member(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<String, String> {
+    let file = archive.by_name(name).map_err(|e| format!("{name}: {e}"))?;
+    let mut xml = String::new();
+    file.take(MAX_PART_BYTES + 1).read_to_string(&mut xml).map_err(|e| format!("{name}: {e}"))?;
+    if xml.len() as u64 > MAX_PART_BYTES {
+        return Err(format!("{name} is larger than {} MB uncompressed", MAX_PART_BYTES / (1024 * 1024)));
+    }
+    Ok(xml)
+}
+
+/// Refuse zip-based documents whose parts would inflate past
+/// `MAX_PART_BYTES`, before handing them to a parser that reads them whole.
+fn check_part_sizes(bytes: &[u8]) -> Result<(), String> {
+    let mut archive = open_zip(bytes)?;
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|e| e.to_string())?;
+        if file.size() > MAX_PART_BYTES {
+            return Err(format!("{} is larger than {} MB uncompressed", file.name(), MAX_PART_BYTES / (1024 * 1024)));
+        }
+    }
+    Ok(())
+}
+
+fn docx(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = open_zip(bytes)?;
+    ooxml_text(&read_member(&mut archive, "word/document.xml")?)
+}
+
+fn pptx(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = open_zip(bytes)?;
+    let slide_number = |name: &str, prefix: &str| -> Option<u32> {
+        name.strip_prefix(prefix)?.strip_suffix(".xml")?.parse().ok()
+    };
+    let mut slides: Vec<u32> = archive.file_names()
+        .filter_map(|name| slide_number(name, "ppt/slides/slide"))
+        .collect();
+    slides.sort_unstable();
+
+    let mut out = String::new();
+    for n in slides {
+        let text = ooxml_text(&read_member(&mut archive, &format!("ppt/slides/slide{n}.xml"))?)?;
+        out.push_str(&format!("## Slide {n}\n\n{}\n\n", text.trim()));
+        if let Ok(notes) = read_member(&mut archive, &format!("ppt/notesSlides/notesSlide{n}.xml")) {
+            let notes = ooxml_text(&notes)?;
+            if !notes.is_empty() {
+                out.push_str(&format!("Speaker notes: {}\n\n", notes.trim()));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Text of a WordprocessingML or DrawingML part: the `t` runs, one line per
+/// paragraph, table cells separated by ` | `, headings marked with `#`.
+fn ooxml_text(xml: &str) -> Result<String, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut out = String::new();
+    let mut in_text = false;
+    let mut paragraph_start = 0;
+
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(e) => match e.local_name().as_ref() {
+                b"t" => in_text = true,
+                b"p" => paragraph_start = out.len(),
+                _ => {}
+            },
+            Event::Empty(e) => match e.local_name().as_ref() {
+                b"tab" => out.push('\t'),
+                b"br" => out.push('\n'),
+                b"pStyle" => {
+                    let style = e.attributes().flatten()
+                        .find(|a| a.key.local_name().as_ref() == b"val")
+                        .and_then(|a| a.unescape_value().ok().map(|v| v.into_owned()))
+                        .unwrap_or_default();
+                    let level = match style.as_str() {
+                        "Title" => Some(1),
+                        s => s.strip_prefix("Heading").and_then(|n| n.parse::<usize>().ok()),
+                    };
+                    if let Some(level) = level.filter(|l| (1..=6).contains(l)) {
+                        out.insert_str(paragraph_start, &format!("{} ", "#".repeat(level)));
+                    }
+                }
+                _ => {}
+            },
+            Event::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"p" => out.push('\n'),
+                b"tc" => {
+                    // Cell paragraphs end in a newline; keep the row on one line
+                    if out.ends_with('\n') {
+                        out.pop();
+                    }
+                    out.push_str(" | ");
+                }
+                b"tr" => {
+                    let row_end = out.trim_end_matches(" | ").len();
+                    out.truncate(row_end);
+                    out.push('\n');
+                }
+                _ => {}
+            },
+            Event::Text(t) if in_text => out.push_str(&t.decode().map_err(|e| e.to_string())?),
+            Event::GeneralRef(r) if in_text => {
+                if let Ok(Some(c)) = r.resolve_char_ref() {
+                    out.push(c);
+                } else {
+                    out.push_str(match &*r {
+                        b"amp" => "&",
+                        b"lt" => "<",
+                        b"gt" => ">",
+                        b"quot" => "\"",
+                        b"apos" => "'",
+                        _ => "",
+                    });
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+// ── Tables ────────────────────────────────────────────────────────────
+
+/// A cell's text and the kind of value it holds ("" when empty).
+struct Cell {
+    text: String,
+    kind: &'static str,
+}
+
+impl Cell {
+    fn guess(text: String) -> Self {
+        let kind = if text.trim().is_empty() {
+            ""
+        } else if text.trim().parse::<f64>().is_ok() {
+            "number"
+        } else if matches!(text.trim().to_lowercase().as_str(), "true" | "false") {
+            "boolean"
+        } else {
+            "text"
+        };
+        Self { text, kind }
+    }
+}
+
+fn spreadsheet(bytes: &[u8]) -> Result<String, String> {
+    use calamine::{Data, Reader as _};
+    // xlsx/xlsm/xlsb/ods are zip containers; legacy .xls is not
+    if bytes.starts_with(b"PK") {
+        check_part_sizes(bytes)?;
+    }
+    let mut workbook = calamine::open_workbook_auto_from_rs(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let names = workbook.sheet_names();
+    let mut out = String::new();
+    for name in names.iter().take(MAX_SHEETS) {
+        let range = workbook.worksheet_range(name).map_err(|e| e.to_string())?;
+        let rows: Vec<Vec<Cell>> = range.rows().map(|row| row.iter().map(|cell| {
+            let kind = match cell {
+                Data::Int(_) | Data::Float(_) => "number",
+                Data::Bool(_) => "boolean",
+                Data::DateTime(_) | Data::DateTimeIso(_) => "date",
+                Data::DurationIso(_) => "duration",
+                Data::String(s) => return Cell::guess(s.clone()),
+                Data::Error(_) => "error",
+                Data::Empty => "",
+            };
+            Cell { text: cell.to_string(), kind }
+        }).collect()).collect();
+        out.push_str(&table_summary(&format!("Sheet: {name}"), rows));
+    }
+    if names.len() > MAX_SHEETS {
+        out.push_str(&format!("({} more sheets not shown)\n", names.len() - MAX_SHEETS));
+    }
+    Ok(out)
+}
+
+fn delimited(bytes: &[u8], delimiter: u8) -> Result<String, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(bytes);
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        rows.push(record.iter().map(|field| Cell::guess(field.to_string())).collect());
+    }
+    Ok(table_summary("Table", rows))
+}
+
+/// Row count, a schema guessed from the first row (as headers) and the
+/// values below it, and a markdown sample of the first rows.
+fn table_summary(title: &str, rows: Vec<Vec<Cell>>) -> String {
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut out = format!("## {title} ({} rows × {columns} columns)\n\n", rows.len().saturating_sub(1));
+    if rows.is_empty() || columns == 0 {
+        out.push_str("(empty)\n\n");
+        return out;
+    }
+
+    let header: Vec<String> = (0..columns)
+        .map(|i| rows[0].get(i).map(|c| cell_text(&c.text)).filter(|t| !t.is_empty()).unwrap_or_else(|| format!("column {}", i + 1)))
+        .collect();
+    out.push_str("Columns:\n");
+    for (i, name) in header.iter().enumerate() {
+        let mut kinds: Vec<&str> = rows.iter().skip(1).take(SCHEMA_SCAN_ROWS)
+            .filter_map(|r| r.get(i).map(|c| c.kind).filter(|k| !k.is_empty()))
+            .collect();
+        kinds.sort_unstable();
+        kinds.dedup();
+        let kind = match kinds.as_slice() {
+            [] => "empty",
+            [kind] => kind,
+            _ => "mixed",
+        };
+        out.push_str(&format!("- {name} ({kind})\n"));
+    }
+
+    out.push_str(&format!("\n| {} |\n|{}\n", header.join(" | "), " --- |".repeat(columns)));
+    for row in rows.iter().skip(1).take(SAMPLE_ROWS) {
+        let cells: Vec<String> = (0..columns).map(|i| row.get(i).map(|c| cell_text(&c.text)).unwrap_or_default()).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    if rows.len() > SAMPLE_ROWS + 1 {
+        out.push_str(&format!("\n({} more rows not shown)\n", rows.len() - SAMPLE_ROWS - 1));
+    }
+    out.push('\n');
+    out
+}
+
+fn cell_text(text: &str) -> String {
+    let text = text.trim().replace('|', "\\|").replace(['\n', '\r'], " ");
+    truncate(&text, MAX_CELL_CHARS)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+// ── Notebooks ─────────────────────────────────────────────────────────
+
+/// Notebook sources and outputs are strings or arrays of lines.
+fn joined(value: Option<&serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::Array(lines)) => lines.iter().filter_map(|l| l.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+fn notebook(bytes: &[u8]) -> Result<String, String> {
+    let nb: serde_json::Value = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let language = nb.pointer("/metadata/language_info/name")
+        .or_else(|| nb.pointer("/metadata/kernelspec/language"))
+        .and_then(|l| l.as_str())
+        .unwrap_or("python");
+    let cells = nb.get("cells").and_then(|c| c.as_array()).cloned().unwrap_or_default();
+
+    let mut out = String::new();
+    for (i, cell) in cells.iter().enumerate() {
+        let source = joined(cell.get("source"));
+        match cell.get("cell_type").and_then(|t| t.as_str()) {
+            Some("code") => {
+                out.push_str(&format!("### In [{}]\n```{language}\n{}\n```\n", i + 1, source.trim_end()));
+                for output in cell.get("outputs").and_then(|o| o.as_array()).into_iter().flatten() {
+                    let text = match output.get("output_type").and_then(|t| t.as_str()) {
+                        Some("stream") => joined(output.get("text")),
+                        Some("execute_result" | "display_data") => {
+                            let data = output.get("data");
+                            let plain = joined(data.and_then(|d| d.get("text/plain")));
+                            let image = data.and_then(|d| d.as_object())
+                                .and_then(|d| d.keys().find(|k| k.starts_with("image/")).cloned());
+                            match image {
+                                Some(mime) if plain.is_empty() || plain.starts_with('<') => format!("[{mime} output]"),
+                                _ => plain,
+                            }
+                        }
+                        Some("error") => format!(
+                            "{}: {}",
+                            output.get("ename").and_then(|v| v.as_str()).unwrap_or("Error"),
+                            output.get("evalue").and_then(|v| v.as_str()).unwrap_or(""),
+                        ),
+                        _ => continue,
+                    };
+                    if !text.trim().is_empty() {
+                        out.push_str(&format!("Output:\n```\n{}\n```\n", truncate(text.trim_end(), MAX_OUTPUT_CHARS)));
+                    }
+                }
+                out.push('\n');
+            }
+            Some("markdown") => out.push_str(&format!("{}\n\n", source.trim_end())),
+            _ => out.push_str(&format!("```\n{}\n```\n\n", source.trim_end())),
+        }
+    }
+    Ok(out)
+}
+
+// ── Archives ──────────────────────────────────────────────────────────
+
+/// Collects an archive listing and its small text members.
+#[derive(Default)]
+struct Listing {
+    entries: Vec<String>,
+    members: Vec<String>,
+    total: usize,
+}
+
+impl Listing {
+    fn add(&mut self, path: &str, size: u64, is_dir: bool, content: impl Read) {
+        self.total += 1;
+        if self.entries.len() < MAX_ARCHIVE_ENTRIES {
+            self.entries.push(if is_dir { format!("- {path}/") } else { format!("- {path} ({size} bytes)") });
+        }
+        if is_dir
+            || size > MAX_MEMBER_BYTES
+            || self.members.len() >= MAX_INLINED_MEMBERS
+            || !crate::attachments::is_text_filename(path)
+        {
+            return;
+        }
+        let mut bytes = Vec::new();
+        if content.take(MAX_MEMBER_BYTES).read_to_end(&mut bytes).is_ok() {
+            if let Ok(text) = String::from_utf8(bytes) {
+                self.members.push(format!("### {path}\n```\n{}\n```\n", text.trim_end()));
+            }
+        }
+    }
+
+    fn render(self) -> String {
+        let mut out = format!("## Archive ({} entries)\n\n{}\n", self.total, self.entries.join("\n"));
+        if self.total > self.entries.len() {
+            out.push_str(&format!("({} more entries not shown)\n", self.total - self.entries.len()));
+        }
+        if !self.members.is_empty() {
+            out.push('\n');
+            out.push_str(&self.members.join("\n"));
+        }
+        out
+    }
+}
+
+fn zip_listing(bytes: &[u8]) -> Result<String, String> {
+    let mut archive = open_zip(bytes)?;
+    let mut listing = Listing::default();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i).map_err(|e| e.to_string())?;
+        let path = file.name().trim_end_matches('/').to_string();
+        listing.add(&path, file.size(), file.is_dir(), file);
+    }
+    Ok(listing.render())
+}
+
+fn tar_listing(reader: impl Read) -> Result<String, String> {
+    let mut archive = tar::Archive::new(reader);
+    let mut listing = Listing::default();
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        // A truncated walk (e.g. past `MAX_TAR_GZ_BYTES`) still lists what it saw
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(_) if listing.total > 0 => {
+                listing.entries.push("… (listing stopped early)".to_string());
+                break;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let path = entry.path().map_err(|e| e.to_string())?.display().to_string();
+        let size = entry.header().size().unwrap_or(0);
+        let is_dir = entry.header().entry_type().is_dir();
+        listing.add(&path, size, is_dir, entry);
+    }
+    Ok(listing.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use serde_json::json;
+
+    /// An in-memory zip with the given members.
+    fn zip_of(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in members {
+            if name.ends_with('/') {
+                writer.add_directory(*name, options).unwrap();
+            } else {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(content).unwrap();
+            }
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    #[test]
+    fn picks_formats_by_extension_or_mime() {
+        assert_eq!(format_of("Report.DOCX", ""), Some(Format::Docx));
+        assert_eq!(format_of("data", "text/csv"), Some(Format::Csv));
+        assert_eq!(format_of("logs.tar.gz", "application/gzip"), Some(Format::TarGz));
+        assert_eq!(format_of("analysis.ipynb", ""), Some(Format::Notebook));
+        assert!(!is_extractable("photo.png", "image/png"));
+        assert!(extract("photo.png", "image/png", b"").is_err());
+    }
+
+    #[test]
+    fn extracts_docx_headings_paragraphs_and_tables() {
+        let document = format!(
+            r#"<w:document {W}><w:body>
+                <w:p><w:pPr><w:pStyle w:val="Heading2"/></w:pPr><w:r><w:t>Plan</w:t></w:r></w:p>
+                <w:p><w:r><w:t xml:space="preserve">Ship </w:t></w:r><w:r><w:t>it &amp; rest</w:t></w:r></w:p>
+                <w:tbl>
+                    <w:tr><w:tc><w:p><w:r><w:t>Owner</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Due</w:t></w:r></w:p></w:tc></w:tr>
+                    <w:tr><w:tc><w:p><w:r><w:t>Ana</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Friday</w:t></w:r></w:p></w:tc></w:tr>
+                </w:tbl>
+            </w:body></w:document>"#
+        );
+        let bytes = zip_of(&[("word/document.xml", document.as_bytes())]);
+        let text = extract("plan.docx", "", &bytes).unwrap();
+        assert_eq!(text, "## Plan\nShip it & rest\nOwner | Due\nAna | Friday");
+    }
+
+    #[test]
+    fn extracts_xlsx_schema_and_sample() {
+        let cell = |r: &str, v: &str| if v.parse::<f64>().is_ok() {
+            format!(r#"<c r="{r}"><v>{v}</v></c>"#)
+        } else {
+            format!(r#"<c r="{r}" t="inlineStr"><is><t>{v}</t></is></c>"#)
+        };
+        let sheet = format!(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>
+                <row r="1">{}{}</row><row r="2">{}{}</row><row r="3">{}{}</row>
+            </sheetData></worksheet>"#,
+            cell("A1", "item"), cell("B1", "qty"),
+            cell("A2", "apples"), cell("B2", "3"),
+            cell("A3", "pears"), cell("B3", "n/a"),
+        );
+        let bytes = zip_of(&[
+            ("[Content_Types].xml", br#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+                <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+                <Default Extension="xml" ContentType="application/xml"/>
+                <Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
+                <Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>
+            </Types>"#),
+            ("_rels/.rels", br#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>
+            </Relationships>"#),
+            ("xl/workbook.xml", br#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+                <sheets><sheet name="Stock" sheetId="1" r:id="rId1"/></sheets>
+            </workbook>"#),
+            ("xl/_rels/workbook.xml.rels", br#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                <Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>
+            </Relationships>"#),
+            ("xl/worksheets/sheet1.xml", sheet.as_bytes()),
+        ]);
+        let text = extract("stock.xlsx", "", &bytes).unwrap();
+        assert!(text.starts_with("## Sheet: Stock (2 rows × 2 columns)"), "{text}");
+        assert!(text.contains("- item (text)\n- qty (mixed)"), "{text}");
+        assert!(text.contains("| apples | 3 |"), "{text}");
+    }
+
+    #[test]
+    fn summarizes_csv_with_guessed_types() {
+        let rows: String = (0..30).map(|i| format!("{i},row {i},true\n")).collect();
+        let text = extract("data.csv", "", format!("id,name|label,\n{rows}").as_bytes()).unwrap();
+        assert!(text.starts_with("## Table (30 rows × 3 columns)"), "{text}");
+        assert!(text.contains("- id (number)\n- name\\|label (text)\n- column 3 (boolean)"), "{text}");
+        assert!(text.contains(&format!("({} more rows not shown)", 30 - SAMPLE_ROWS)));
+        assert_eq!(extract("empty.csv", "", b"").unwrap(), "## Table (0 rows × 0 columns)\n\n(empty)");
+    }
+
+    #[test]
+    fn extracts_notebook_cells_and_outputs() {
+        let nb = json!({
+            "metadata": { "language_info": { "name": "julia" } },
+            "cells": [
+                { "cell_type": "markdown", "source": ["# Title\n", "Intro"] },
+                { "cell_type": "code", "source": "1 + 1", "outputs": [
+                    { "output_type": "execute_result", "data": { "text/plain": ["2"] } },
+                    { "output_type": "display_data", "data": { "image/png": "iVBOR", "text/plain": "<Figure>" } },
+                    { "output_type": "error", "ename": "BoundsError", "evalue": "index 3" },
+                ] },
+                { "cell_type": "code", "source": "print(\"x\" ^ 5000)", "outputs": [
+                    { "output_type": "stream", "text": "x".repeat(5_000) },
+                ] },
+            ],
+        });
+        let text = extract("work.ipynb", "", nb.to_string().as_bytes()).unwrap();
+        assert!(text.starts_with("# Title\nIntro\n\n### In [2]\n```julia\n1 + 1\n```"), "{text}");
+        assert!(text.contains("Output:\n```\n2\n```"));
+        assert!(text.contains("[image/png output]"));
+        assert!(text.contains("BoundsError: index 3"));
+        assert!(text.contains(&format!("{}…", "x".repeat(MAX_OUTPUT_CHARS))));
+        assert!(extract("bad.ipynb", "", b"{ not json").is_err());
+    }
+
+    #[test]
+    fn lists_zip_entries_and_inlines_small_text() {
+        let big = vec![b'a'; MAX_MEMBER_BYTES as usize + 1];
+        let bytes = zip_of(&[
+            ("src/", b""),
+            ("src/main.rs", b"fn main() {}\n"),
+            ("logo.png", b"\x89PNG"),
+            ("big.txt", &big),
+        ]);
+        let text = extract("project.zip", "", &bytes).unwrap();
+        assert!(text.starts_with("## Archive (4 entries)\n\n- src/\n- src/main.rs (13 bytes)\n- logo.png (4 bytes)"), "{text}");
+        assert!(text.contains("### src/main.rs\n```\nfn main() {}\n```"));
+        assert!(!text.contains("### logo.png") && !text.contains("### big.txt"));
+    }
+
+    #[test]
+    fn lists_tar_gz_entries() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "notes.md", &b"hello"[..]).unwrap();
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+        let text = extract("backup.tgz", "", &bytes).unwrap();
+        assert!(text.starts_with("## Archive (1 entries)\n\n- notes.md (5 bytes)"), "{text}");
+        assert!(text.contains("### notes.md\n```\nhello\n```"));
+    }
+
+    #[test]
+    fn refuses_oversized_office_parts() {
+        let huge = vec![b' '; MAX_PART_BYTES as usize + 1];
+        let docx = zip_of(&[("word/document.xml", &huge)]);
+        let err = extract("bomb.docx", "", &docx).unwrap_err();
+        assert!(err.contains("larger than 20 MB"), "{err}");
+
+        let xlsx = zip_of(&[("xl/workbook.xml", &huge)]);
+        let err = extract("bomb.xlsx", "", &xlsx).unwrap_err();
+        assert!(err.contains("larger than 20 MB"), "{err}");
+    }
+}
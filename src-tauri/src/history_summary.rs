@@ -0,0 +1,309 @@
+//! Rolling summaries of conversation history that no longer fits in the
+//! context budget.
+//!
+//! When the token budget forces older messages out of a thread's context,
+//! a cheap model (`summary_model`, via the Anthropic API) folds them into a
+//! running summary. The summary is cached per thread in
+//! `history_summaries.json` next to the store, together with a fingerprint
+//! of the last message it covers, so later runs only summarize messages
+//! that have newly fallen out of the window.
+//!
+//! Runs never wait for the model: they use the summary as cached and start
+//! a background refresh when it is behind, so a thread's first run past the
+//! budget goes without a summary (or with a slightly older one).
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::context::{self, ContextMessage, Role};
+use crate::runtime::now_ms;
+use crate::state::AppState;
+
+const SUMMARY_FILE: &str = "history_summaries.json";
+/// Part of the context budget reserved for the summary itself.
+pub const SUMMARY_RESERVE_TOKENS: usize = 1_500;
+const SUMMARY_MAX_TOKENS: u32 = 1_024;
+/// Transcript characters sent per summarization call.
+const MAX_BATCH_CHARS: usize = 40_000;
+const MAX_THREADS: usize = 200;
+
+const SUMMARY_SYSTEM: &str =
+    "You maintain a running summary of a team chat between users and AI agents. \
+     Preserve decisions, agreed plans, open questions, action items, names, file \
+     paths and other facts later messages may depend on. Drop small talk. Write \
+     compact bullet points, at most 300 words. Output only the summary.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ThreadSummary {
+    summary: String,
+    /// Fingerprint of the newest message folded into the summary.
+    covered_through: String,
+    covered_count: usize,
+    updated_at: u64,
+}
+
+pub struct SummaryCache {
+    path: PathBuf,
+    threads: Mutex<HashMap<String, ThreadSummary>>,
+    /// Threads with a refresh in flight, so concurrent runs don't summarize twice.
+    refreshing: Mutex<HashSet<String>>,
+}
+
+impl SummaryCache {
+    pub fn load(store_path: &Path) -> Self {
+        let path = store_path
+            .parent()
+            .map(|dir| dir.join(SUMMARY_FILE))
+            .unwrap_or_else(|| PathBuf::from(SUMMARY_FILE));
+        let threads = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { path, threads: Mutex::new(threads), refreshing: Default::default() }
+    }
+
+    fn get(&self, thread_id: &str) -> Option<ThreadSummary> {
+        self.threads.lock().unwrap().get(thread_id).cloned()
+    }
+
+    fn put(&self, thread_id: &str, summary: ThreadSummary) {
+        let mut threads = self.threads.lock().unwrap();
+        threads.insert(thread_id.to_string(), summary);
+        while threads.len() > MAX_THREADS {
+            let Some(oldest) = threads.iter().min_by_key(|(_, s)| s.updated_at).map(|(k, _)| k.clone()) else { break };
+            threads.remove(&oldest);
+        }
+        if let Err(e) = persist(&self.path, &threads) {
+            tracing::error!("[summary] Failed to save summaries: {e}");
+        }
+    }
+}
+
+fn persist(path: &Path, threads: &HashMap<String, ThreadSummary>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(threads).unwrap_or_default())?;
+    std::fs::rename(&tmp, path)
+}
+
+fn fingerprint(message: &ContextMessage) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(message.timestamp.as_deref().unwrap_or("").as_bytes());
+    hasher.update([0]);
+    hasher.update(message.author.as_bytes());
+    hasher.update([0]);
+    hasher.update(message.text.as_bytes());
+    hex::encode(&hasher.finalize()[..12])
+}
+
+fn transcript(messages: &[ContextMessage]) -> String {
+    messages.iter()
+        .map(|m| {
+            let who = match (m.role, m.author.is_empty()) {
+                (Role::User, _) => "User".to_string(),
+                (Role::Assistant, true) => "Assistant".to_string(),
+                (Role::Assistant, false) => format!("Assistant ({})", m.author),
+            };
+            format!("{who}: {}", m.text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Cached summary of the messages that don't fit in `budget`, if summaries
+/// are enabled and there is anything to summarize. Starts a background
+/// refresh when the summary doesn't cover them all yet. The caller should
+/// render the remaining history with `budget - SUMMARY_RESERVE_TOKENS`.
+pub async fn summary_for(
+    state: &Arc<AppState>,
+    thread_id: &str,
+    messages: &[ContextMessage],
+    budget: usize,
+    provider: &str,
+    model: Option<&str>,
+) -> Option<String> {
+    let (enabled, summary_model, api_key) = {
+        let settings = state.settings.read().await;
+        (
+            settings.history_summary_enabled,
+            settings.summary_model.clone(),
+            settings.anthropic_api_key.clone().filter(|k| !k.is_empty()),
+        )
+    };
+    if !enabled || thread_id.is_empty() {
+        return None;
+    }
+
+    let window = budget.saturating_sub(SUMMARY_RESERVE_TOKENS);
+    let (_, dropped) = context::select_within_budget(messages, window, provider, model);
+    if dropped == 0 {
+        return None;
+    }
+    let older = &messages[..dropped];
+    let cached = state.summaries.get(thread_id);
+    let up_to_date = cached.as_ref().is_some_and(|c| c.covered_through == fingerprint(&older[dropped - 1]));
+
+    // Can't update without a key; a stale summary beats none
+    if let (false, Some(api_key)) = (up_to_date, api_key) {
+        if state.summaries.refreshing.lock().unwrap().insert(thread_id.to_string()) {
+            let state = state.clone();
+            let thread_id = thread_id.to_string();
+            let older = older.to_vec();
+            tokio::spawn(async move {
+                refresh(&state, &thread_id, &older, &api_key, &summary_model).await;
+                state.summaries.refreshing.lock().unwrap().remove(&thread_id);
+            });
+        }
+    }
+    cached.map(|c| c.summary)
+}
+
+/// Fold the messages the thread's summary doesn't cover yet into it. The
+/// cache is updated after every batch, so a failure keeps what was done.
+async fn refresh(state: &AppState, thread_id: &str, older: &[ContextMessage], api_key: &str, model: &str) {
+    let (mut summary, mut covered) = resume_point(state.summaries.get(thread_id), older);
+
+    for batch in batches(&older[covered..]) {
+        let prompt = if summary.is_empty() {
+            format!("Summarize this conversation:\n\n{}", transcript(batch))
+        } else {
+            format!(
+                "Current summary:\n{summary}\n\nUpdate it with these later messages:\n\n{}",
+                transcript(batch),
+            )
+        };
+        match crate::claude_api::complete(api_key, model, SUMMARY_SYSTEM, &prompt, SUMMARY_MAX_TOKENS).await {
+            Ok(text) if !text.trim().is_empty() => summary = text.trim().to_string(),
+            Ok(_) => return,
+            Err(e) => {
+                tracing::warn!("[summary] Summarization failed: {e}");
+                return;
+            }
+        }
+        covered += batch.len();
+        state.summaries.put(thread_id, ThreadSummary {
+            summary: summary.clone(),
+            covered_through: fingerprint(&older[covered - 1]),
+            covered_count: covered,
+            updated_at: now_ms(),
+        });
+    }
+}
+
+/// The summary to extend and how many of `older` it already covers: only
+/// messages after the last covered one are folded in. If that marker is gone
+/// (history edited or trimmed), start over.
+fn resume_point(cached: Option<ThreadSummary>, older: &[ContextMessage]) -> (String, usize) {
+    cached
+        .and_then(|c| older.iter().position(|m| fingerprint(m) == c.covered_through).map(|i| (c.summary, i + 1)))
+        .unwrap_or_default()
+}
+
+/// Split messages into chunks of at most `MAX_BATCH_CHARS` transcript text.
+fn batches(messages: &[ContextMessage]) -> Vec<&[ContextMessage]> {
+    let mut out = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    for (i, message) in messages.iter().enumerate() {
+        let len = message.text.len();
+        if size + len > MAX_BATCH_CHARS && i > start {
+            out.push(&messages[start..i]);
+            start = i;
+            size = 0;
+        }
+        size += len;
+    }
+    if start < messages.len() {
+        out.push(&messages[start..]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(author: &str, text: &str) -> ContextMessage {
+        ContextMessage {
+            author: author.to_string(),
+            role: if author.is_empty() { Role::User } else { Role::Assistant },
+            text: text.to_string(),
+            timestamp: Some("2026-01-01T09:00:00Z".into()),
+            attachments: Vec::new(),
+        }
+    }
+
+    fn cached(summary: &str, through: &ContextMessage) -> ThreadSummary {
+        ThreadSummary {
+            summary: summary.to_string(),
+            covered_through: fingerprint(through),
+            covered_count: 0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn batches_by_transcript_size() {
+        let small: Vec<ContextMessage> = (0..5).map(|i| message("", &format!("m{i}"))).collect();
+        assert_eq!(batches(&small).len(), 1);
+        assert!(batches(&[]).is_empty());
+
+        let half = "x".repeat(MAX_BATCH_CHARS / 2);
+        let big = vec![message("", &half), message("", &half), message("", "tail")];
+        let split: Vec<usize> = batches(&big).iter().map(|b| b.len()).collect();
+        assert_eq!(split, [2, 1]);
+
+        // A single oversized message still gets a batch of its own
+        let huge = vec![message("", "a"), message("", &"y".repeat(MAX_BATCH_CHARS + 1)), message("", "b")];
+        let split: Vec<usize> = batches(&huge).iter().map(|b| b.len()).collect();
+        assert_eq!(split, [1, 1, 1]);
+    }
+
+    #[test]
+    fn fingerprints_identify_messages() {
+        let a = message("Coder", "Done");
+        assert_eq!(fingerprint(&a), fingerprint(&a.clone()));
+        assert_ne!(fingerprint(&a), fingerprint(&message("Tester", "Done")));
+        let mut later = a.clone();
+        later.timestamp = Some("2026-01-01T09:05:00Z".into());
+        assert_ne!(fingerprint(&a), fingerprint(&later));
+    }
+
+    #[test]
+    fn resumes_after_the_last_covered_message() {
+        let older: Vec<ContextMessage> = (0..4).map(|i| message("", &format!("m{i}"))).collect();
+        assert_eq!(resume_point(None, &older), (String::new(), 0));
+        assert_eq!(resume_point(Some(cached("so far", &older[1])), &older), ("so far".to_string(), 2));
+        assert_eq!(resume_point(Some(cached("all", &older[3])), &older), ("all".to_string(), 4));
+        // Marker edited away: summarize from scratch
+        let gone = message("", "deleted");
+        assert_eq!(resume_point(Some(cached("stale", &gone)), &older), (String::new(), 0));
+    }
+
+    #[test]
+    fn transcript_names_agents() {
+        let text = transcript(&[message("", "Fix the build"), message("Coder", "Fixed")]);
+        assert_eq!(text, "User: Fix the build\n\nAssistant (Coder): Fixed");
+    }
+
+    #[test]
+    fn cache_persists_next_to_the_store() {
+        let dir = std::env::temp_dir().join(format!("jaibber-summary-{}", uuid::Uuid::new_v4()));
+        let store = dir.join("jaibber.json");
+        let first = message("", "hello");
+
+        let cache = SummaryCache::load(&store);
+        cache.put("thread-1", cached("They said hello.", &first));
+        assert!(dir.join(SUMMARY_FILE).exists());
+
+        let reloaded = SummaryCache::load(&store).get("thread-1").unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(reloaded.summary, "They said hello.");
+        assert_eq!(reloaded.covered_through, fingerprint(&first));
+    }
+}
@@ -0,0 +1,309 @@
+//! Image preprocessing before attachments reach a vision model.
+//!
+//! The format is sniffed from the file's magic bytes rather than trusting
+//! its declared MIME type. Formats the providers don't take (BMP, TIFF, ICO,
+//! SVG, HEIC/AVIF) are converted to PNG or JPEG, large images are scaled
+//! down to the size vision models work at, and metadata is dropped — after
+//! applying the EXIF orientation, so phone photos don't arrive sideways.
+//!
+//! HEIC and AVIF have no pure-Rust decoder; they're converted with `sips`
+//! (macOS), `heif-convert` or ImageMagick when one is installed.
+
+use std::io::Cursor;
+use std::sync::{Arc, LazyLock};
+use image::{DynamicImage, ImageDecoder, ImageReader};
+use resvg::{tiny_skia, usvg};
+
+/// Longest edge Anthropic recommends; larger images are downscaled anyway.
+const MAX_EDGE: u32 = 1568;
+/// About 1.15 megapixels, the other half of the recommendation.
+const MAX_PIXELS: f64 = 1_150_000.0;
+/// SVGs are rendered with at least this long edge (icons are tiny).
+const MIN_SVG_EDGE: f32 = 512.0;
+const JPEG_QUALITY: u8 = 85;
+const CONVERT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+    Ico,
+    Heif,
+    Avif,
+    Svg,
+}
+
+impl Format {
+    /// MIME type, for formats the providers accept as they are.
+    fn native_mime(self) -> Option<&'static str> {
+        match self {
+            Format::Png => Some("image/png"),
+            Format::Jpeg => Some("image/jpeg"),
+            Format::Gif => Some("image/gif"),
+            Format::Webp => Some("image/webp"),
+            _ => None,
+        }
+    }
+
+    /// Flat graphics (screenshots, diagrams) compress better as PNG.
+    fn prefers_png(self) -> bool {
+        matches!(self, Format::Png | Format::Gif | Format::Bmp | Format::Ico | Format::Svg)
+    }
+}
+
+/// The image format of `bytes`, judged by content.
+pub fn sniff(bytes: &[u8]) -> Option<Format> {
+    let format = match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Format::Png,
+        [0xFF, 0xD8, 0xFF, ..] => Format::Jpeg,
+        [b'G', b'I', b'F', b'8', ..] => Format::Gif,
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Format::Webp,
+        [b'B', b'M', ..] => Format::Bmp,
+        [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => Format::Tiff,
+        [0, 0, 1, 0, ..] => Format::Ico,
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] => match brand.get(..4)? {
+            b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1" => Format::Heif,
+            b"avif" | b"avis" => Format::Avif,
+            _ => return None,
+        },
+        _ if looks_like_svg(bytes) => Format::Svg,
+        _ => return None,
+    };
+    Some(format)
+}
+
+fn looks_like_svg(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    head.starts_with('<') && head.contains("<svg")
+}
+
+/// An image ready to send.
+pub struct PreparedImage {
+    pub bytes: Vec<u8>,
+    pub mime: &'static str,
+    /// False when the original bytes were fine as they were.
+    pub converted: bool,
+}
+
+/// Convert, downscale and strip the image as needed. Images in a native
+/// format that are small enough and carry no EXIF are returned unchanged.
+pub async fn prepare(bytes: Vec<u8>) -> Result<PreparedImage, String> {
+    let format = sniff(&bytes).ok_or("not a recognized image format")?;
+    let bytes = match format {
+        Format::Heif | Format::Avif => convert_with_tool(&bytes, format).await?,
+        _ => bytes,
+    };
+    tokio::task::spawn_blocking(move || prepare_blocking(bytes, format))
+        .await
+        .map_err(|e| format!("Image conversion failed: {e}"))?
+}
+
+fn prepare_blocking(bytes: Vec<u8>, format: Format) -> Result<PreparedImage, String> {
+    let image = match format {
+        Format::Svg => render_svg(&bytes)?,
+        _ => {
+            let (width, height, has_exif) = {
+                let mut decoder = open_decoder(&bytes)?;
+                let (width, height) = decoder.dimensions();
+                (width, height, decoder.exif_metadata().ok().flatten().is_some())
+            };
+            if let Some(mime) = format.native_mime() {
+                if scale_to_fit(width as f32, height as f32) >= 1.0 && !has_exif {
+                    return Ok(PreparedImage { bytes, mime, converted: false });
+                }
+            }
+            let mut decoder = open_decoder(&bytes)?;
+            let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+            let mut image = DynamicImage::from_decoder(decoder).map_err(|e| format!("Could not decode image: {e}"))?;
+            image.apply_orientation(orientation);
+            image
+        }
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let scale = scale_to_fit(width as f32, height as f32);
+    let image = if scale < 1.0 {
+        let w = ((width as f32 * scale).round() as u32).max(1);
+        let h = ((height as f32 * scale).round() as u32).max(1);
+        image.resize(w, h, image::imageops::FilterType::Lanczos3)
+    } else {
+        image
+    };
+    encode(&image, format.prefers_png() || image.color().has_alpha())
+}
+
+fn open_decoder(bytes: &[u8]) -> Result<impl ImageDecoder + '_, String> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?
+        .into_decoder()
+        .map_err(|e| format!("Could not decode image: {e}"))
+}
+
+/// Factor that brings the image within the recommended size (1.0 or more
+/// when it already fits).
+fn scale_to_fit(width: f32, height: f32) -> f32 {
+    let by_edge = MAX_EDGE as f32 / width.max(height).max(1.0);
+    let by_area = (MAX_PIXELS / (width as f64 * height as f64).max(1.0)).sqrt() as f32;
+    by_edge.min(by_area)
+}
+
+/// Re-encode as PNG (falling back to JPEG when that's too big) or JPEG.
+/// Encoding from pixels leaves all metadata behind.
+fn encode(image: &DynamicImage, png: bool) -> Result<PreparedImage, String> {
+    if png {
+        let mut out = Vec::new();
+        image.write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .map_err(|e| format!("Could not encode PNG: {e}"))?;
+        if out.len() <= crate::attachments::MAX_INLINE_IMAGE_BYTES {
+            return Ok(PreparedImage { bytes: out, mime: "image/png", converted: true });
+        }
+    }
+    let mut out = Vec::new();
+    let encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY);
+    DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)
+        .map_err(|e| format!("Could not encode JPEG: {e}"))?;
+    Ok(PreparedImage { bytes: out, mime: "image/jpeg", converted: true })
+}
+
+static FONTS: LazyLock<Arc<usvg::fontdb::Database>> = LazyLock::new(|| {
+    let mut fonts = usvg::fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+
+/// Rasterize an SVG on white, sized for the model.
+fn render_svg(bytes: &[u8]) -> Result<DynamicImage, String> {
+    let mut options = usvg::Options::default();
+    // Never read files an `<image href>` points at
+    options.image_href_resolver.resolve_string = Box::new(|_, _| None);
+    options.fontdb = FONTS.clone();
+    let tree = usvg::Tree::from_data(bytes, &options).map_err(|e| format!("Invalid SVG: {e}"))?;
+
+    let size = tree.size();
+    let long_edge = size.width().max(size.height());
+    let scale = scale_to_fit(size.width(), size.height()).min(1.0).max(MIN_SVG_EDGE / long_edge);
+    let width = ((size.width() * scale).ceil() as u32).max(1);
+    let height = ((size.height() * scale).ceil() as u32).max(1);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or("SVG is too large to render")?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    // Opaque, so premultiplied RGBA is plain RGBA
+    image::RgbaImage::from_raw(width, height, pixmap.take())
+        .map(|rgba| DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rgba).to_rgb8()))
+        .ok_or_else(|| "Could not render SVG".to_string())
+}
+
+/// Convert HEIC/AVIF to PNG with whichever system tool is available.
+async fn convert_with_tool(bytes: &[u8], format: Format) -> Result<Vec<u8>, String> {
+    let dir = std::env::temp_dir().join(format!("jaibber-image-{}", uuid::Uuid::new_v4().simple()));
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    let input = dir.join(if format == Format::Avif { "input.avif" } else { "input.heic" });
+    let output = dir.join("output.png");
+    let result = async {
+        tokio::fs::write(&input, bytes).await.map_err(|e| e.to_string())?;
+        let (input, output) = (input.as_os_str(), output.as_os_str());
+        let mut tools: Vec<(&str, Vec<&std::ffi::OsStr>)> = vec![
+            ("sips", vec!["-s".as_ref(), "format".as_ref(), "png".as_ref(), input, "--out".as_ref(), output]),
+            ("heif-convert", vec![input, output]),
+            ("magick", vec![input, output]),
+        ];
+        // Windows has an unrelated convert.exe
+        if !cfg!(windows) {
+            tools.push(("convert", vec![input, output]));
+        }
+        for (tool, args) in tools {
+            let Ok(path) = which::which(tool) else { continue };
+            let run = tokio::process::Command::new(path).args(&args).kill_on_drop(true).output();
+            match tokio::time::timeout(CONVERT_TIMEOUT, run).await {
+                Ok(Ok(out)) if out.status.success() => {
+                    if let Ok(png) = tokio::fs::read(output).await {
+                        return Ok(png);
+                    }
+                }
+                Ok(Ok(out)) => tracing::warn!("[images] {tool} failed: {}", String::from_utf8_lossy(&out.stderr).trim()),
+                Ok(Err(e)) => tracing::warn!("[images] Could not run {tool}: {e}"),
+                Err(_) => tracing::warn!("[images] {tool} timed out"),
+            }
+        }
+        Err("HEIC/AVIF images need sips, heif-convert or ImageMagick installed to be converted".to_string())
+    }.await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::new_rgb8(width, height).write_to(&mut Cursor::new(&mut out), format).unwrap();
+        out
+    }
+
+    #[test]
+    fn sniffs_formats_by_content() {
+        assert_eq!(sniff(&encoded(2, 2, image::ImageFormat::Png)), Some(Format::Png));
+        assert_eq!(sniff(&encoded(2, 2, image::ImageFormat::Jpeg)), Some(Format::Jpeg));
+        assert_eq!(sniff(&encoded(2, 2, image::ImageFormat::Bmp)), Some(Format::Bmp));
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some(Format::Webp));
+        assert_eq!(sniff(b"\0\0\0\x18ftypheic\0\0"), Some(Format::Heif));
+        assert_eq!(sniff(b"\0\0\0\x1cftypavif\0\0"), Some(Format::Avif));
+        assert_eq!(sniff(b"\0\0\0\x18ftypmp42\0\0"), None);
+        assert_eq!(sniff("\u{feff}  <?xml version=\"1.0\"?>\n<svg width=\"4\"/>".as_bytes()), Some(Format::Svg));
+        assert_eq!(sniff(b"<html><body>not an image</body></html>"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    #[test]
+    fn scales_to_the_edge_and_area_limits() {
+        assert!(scale_to_fit(800.0, 600.0) >= 1.0);
+        // Long edge is the binding limit for panoramas
+        assert_eq!(scale_to_fit(3136.0, 100.0), 0.5);
+        // Area is the binding limit for big squares
+        let scale = scale_to_fit(1500.0, 1500.0);
+        let pixels = (1500.0 * scale) * (1500.0 * scale);
+        assert!(scale < 1.0 && (pixels as f64 - MAX_PIXELS).abs() < 1_000.0, "{scale}");
+        assert!(scale_to_fit(0.0, 0.0).is_finite());
+    }
+
+    #[test]
+    fn passes_small_native_images_through() {
+        let png = encoded(64, 32, image::ImageFormat::Png);
+        let prepared = prepare_blocking(png.clone(), Format::Png).unwrap();
+        assert!(!prepared.converted);
+        assert_eq!(prepared.mime, "image/png");
+        assert_eq!(prepared.bytes, png);
+    }
+
+    #[test]
+    fn converts_and_downscales() {
+        let bmp = prepare_blocking(encoded(40, 20, image::ImageFormat::Bmp), Format::Bmp).unwrap();
+        assert!(bmp.converted);
+        assert_eq!(bmp.mime, "image/png");
+        assert_eq!(sniff(&bmp.bytes), Some(Format::Png));
+
+        let large = prepare_blocking(encoded(3136, 200, image::ImageFormat::Jpeg), Format::Jpeg).unwrap();
+        assert!(large.converted);
+        assert_eq!(large.mime, "image/jpeg");
+        let decoded = image::load_from_memory(&large.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (MAX_EDGE, 100));
+    }
+
+    #[test]
+    fn renders_small_svgs_at_a_readable_size() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="16" height="8"><rect width="16" height="8" fill="red"/></svg>"#;
+        let prepared = prepare_blocking(svg.to_vec(), Format::Svg).unwrap();
+        assert_eq!(prepared.mime, "image/png");
+        let decoded = image::load_from_memory(&prepared.bytes).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (MIN_SVG_EDGE as u32, MIN_SVG_EDGE as u32 / 2));
+        assert!(prepare_blocking(b"<svg".to_vec(), Format::Svg).is_err());
+    }
+}
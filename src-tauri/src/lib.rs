@@ -1,14 +1,66 @@
 use std::sync::Arc;
 
 mod error;
+mod api_client;
+mod outbox;
 mod state;
 mod agent_providers;
 mod openclaw;
 mod claude_api;
+mod claude_tools;
+mod runtime;
+mod local_api;
+mod local_store;
+mod mcp;
+mod openai_compat;
+mod scheduler;
+mod watcher;
+mod task_worker;
+mod webhooks;
+mod pipeline;
+mod compare;
+mod prompt_template;
+mod context;
+mod history_summary;
+mod models;
+mod attachments;
+mod extract;
+mod images;
+mod retry;
 mod commands;
 
 use commands::settings_commands;
 use commands::process_commands;
+use commands::schedule_commands;
+use commands::watch_commands;
+use commands::outbox_commands;
+use commands::webhook_commands;
+use commands::pipeline_commands;
+use commands::compare_commands;
+use commands::model_commands;
+use tauri::Manager;
+
+/// Headless MCP server over stdio (`jaibber --mcp`). Runs without a window,
+/// reading settings and agents straight from the store file.
+pub fn run_mcp_stdio() {
+    // stdout carries the protocol — logs must go to stderr
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let runtime = tokio::runtime::Runtime::new().expect("failed to start tokio runtime");
+    runtime.block_on(async {
+        let app_state = Arc::new(state::AppState::new());
+        if let Some(settings) = local_store::read_key(&app_state.store_path, local_store::SETTINGS_KEY) {
+            app_state.set_settings(settings).await;
+        }
+        mcp::serve_stdio(app_state).await;
+    });
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -27,11 +79,62 @@ pub fn run() {
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
         .manage(app_state)
+        .setup(|app| {
+            // Load persisted settings before anything reads them, then start
+            // background services that depend on them.
+            let handle = app.handle().clone();
+            scheduler::start(handle.clone());
+            tauri::async_runtime::spawn(async move {
+                let state = handle.state::<Arc<state::AppState>>().inner().clone();
+                if let Some(settings) = settings_commands::load_stored_settings(&handle) {
+                    state.set_settings(settings).await;
+                }
+                outbox::start(state.outbox.clone(), state.api.clone());
+                local_api::apply_settings(&state).await;
+                watcher::apply(&handle);
+                task_worker::apply_settings(&state, Arc::new(handle.clone())).await;
+                webhooks::apply_settings(&state, Arc::new(handle.clone())).await;
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             settings_commands::get_settings,
             settings_commands::save_settings,
             process_commands::run_agent,
             process_commands::run_agent_stream,
+            process_commands::list_agent_runs,
+            process_commands::cancel_agent_run,
+            process_commands::approve_tool_call,
+            model_commands::list_models,
+            schedule_commands::list_scheduled_jobs,
+            schedule_commands::save_scheduled_job,
+            schedule_commands::delete_scheduled_job,
+            schedule_commands::run_scheduled_job_now,
+            schedule_commands::get_scheduled_job_history,
+            watch_commands::list_file_watches,
+            watch_commands::save_file_watch,
+            watch_commands::delete_file_watch,
+            outbox_commands::get_outbox_status,
+            outbox_commands::outbox_send_message,
+            outbox_commands::outbox_update_task,
+            outbox_commands::retry_outbox,
+            outbox_commands::discard_outbox_failed,
+            outbox_commands::flush_outbox,
+            webhook_commands::list_webhook_routes,
+            webhook_commands::save_webhook_route,
+            webhook_commands::delete_webhook_route,
+            pipeline_commands::list_pipelines,
+            pipeline_commands::save_pipeline,
+            pipeline_commands::delete_pipeline,
+            pipeline_commands::run_pipeline,
+            pipeline_commands::list_pipeline_runs,
+            pipeline_commands::get_pipeline_run,
+            pipeline_commands::approve_pipeline_step,
+            pipeline_commands::cancel_pipeline_run,
+            compare_commands::run_agent_compare,
+            compare_commands::get_agent_comparison,
+            compare_commands::list_agent_comparisons,
+            compare_commands::run_agent_judge,
         ])
         .run(tauri::generate_context!())
         .expect("error while running Jaibber");
@@ -0,0 +1,234 @@
+//! Local authenticated HTTP/WebSocket API for the agent runtime — lets editor
+//! extensions, shell scripts and other tooling start, observe, cancel and list
+//! agent runs on this machine without going through the chat UI.
+//!
+//! Listens on 127.0.0.1 only and requires `Authorization: Bearer <token>`
+//! (or `?token=<token>` for WebSocket clients that cannot set headers).
+//! Configuration lives in `AppSettings` (`local_api_*` fields).
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+};
+use futures_util::StreamExt;
+use tokio::sync::oneshot;
+use crate::state::AppState;
+use crate::runtime::{self, RunRequest};
+
+/// Handle to a running local API server. Dropping the shutdown sender stops it.
+pub struct LocalApiHandle {
+    port: u16,
+    token: String,
+    shutdown: oneshot::Sender<()>,
+}
+
+/// Shared state for all local API handlers.
+#[derive(Clone)]
+pub struct ApiContext {
+    pub state: Arc<AppState>,
+    token: Arc<str>,
+}
+
+/// Error response: `{ "error": "..." }` with the given status.
+pub fn api_error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+// ── Lifecycle ─────────────────────────────────────────────────────────
+
+/// Start, restart or stop the server so it matches the current settings.
+/// Called at startup and whenever settings are saved.
+pub async fn apply_settings(state: &Arc<AppState>) {
+    let settings = state.settings.read().await.clone();
+    let token = settings.local_api_token.clone().unwrap_or_default();
+    let mut current = state.local_api.lock().await;
+
+    if let Some(ref handle) = *current {
+        if settings.local_api_enabled && handle.port == settings.local_api_port && handle.token == token {
+            return; // Already running with the same config
+        }
+    }
+
+    if let Some(handle) = current.take() {
+        let _ = handle.shutdown.send(());
+        tracing::info!("[local_api] Stopped server on port {}", handle.port);
+    }
+
+    if !settings.local_api_enabled {
+        return;
+    }
+    if token.is_empty() {
+        tracing::warn!("[local_api] Enabled without a token — refusing to start");
+        return;
+    }
+
+    match start(state.clone(), settings.local_api_port, token).await {
+        Ok(handle) => *current = Some(handle),
+        Err(e) => tracing::error!("[local_api] Failed to start: {e}"),
+    }
+}
+
+async fn start(state: Arc<AppState>, port: u16, token: String) -> Result<LocalApiHandle, String> {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|e| format!("Cannot bind 127.0.0.1:{port}: {e}"))?;
+
+    let ctx = ApiContext { state, token: Arc::from(token.as_str()) };
+    let app = router(ctx);
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+
+    tokio::spawn(async move {
+        let result = axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            })
+            .await;
+        if let Err(e) = result {
+            tracing::error!("[local_api] Server error: {e}");
+        }
+    });
+
+    tracing::info!("[local_api] Listening on http://127.0.0.1:{port}");
+    Ok(LocalApiHandle { port, token, shutdown })
+}
+
+fn router(ctx: ApiContext) -> Router {
+    Router::new()
+        .route("/v1/runs", get(list_runs).post(create_run))
+        .route("/v1/runs/{id}", get(get_run).delete(cancel_run))
+        .route("/v1/runs/{id}/cancel", post(cancel_run))
+        .route("/v1/runs/{id}/events", get(run_events_sse))
+        .route("/v1/runs/{id}/ws", get(run_events_ws))
+        .route("/v1/models", get(crate::openai_compat::list_models))
+        .route("/v1/chat/completions", post(crate::openai_compat::chat_completions))
+        .route("/mcp", post(crate::mcp::http_post).get(crate::mcp::http_get))
+        .layer(middleware::from_fn_with_state(ctx.clone(), require_token))
+        .with_state(ctx)
+}
+
+// ── Auth ──────────────────────────────────────────────────────────────
+
+#[derive(serde::Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+async fn require_token(
+    State(ctx): State<ApiContext>,
+    Query(query): Query<TokenQuery>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let header_token = request
+        .headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    let provided = header_token.or(query.token.as_deref()).unwrap_or("");
+
+    if !constant_time_eq(provided.as_bytes(), ctx.token.as_bytes()) {
+        return api_error(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token");
+    }
+    next.run(request).await
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────
+
+async fn list_runs(State(ctx): State<ApiContext>) -> Response {
+    Json(ctx.state.runs.list()).into_response()
+}
+
+async fn create_run(State(ctx): State<ApiContext>, Json(request): Json<RunRequest>) -> Response {
+    match runtime::start_run(&ctx.state, request, "api", None).await {
+        Ok(response_id) => (
+            StatusCode::ACCEPTED,
+            Json(serde_json::json!({ "responseId": response_id })),
+        ).into_response(),
+        Err(e) => api_error(StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+async fn get_run(State(ctx): State<ApiContext>, Path(id): Path<String>) -> Response {
+    match ctx.state.runs.get(&id) {
+        Some(info) => Json(info).into_response(),
+        None => api_error(StatusCode::NOT_FOUND, format!("Unknown run {id}")),
+    }
+}
+
+async fn cancel_run(State(ctx): State<ApiContext>, Path(id): Path<String>) -> Response {
+    if ctx.state.runs.get(&id).is_none() {
+        return api_error(StatusCode::NOT_FOUND, format!("Unknown run {id}"));
+    }
+    let cancelled = ctx.state.runs.cancel(&id);
+    Json(serde_json::json!({ "cancelled": cancelled })).into_response()
+}
+
+/// Server-Sent Events: replays the run's events so far, then streams the rest.
+/// Each SSE event is named after the runtime event (`agent-chunk`, ...).
+async fn run_events_sse(State(ctx): State<ApiContext>, Path(id): Path<String>) -> Response {
+    let Some(events) = ctx.state.runs.events(&id) else {
+        return api_error(StatusCode::NOT_FOUND, format!("Unknown run {id}"));
+    };
+    let stream = events.map(|e| {
+        Ok::<_, Infallible>(Event::default().event(e.event).data(e.payload.to_string()))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// WebSocket: sends `{ "event", "payload" }` text frames for each run event.
+/// The client may send `{"type":"cancel"}` to cancel the run.
+async fn run_events_ws(
+    State(ctx): State<ApiContext>,
+    Path(id): Path<String>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    if ctx.state.runs.get(&id).is_none() {
+        return api_error(StatusCode::NOT_FOUND, format!("Unknown run {id}"));
+    }
+    ws.on_upgrade(move |socket| forward_run_to_socket(ctx, id, socket))
+}
+
+async fn forward_run_to_socket(ctx: ApiContext, id: String, mut socket: WebSocket) {
+    let Some(mut events) = ctx.state.runs.events(&id) else { return };
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else { break };
+                let text = serde_json::to_string(&event).unwrap_or_default();
+                if socket.send(Message::Text(text.into())).await.is_err() {
+                    return;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Text(text))) => {
+                        let msg: serde_json::Value = serde_json::from_str(text.as_str()).unwrap_or_default();
+                        if msg.get("type").and_then(|t| t.as_str()) == Some("cancel") {
+                            ctx.state.runs.cancel(&id);
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+}
@@ -0,0 +1,89 @@
+//! Access to the `jaibber.json` store file written by tauri-plugin-store.
+//!
+//! `read_key` and friends read the file directly, for background services
+//! and headless modes (e.g. the MCP stdio server) that don't hold an
+//! `AppHandle`. Writes must go through the store plugin so its in-memory
+//! cache doesn't overwrite them — `read_list` / `write_list` do that for the
+//! lists the backend owns (schedules, watches, webhook routes, pipelines).
+
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::error::JaibberError;
+use crate::state::{LocalProject, StoredAuth};
+
+/// Store file name, shared with the frontend (`Store.load("jaibber.json")`).
+pub const STORE_FILE: &str = "jaibber.json";
+/// Key holding `AppSettings`.
+pub const SETTINGS_KEY: &str = "app_settings";
+/// Key holding the frontend's `LocalProject[]` (one entry per local agent).
+pub const LOCAL_PROJECTS_KEY: &str = "local_projects";
+/// Key holding the signed-in user's `StoredAuth`.
+pub const AUTH_KEY: &str = "auth";
+
+/// Bundle identifier from tauri.conf.json — names the app data directory.
+const APP_IDENTIFIER: &str = "com.jaibber.hub";
+
+/// Path of the store file, mirroring Tauri's `app_data_dir()` resolution.
+pub fn default_store_path() -> PathBuf {
+    data_dir().join(APP_IDENTIFIER).join(STORE_FILE)
+}
+
+/// Platform data directory (same rules as Tauri / the `dirs` crate).
+fn data_dir() -> PathBuf {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .unwrap_or_else(|_| ".".to_string());
+    if cfg!(target_os = "windows") {
+        std::env::var("APPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| Path::new(&home).join("AppData").join("Roaming"))
+    } else if cfg!(target_os = "macos") {
+        Path::new(&home).join("Library").join("Application Support")
+    } else {
+        std::env::var("XDG_DATA_HOME")
+            .ok()
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(&home).join(".local").join("share"))
+    }
+}
+
+/// Read and deserialize a single key from the store file.
+pub fn read_key<T: DeserializeOwned>(path: &Path, key: &str) -> Option<T> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    let mut json: serde_json::Value = serde_json::from_str(&contents).ok()?;
+    let value = json.get_mut(key)?.take();
+    serde_json::from_value(value).ok()
+}
+
+/// Load the locally configured agents (empty if none are saved yet).
+pub fn load_local_projects(path: &Path) -> Vec<LocalProject> {
+    read_key(path, LOCAL_PROJECTS_KEY).unwrap_or_default()
+}
+
+/// Load the signed-in user's session, if any.
+pub fn load_auth(path: &Path) -> Option<StoredAuth> {
+    read_key(path, AUTH_KEY)
+}
+
+/// Read a list saved under `key` through the store plugin (empty if missing
+/// or malformed).
+pub fn read_list<T: DeserializeOwned>(app: &tauri::AppHandle, key: &str) -> Vec<T> {
+    use tauri_plugin_store::StoreExt;
+    app.store(STORE_FILE)
+        .ok()
+        .and_then(|store| store.get(key))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+/// Replace the list saved under `key` and flush the store to disk.
+pub fn write_list<T: Serialize>(app: &tauri::AppHandle, key: &str, items: &[T]) -> Result<(), JaibberError> {
+    use tauri_plugin_store::StoreExt;
+    let store = app.store(STORE_FILE)
+        .map_err(|e| JaibberError::Other(e.to_string()))?;
+    store.set(key, serde_json::to_value(items)?);
+    store.save()
+        .map_err(|e| JaibberError::Other(e.to_string()))
+}
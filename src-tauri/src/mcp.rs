@@ -0,0 +1,321 @@
+//! MCP (Model Context Protocol) server — publishes each locally configured
+//! agent as an `ask_<agent>` tool, plus one `ask_<provider>` tool per CLI
+//! provider, so other MCP clients on this machine can delegate work to them.
+//!
+//! Two transports share the same JSON-RPC handler:
+//! - stdio: `jaibber --mcp` runs headless (no window) and reads settings and
+//!   agents straight from the store file.
+//! - streamable HTTP: `POST /mcp` on the local API (same bearer token).
+
+use std::sync::Arc;
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use crate::agent_providers::ModelConfig;
+use crate::local_api::ApiContext;
+use crate::runtime::{self, RunRequest};
+use crate::state::{AppState, LocalProject};
+
+/// Protocol revisions we can speak, newest first.
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Providers exposed as generic `ask_<provider>` tools.
+const PROVIDER_TOOLS: &[&str] = &["claude", "codex", "gemini", "openclaw"];
+
+/// A tool backed by an agent run.
+struct AgentTool {
+    name: String,
+    description: String,
+    provider: String,
+    custom_command: Option<String>,
+    /// Default working directory (None for generic provider tools).
+    project_dir: Option<String>,
+    system_prompt: String,
+    thinking_budget: Option<u32>,
+    model_config: ModelConfig,
+}
+
+/// Build the tool list from the configured agents (`ask_<slug>`, see
+/// `agent_slugs`) plus one tool per built-in provider.
+fn build_tools(projects: &[LocalProject]) -> Vec<AgentTool> {
+    let mut tools: Vec<AgentTool> = Vec::new();
+
+    for (slug, p) in agent_slugs(projects) {
+        tools.push(AgentTool {
+            name: format!("ask_{slug}"),
+            description: format!(
+                "Ask the Jaibber agent \"{}\" ({} provider) working in project \"{}\" ({}).",
+                p.agent_name,
+                p.provider(),
+                p.name,
+                if p.project_dir.is_empty() { "no local directory" } else { &p.project_dir },
+            ),
+            provider: p.provider().to_string(),
+            custom_command: p.custom_command.clone(),
+            project_dir: Some(p.project_dir.clone()).filter(|d| !d.is_empty()),
+            system_prompt: p.agent_instructions.clone(),
+            thinking_budget: p.thinking_budget,
+            model_config: p.model_config.clone(),
+        });
+    }
+
+    for provider in PROVIDER_TOOLS {
+        let name = format!("ask_{provider}");
+        if tools.iter().any(|t| t.name == name) {
+            continue;
+        }
+        tools.push(AgentTool {
+            name,
+            description: if *provider == "openclaw" {
+                "Run a prompt through the local OpenClaw gateway.".to_string()
+            } else {
+                format!("Run a prompt through the {provider} agent on this machine in the given project_dir.")
+            },
+            provider: provider.to_string(),
+            custom_command: None,
+            project_dir: None,
+            system_prompt: String::new(),
+            thinking_budget: None,
+            model_config: ModelConfig::default(),
+        });
+    }
+
+    tools
+}
+
+/// One unique slug per configured agent, used for MCP tool names and
+/// OpenAI-compatible model IDs. Names are sanitized to `[a-z0-9_]`;
+/// duplicate agent names are disambiguated by project name.
+pub fn agent_slugs(projects: &[LocalProject]) -> Vec<(String, &LocalProject)> {
+    let mut slugs: Vec<(String, &LocalProject)> = Vec::new();
+    for p in projects {
+        let base = sanitize_tool_name(&p.agent_name);
+        let duplicate = projects.iter()
+            .filter(|o| sanitize_tool_name(&o.agent_name) == base)
+            .count() > 1;
+        let slug = if duplicate {
+            format!("{base}_{}", sanitize_tool_name(&p.name))
+        } else {
+            base
+        };
+        if !slugs.iter().any(|(s, _)| *s == slug) {
+            slugs.push((slug, p));
+        }
+    }
+    slugs
+}
+
+/// Lowercase `[a-z0-9_]` slug used for tool (and model) names.
+pub fn sanitize_tool_name(name: &str) -> String {
+    let mut out: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    while out.contains("__") {
+        out = out.replace("__", "_");
+    }
+    out.trim_matches('_').to_string()
+}
+
+fn tool_schema(tool: &AgentTool) -> Value {
+    let dir_required = tool.project_dir.is_none() && tool.provider != "openclaw";
+    let mut required = vec!["prompt"];
+    if dir_required {
+        required.push("project_dir");
+    }
+    json!({
+        "name": tool.name,
+        "description": tool.description,
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "prompt": {
+                    "type": "string",
+                    "description": "The task or question for the agent.",
+                },
+                "project_dir": {
+                    "type": "string",
+                    "description": match tool.project_dir {
+                        Some(ref d) => format!("Working directory (defaults to {d})."),
+                        None => "Absolute path of the project the agent should work in.".to_string(),
+                    },
+                },
+            },
+            "required": required,
+        },
+    })
+}
+
+// ── JSON-RPC handling ─────────────────────────────────────────────────
+
+/// Handle one JSON-RPC message or batch. Returns None when nothing should be
+/// sent back (notifications and responses from the client).
+pub async fn handle_message(state: &Arc<AppState>, message: Value) -> Option<Value> {
+    if let Value::Array(batch) = message {
+        let mut replies = Vec::new();
+        for item in batch {
+            if let Some(reply) = Box::pin(handle_message(state, item)).await {
+                replies.push(reply);
+            }
+        }
+        return (!replies.is_empty()).then_some(Value::Array(replies));
+    }
+
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+        // A response to something we never sent, or garbage
+        return id.map(|id| rpc_error(id, -32600, "Invalid request"));
+    };
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    // Notifications carry no id and never get a reply
+    let id = id?;
+
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(list_tools(state)),
+        "tools/call" => call_tool(state, &params).await,
+        _ => Err((-32601, format!("Method not found: {method}"))),
+    };
+
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, msg)) => rpc_error(id, code, &msg),
+    })
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(|v| v.as_str()).unwrap_or("");
+    let version = SUPPORTED_PROTOCOL_VERSIONS.iter()
+        .find(|v| **v == requested)
+        .unwrap_or(&SUPPORTED_PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": { "listChanged": false } },
+        "serverInfo": { "name": "jaibber", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "Each tool delegates a prompt to a Jaibber agent on this machine and returns its full answer.",
+    })
+}
+
+fn list_tools(state: &Arc<AppState>) -> Value {
+    let projects = crate::local_store::load_local_projects(&state.store_path);
+    let tools: Vec<Value> = build_tools(&projects).iter().map(tool_schema).collect();
+    json!({ "tools": tools })
+}
+
+async fn call_tool(state: &Arc<AppState>, params: &Value) -> Result<Value, (i64, String)> {
+    let name = params.get("name").and_then(|n| n.as_str()).unwrap_or("");
+    let args = params.get("arguments").cloned().unwrap_or(Value::Null);
+
+    let projects = crate::local_store::load_local_projects(&state.store_path);
+    let tools = build_tools(&projects);
+    let tool = tools.iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| (-32602, format!("Unknown tool: {name}")))?;
+
+    let prompt = args.get("prompt").and_then(|p| p.as_str()).unwrap_or("");
+    if prompt.trim().is_empty() {
+        return Err((-32602, "Missing required argument: prompt".to_string()));
+    }
+    let project_dir = args.get("project_dir")
+        .and_then(|d| d.as_str())
+        .map(|d| d.to_string())
+        .or_else(|| tool.project_dir.clone())
+        .unwrap_or_default();
+
+    let request = RunRequest {
+        prompt: prompt.to_string(),
+        project_dir,
+        system_prompt: tool.system_prompt.clone(),
+        agent_provider: Some(tool.provider.clone()),
+        custom_command: tool.custom_command.clone(),
+        thinking_budget: tool.thinking_budget,
+        model_config: tool.model_config.clone(),
+        ..Default::default()
+    };
+
+    // Agent failures are tool errors (visible to the model), not protocol errors
+    Ok(match runtime::run_to_completion(state, request, "mcp").await {
+        Ok(text) => json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": false,
+        }),
+        Err(e) => json!({
+            "content": [{ "type": "text", "text": e }],
+            "isError": true,
+        }),
+    })
+}
+
+// ── Transports ────────────────────────────────────────────────────────
+
+/// Serve MCP over stdin/stdout (newline-delimited JSON-RPC) until EOF.
+/// Requests are handled concurrently so a long agent run doesn't block pings.
+pub async fn serve_stdio(state: Arc<AppState>) {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Value>();
+
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(reply) = rx.recv().await {
+            let mut line = reply.to_string();
+            line.push('\n');
+            if stdout.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+            let _ = stdout.flush().await;
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message = match serde_json::from_str::<Value>(&line) {
+            Ok(m) => m,
+            Err(e) => {
+                let _ = tx.send(rpc_error(Value::Null, -32700, &format!("Parse error: {e}")));
+                continue;
+            }
+        };
+        let state = state.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Some(reply) = handle_message(&state, message).await {
+                let _ = tx.send(reply);
+            }
+        });
+    }
+
+    drop(tx);
+    let _ = writer.await;
+}
+
+/// Streamable HTTP transport: `POST /mcp` with a JSON-RPC message or batch.
+/// Replies are returned as a single JSON body; notifications get 202.
+pub async fn http_post(State(ctx): State<ApiContext>, Json(message): Json<Value>) -> Response {
+    match handle_message(&ctx.state, message).await {
+        Some(reply) => Json(reply).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// We never push server-initiated messages, so there is no GET stream.
+pub async fn http_get() -> Response {
+    StatusCode::METHOD_NOT_ALLOWED.into_response()
+}
@@ -0,0 +1,115 @@
+//! Model discovery for `list_models`: asks each provider which models the
+//! configured key (or local gateway) can use.
+//!
+//! Claude uses the Anthropic Models API, Codex the OpenAI one and Gemini the
+//! Generative Language API, each with the fallback key from settings. The
+//! CLIs accept the same model IDs via `--model`. Custom commands can't be
+//! queried.
+
+use serde::Serialize;
+use crate::state::AppSettings;
+
+const OPENAI_MODELS_URL: &str = "https://api.openai.com/v1/models";
+const GEMINI_MODELS_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+/// Providers `list_models` queries when none is given.
+pub const PROVIDERS: &[&str] = &["claude", "codex", "gemini", "openclaw"];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInfo {
+    pub id: String,
+    pub display_name: Option<String>,
+}
+
+/// Models for one provider, or why they couldn't be listed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderModels {
+    pub provider: String,
+    pub models: Vec<ModelInfo>,
+    pub error: Option<String>,
+}
+
+/// List the models for `provider`.
+pub async fn list_for(provider: &str, settings: &AppSettings) -> ProviderModels {
+    let key = settings.fallback_key_for(provider).map(|k| k.to_string());
+    let result = match provider {
+        "claude" => match key {
+            Some(key) => crate::claude_api::list_models(&key).await,
+            None => Err("No Anthropic API key configured".to_string()),
+        },
+        "codex" => match key {
+            Some(key) => list_openai(&key).await,
+            None => Err("No OpenAI API key configured".to_string()),
+        },
+        "gemini" => match key {
+            Some(key) => list_gemini(&key).await,
+            None => Err("No Google API key configured".to_string()),
+        },
+        "openclaw" => match crate::openclaw::discover_openclaw() {
+            Ok(config) => crate::openclaw::list_models(&config).await
+                .map(|ids| ids.into_iter().map(|id| ModelInfo { id, display_name: None }).collect()),
+            Err(e) => Err(e),
+        },
+        _ => Err(format!("Listing models is not supported for the {provider} provider")),
+    };
+    match result {
+        Ok(models) => ProviderModels { provider: provider.to_string(), models, error: None },
+        Err(e) => ProviderModels { provider: provider.to_string(), models: Vec::new(), error: Some(e) },
+    }
+}
+
+/// Chat models from the OpenAI API (skips embeddings, audio, images, …).
+async fn list_openai(api_key: &str) -> Result<Vec<ModelInfo>, String> {
+    let json = get_json(
+        reqwest::Client::new().get(OPENAI_MODELS_URL).bearer_auth(api_key),
+        "OpenAI",
+    ).await?;
+    let mut ids: Vec<String> = json.get("data").and_then(|d| d.as_array()).into_iter().flatten()
+        .filter_map(|m| m.get("id").and_then(|id| id.as_str()))
+        .filter(|id| is_openai_chat_model(id))
+        .map(|id| id.to_string())
+        .collect();
+    ids.sort();
+    Ok(ids.into_iter().map(|id| ModelInfo { id, display_name: None }).collect())
+}
+
+fn is_openai_chat_model(id: &str) -> bool {
+    let family = id.starts_with("gpt-")
+        || id.starts_with("codex")
+        || (id.starts_with('o') && id[1..].starts_with(|c: char| c.is_ascii_digit()));
+    let other = ["embedding", "tts", "whisper", "audio", "realtime", "transcribe", "image", "search", "moderation"];
+    family && !other.iter().any(|o| id.contains(o))
+}
+
+/// Gemini models that support `generateContent`.
+async fn list_gemini(api_key: &str) -> Result<Vec<ModelInfo>, String> {
+    let json = get_json(
+        reqwest::Client::new().get(GEMINI_MODELS_URL).query(&[("key", api_key), ("pageSize", "1000")]),
+        "Google",
+    ).await?;
+    Ok(json.get("models").and_then(|d| d.as_array()).into_iter().flatten()
+        .filter(|m| {
+            m.get("supportedGenerationMethods").and_then(|s| s.as_array())
+                .is_some_and(|methods| methods.iter().any(|v| v.as_str() == Some("generateContent")))
+        })
+        .filter_map(|m| Some(ModelInfo {
+            id: m.get("name")?.as_str()?.trim_start_matches("models/").to_string(),
+            display_name: m.get("displayName").and_then(|n| n.as_str()).map(|n| n.to_string()),
+        }))
+        .collect())
+}
+
+async fn get_json(request: reqwest::RequestBuilder, service: &str) -> Result<serde_json::Value, String> {
+    let response = request
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| format!("{service} API request failed: {e}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("{service} API returned {status}: {text}"));
+    }
+    response.json().await.map_err(|e| format!("Invalid {service} API response: {e}"))
+}
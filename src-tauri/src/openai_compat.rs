@@ -0,0 +1,320 @@
+//! OpenAI-compatible endpoint — the reverse of `openclaw.rs`. Serves
+//! `/v1/models` and `/v1/chat/completions` on the local API so any
+//! OpenAI-compatible tool can use the agents on this machine.
+//!
+//! Each "model" maps to a provider:
+//! - `claude-cli`, `codex-cli`, `gemini-cli`, `openclaw`
+//! - `claude-api` (only listed when an Anthropic key is configured)
+//! - `agent/<name>` for every locally configured agent (including custom
+//!   command agents), using that agent's directory and instructions. Agents
+//!   sharing a name get `agent/<name>_<project>`, as their MCP tools do.
+//!
+//! CLI models need a working directory: pass `project_dir` in the request
+//! body or an `X-Jaibber-Project-Dir` header.
+
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::agent_providers::ModelConfig;
+use crate::local_api::ApiContext;
+use crate::mcp::agent_slugs;
+use crate::runtime::{self, RunRequest};
+use crate::state::AppState;
+
+const AGENT_MODEL_PREFIX: &str = "agent/";
+
+/// A chat message in OpenAI format. `content` is a string or an array of parts.
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    role: String,
+    #[serde(default)]
+    content: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+    /// Jaibber extension: working directory for CLI providers.
+    #[serde(default, alias = "projectDir")]
+    project_dir: Option<String>,
+    #[serde(default, alias = "max_completion_tokens")]
+    max_tokens: Option<u32>,
+    #[serde(default)]
+    temperature: Option<f64>,
+    #[serde(default)]
+    top_p: Option<f64>,
+    /// A string or an array of strings.
+    #[serde(default)]
+    stop: Option<Value>,
+}
+
+/// What a model name resolves to.
+struct ModelTarget {
+    provider: String,
+    use_api: Option<bool>,
+    custom_command: Option<String>,
+    project_dir: Option<String>,
+    system_prompt: String,
+    thinking_budget: Option<u32>,
+    model_config: ModelConfig,
+}
+
+fn list_model_ids(state: &Arc<AppState>, has_api_key: bool) -> Vec<String> {
+    let mut ids: Vec<String> = ["claude-cli", "codex-cli", "gemini-cli", "openclaw"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    if has_api_key {
+        ids.push("claude-api".to_string());
+    }
+    let projects = crate::local_store::load_local_projects(&state.store_path);
+    ids.extend(agent_slugs(&projects).into_iter().map(|(slug, _)| format!("{AGENT_MODEL_PREFIX}{slug}")));
+    ids
+}
+
+fn resolve_model(state: &Arc<AppState>, model: &str) -> Option<ModelTarget> {
+    let builtin = |provider: &str, use_api: Option<bool>| ModelTarget {
+        provider: provider.to_string(),
+        use_api,
+        custom_command: None,
+        project_dir: None,
+        system_prompt: String::new(),
+        thinking_budget: None,
+        model_config: ModelConfig::default(),
+    };
+    match model {
+        "claude-cli" => Some(builtin("claude", Some(false))),
+        "claude" => Some(builtin("claude", None)),
+        "claude-api" => Some(builtin("claude", Some(true))),
+        "codex-cli" | "codex" => Some(builtin("codex", None)),
+        "gemini-cli" | "gemini" => Some(builtin("gemini", None)),
+        "openclaw" => Some(builtin("openclaw", None)),
+        _ => {
+            let slug = model.strip_prefix(AGENT_MODEL_PREFIX)?;
+            let projects = crate::local_store::load_local_projects(&state.store_path);
+            let (_, agent) = agent_slugs(&projects).into_iter().find(|(s, _)| s == slug)?;
+            Some(ModelTarget {
+                provider: agent.provider().to_string(),
+                use_api: None,
+                custom_command: agent.custom_command.clone(),
+                project_dir: Some(agent.project_dir.clone()).filter(|d| !d.is_empty()),
+                system_prompt: agent.agent_instructions.clone(),
+                thinking_budget: agent.thinking_budget,
+                model_config: agent.model_config.clone(),
+            })
+        }
+    }
+}
+
+/// Flatten OpenAI message content (string or `[{type:"text",text}]`) to text.
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts.iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+/// Split OpenAI messages into (system prompt, conversation context, prompt):
+/// system messages are joined, the last user message is the prompt and
+/// everything in between becomes the conversation context.
+fn split_messages(messages: &[ChatMessage]) -> (String, String, String) {
+    let system: Vec<String> = messages.iter()
+        .filter(|m| m.role == "system" || m.role == "developer")
+        .map(|m| content_text(&m.content))
+        .collect();
+    let turns: Vec<&ChatMessage> = messages.iter()
+        .filter(|m| m.role != "system" && m.role != "developer")
+        .collect();
+
+    let last_user = turns.iter().rposition(|m| m.role == "user");
+    let prompt = last_user.map(|i| content_text(&turns[i].content)).unwrap_or_default();
+    let history = &turns[..last_user.unwrap_or(turns.len())];
+    let context = history.iter()
+        .map(|m| {
+            let who = if m.role == "assistant" { "Assistant" } else { "User" };
+            format!("{who}: {}", content_text(&m.content))
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    (system.join("\n\n"), context, prompt)
+}
+
+/// Sampling parameters from the request, applied on top of the agent's
+/// settings. The `model` field names the target, so it isn't one of them.
+fn sampling_override(body: &ChatCompletionRequest) -> ModelConfig {
+    let stop_sequences = match &body.stop {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(items)) => items.iter().filter_map(|s| s.as_str().map(|s| s.to_string())).collect(),
+        _ => Vec::new(),
+    };
+    ModelConfig {
+        model: None,
+        max_tokens: body.max_tokens,
+        temperature: body.temperature,
+        top_p: body.top_p,
+        stop_sequences,
+    }
+}
+
+/// Cancels the run if the client goes away before it finishes.
+struct CancelOnDrop {
+    state: Arc<AppState>,
+    response_id: String,
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.state.runs.cancel(&self.response_id);
+    }
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────
+
+pub async fn list_models(State(ctx): State<ApiContext>) -> Response {
+    let has_api_key = ctx.state.settings.read().await.fallback_key_for("claude").is_some();
+    let data: Vec<Value> = list_model_ids(&ctx.state, has_api_key)
+        .into_iter()
+        .map(|id| json!({ "id": id, "object": "model", "created": 0, "owned_by": "jaibber" }))
+        .collect();
+    Json(json!({ "object": "list", "data": data })).into_response()
+}
+
+pub async fn chat_completions(
+    State(ctx): State<ApiContext>,
+    headers: HeaderMap,
+    Json(body): Json<ChatCompletionRequest>,
+) -> Response {
+    let Some(target) = resolve_model(&ctx.state, &body.model) else {
+        return openai_error(StatusCode::NOT_FOUND, &format!("The model `{}` does not exist", body.model));
+    };
+
+    let (system, context, prompt) = split_messages(&body.messages);
+    if prompt.trim().is_empty() {
+        return openai_error(StatusCode::BAD_REQUEST, "messages must include a user message");
+    }
+    let mut system_prompt = target.system_prompt.clone();
+    if !system.is_empty() {
+        if !system_prompt.is_empty() {
+            system_prompt.push_str("\n\n");
+        }
+        system_prompt.push_str(&system);
+    }
+
+    let project_dir = body.project_dir.clone()
+        .or_else(|| headers.get("x-jaibber-project-dir").and_then(|v| v.to_str().ok()).map(|s| s.to_string()))
+        .or(target.project_dir.clone())
+        .unwrap_or_default();
+
+    let request = RunRequest {
+        prompt,
+        project_dir,
+        system_prompt,
+        conversation_context: context,
+        agent_provider: Some(target.provider.clone()),
+        custom_command: target.custom_command.clone(),
+        use_api: target.use_api,
+        thinking_budget: target.thinking_budget,
+        model_config: target.model_config.clone(),
+        model_override: sampling_override(&body),
+        ..Default::default()
+    };
+
+    let rid = match runtime::start_run(&ctx.state, request, "openai", None).await {
+        Ok(rid) => rid,
+        Err(e) => return openai_error(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let guard = CancelOnDrop { state: ctx.state.clone(), response_id: rid.clone() };
+    let completion_id = format!("chatcmpl-{rid}");
+    let created = runtime::now_ms() / 1000;
+
+    if !body.stream {
+        let result = runtime::collect_output(&ctx.state, &rid).await;
+        drop(guard);
+        return match result {
+            Ok(text) => Json(json!({
+                "id": completion_id,
+                "object": "chat.completion",
+                "created": created,
+                "model": body.model,
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": text },
+                    "finish_reason": "stop",
+                }],
+            })).into_response(),
+            Err(e) => openai_error(StatusCode::BAD_GATEWAY, &e),
+        };
+    }
+
+    let Some(events) = ctx.state.runs.events(&rid) else {
+        return openai_error(StatusCode::INTERNAL_SERVER_ERROR, "Run is no longer tracked");
+    };
+    let model = body.model.clone();
+    let chunk = move |delta: Value, finish_reason: Option<&str>| json!({
+        "id": completion_id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    });
+
+    let first = chunk(json!({ "role": "assistant", "content": "" }), None);
+    // End the stream after a gap: the text sent so far is incomplete
+    let events = events.scan(false, |failed, event| {
+        let next = (!*failed).then(|| {
+            *failed = event.missed_error().is_some();
+            event
+        });
+        std::future::ready(next)
+    });
+    let body_stream = events.filter_map(move |event| {
+        let _keep_alive = &guard; // dropped (→ cancel) when the client disconnects
+        let out = if let Some(err) = event.missed_error() {
+            Some(json!({ "error": { "message": err, "type": "api_error" } }))
+        } else if event.event != "agent-chunk" {
+            None
+        } else if let Some(err) = event.payload.get("error").and_then(|e| e.as_str()) {
+            Some(json!({ "error": { "message": err, "type": "agent_error" } }))
+        } else if event.payload.get("done").and_then(|d| d.as_bool()) == Some(true) {
+            Some(chunk(json!({}), Some("stop")))
+        } else {
+            event.payload.get("chunk")
+                .and_then(|c| c.as_str())
+                .filter(|c| !c.is_empty())
+                .map(|c| chunk(json!({ "content": c }), None))
+        };
+        std::future::ready(out.map(|v| v.to_string()))
+    });
+
+    let stream = futures_util::stream::iter([first.to_string()])
+        .chain(body_stream)
+        .chain(futures_util::stream::iter(["[DONE]".to_string()]))
+        .map(|data| Ok::<_, Infallible>(Event::default().data(data)));
+
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+/// Error in OpenAI's `{ "error": { message, type } }` shape.
+fn openai_error(status: StatusCode, message: &str) -> Response {
+    let kind = if status.is_server_error() { "api_error" } else { "invalid_request_error" };
+    (status, Json(json!({
+        "error": { "message": message, "type": kind, "code": status.as_u16() },
+    }))).into_response()
+}
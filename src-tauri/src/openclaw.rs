@@ -1,8 +1,10 @@
 //! OpenClaw integration — auto-discovers local gateway config and streams
 //! responses via the OpenAI-compatible HTTP API.
 
-use tauri::Emitter;
 use futures_util::StreamExt;
+use crate::agent_providers::ModelConfig;
+use crate::retry::{self, Retrier};
+use crate::runtime::EventSink;
 
 /// Discovered OpenClaw gateway configuration.
 pub struct OpenClawConfig {
@@ -49,17 +51,39 @@ pub fn discover_openclaw() -> Result<OpenClawConfig, String> {
     Ok(OpenClawConfig { url, auth_token })
 }
 
+/// Model IDs served by the gateway's `/v1/models`.
+pub async fn list_models(config: &OpenClawConfig) -> Result<Vec<String>, String> {
+    let mut request = reqwest::Client::new()
+        .get(format!("{}/v1/models", config.url))
+        .timeout(std::time::Duration::from_secs(10));
+    if !config.auth_token.is_empty() {
+        request = request.header("Authorization", format!("Bearer {}", config.auth_token));
+    }
+    let response = request.send().await.map_err(|e| format!("OpenClaw request failed: {e}"))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("OpenClaw returned {status}: {text}"));
+    }
+    let json: serde_json::Value = response.json().await
+        .map_err(|e| format!("Invalid OpenClaw response: {e}"))?;
+    Ok(json.get("data").and_then(|d| d.as_array()).into_iter().flatten()
+        .filter_map(|m| m.get("id").and_then(|id| id.as_str()).map(|id| id.to_string()))
+        .collect())
+}
+
 /// Stream a response from the OpenClaw gateway via SSE.
 ///
 /// Sends a chat completion request with `stream: true` and parses the
-/// Server-Sent Events response, emitting `"agent-chunk"` Tauri events
+/// Server-Sent Events response, emitting `"agent-chunk"` events
 /// for each text delta — same schema as the CLI providers.
 pub async fn stream_openclaw(
     config: &OpenClawConfig,
     system_prompt: &str,
     prompt: &str,
+    model: &ModelConfig,
     response_id: &str,
-    window: &tauri::Window,
+    sink: &dyn EventSink,
 ) -> Result<(), String> {
     let client = reqwest::Client::new();
 
@@ -76,42 +100,66 @@ pub async fn stream_openclaw(
         "content": prompt,
     }));
 
-    let body = serde_json::json!({
-        "model": "default",
+    let mut body = serde_json::json!({
+        "model": model.model_name().unwrap_or("default"),
         "stream": true,
         "messages": messages,
     });
+    if let Some(max_tokens) = model.max_tokens {
+        body["max_tokens"] = serde_json::json!(max_tokens);
+    }
+    if let Some(temperature) = model.temperature {
+        body["temperature"] = serde_json::json!(temperature);
+    }
+    if let Some(top_p) = model.top_p {
+        body["top_p"] = serde_json::json!(top_p);
+    }
+    if !model.stop_sequences.is_empty() {
+        body["stop"] = serde_json::json!(model.stop_sequences);
+    }
 
-    let mut request = client
-        .post(format!("{}/v1/chat/completions", config.url))
-        .header("Content-Type", "application/json")
-        .timeout(std::time::Duration::from_secs(120));
+    // Retry rate limits and gateway errors; nothing has streamed yet
+    let mut retrier = Retrier::new("OpenClaw", response_id, sink);
+    let response = loop {
+        let mut request = client
+            .post(format!("{}/v1/chat/completions", config.url))
+            .header("Content-Type", "application/json")
+            .timeout(std::time::Duration::from_secs(120));
 
-    if !config.auth_token.is_empty() {
-        request = request.header("Authorization", format!("Bearer {}", config.auth_token));
-    }
+        if !config.auth_token.is_empty() {
+            request = request.header("Authorization", format!("Bearer {}", config.auth_token));
+        }
 
-    let response = request
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| {
-            if e.is_connect() {
-                format!(
+        let response = match request.json(&body).send().await {
+            Ok(response) => response,
+            Err(e) if e.is_connect() => {
+                return Err(format!(
                     "Cannot connect to OpenClaw gateway at {}. \
                      Make sure it's running: `openclaw gateway start`",
                     config.url
-                )
-            } else {
-                format!("OpenClaw request failed: {e}")
+                ));
+            }
+            Err(e) => {
+                let error = format!("OpenClaw request failed: {e}");
+                if e.is_timeout() && retrier.wait(None, &error).await {
+                    continue;
+                }
+                return Err(error);
             }
-        })?;
+        };
 
-    if !response.status().is_success() {
         let status = response.status();
+        if status.is_success() {
+            break response;
+        }
+        let hint = retry::retry_after(response.headers());
         let text = response.text().await.unwrap_or_default();
-        return Err(format!("OpenClaw returned {status}: {text}"));
-    }
+        let error = format!("OpenClaw returned {status}: {text}");
+        if retry::is_retryable_status(status) && retrier.wait(hint, &error).await {
+            continue;
+        }
+        return Err(error);
+    };
 
     // Read the SSE stream
     let mut stream = response.bytes_stream();
@@ -135,7 +183,7 @@ pub async fn stream_openclaw(
             if let Some(data) = line.strip_prefix("data: ") {
                 if data.trim() == "[DONE]" {
                     // Stream complete
-                    let _ = window.emit("agent-chunk", serde_json::json!({
+                    sink.emit_event("agent-chunk", serde_json::json!({
                         "responseId": response_id,
                         "chunk": "",
                         "done": true,
@@ -154,7 +202,7 @@ pub async fn stream_openclaw(
                         .and_then(|t| t.as_str())
                     {
                         if !content.is_empty() {
-                            let _ = window.emit("agent-chunk", serde_json::json!({
+                            sink.emit_event("agent-chunk", serde_json::json!({
                                 "responseId": response_id,
                                 "chunk": content,
                                 "done": false,
@@ -168,7 +216,7 @@ pub async fn stream_openclaw(
     }
 
     // Stream ended without [DONE] — still mark as complete
-    let _ = window.emit("agent-chunk", serde_json::json!({
+    sink.emit_event("agent-chunk", serde_json::json!({
         "responseId": response_id,
         "chunk": "",
         "done": true,
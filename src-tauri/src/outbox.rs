@@ -0,0 +1,298 @@
+//! Durable outbox for writes to the Jaibber API that must not be lost when
+//! the network drops — completed agent responses and task status updates.
+//!
+//! Items are appended to `outbox.json` (next to the store file) before any
+//! send is attempted, then flushed in order. Each item carries a stable
+//! idempotency key, so a send that reached the server before the connection
+//! dropped is not duplicated when it is retried.
+//!
+//! Network errors, 429s and 502–504 stop the flush and back off; 401 waits
+//! for the user to sign in again. Other 5xx and 408s count against the item:
+//! it stays queued while later items go ahead, and after `MAX_ATTEMPTS` it is
+//! moved to `failed` like any other 4xx, where it stays visible in the status
+//! until retried or discarded.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Notify;
+use crate::api_client::{ApiClient, NewMessage};
+use crate::error::JaibberError;
+use crate::runtime::now_ms;
+
+const OUTBOX_FILE: &str = "outbox.json";
+/// Periodic flush attempt while items are pending.
+const FLUSH_INTERVAL_SECS: u64 = 30;
+const MAX_BACKOFF_SECS: u64 = 300;
+/// Failed items kept for inspection.
+const MAX_FAILED: usize = 100;
+/// Sends an item may fail on its own (5xx, 408) before it is moved to `failed`.
+const MAX_ATTEMPTS: u32 = 8;
+
+/// A queued write.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum OutboxOp {
+    #[serde(rename_all = "camelCase")]
+    Message { project_id: String, message: NewMessage },
+    #[serde(rename_all = "camelCase")]
+    TaskUpdate { task_id: String, updates: Value },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxItem {
+    /// Idempotency key sent with every attempt.
+    pub id: String,
+    #[serde(flatten)]
+    pub op: OutboxOp,
+    pub created_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OutboxFile {
+    pending: Vec<OutboxItem>,
+    failed: Vec<OutboxItem>,
+}
+
+/// Returned by `get_outbox_status`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboxStatus {
+    pub pending: Vec<OutboxItem>,
+    pub failed: Vec<OutboxItem>,
+    /// False after the last flush hit a network error.
+    pub online: bool,
+    pub last_flush_at: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct Inner {
+    file: OutboxFile,
+    online: bool,
+    last_flush_at: Option<u64>,
+    last_error: Option<String>,
+}
+
+pub struct Outbox {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+    wake: Notify,
+}
+
+/// What to do after a failed send.
+enum Disposition {
+    /// The server is unreachable or refusing everyone: keep the item and
+    /// stop flushing for now.
+    Retry,
+    /// The send failed on its own: keep the item (until `MAX_ATTEMPTS`) and
+    /// go on with the next one.
+    Defer,
+    /// Move the item to `failed`.
+    Reject,
+}
+
+impl Outbox {
+    /// Load the outbox stored next to the store file.
+    pub fn load(store_path: &std::path::Path) -> Self {
+        let path = store_path
+            .parent()
+            .map(|dir| dir.join(OUTBOX_FILE))
+            .unwrap_or_else(|| PathBuf::from(OUTBOX_FILE));
+        let file = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            inner: Mutex::new(Inner { file, online: true, ..Default::default() }),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Queue a chat message. The message ID is the idempotency key.
+    pub fn send_message(&self, project_id: &str, message: NewMessage) -> Result<(), JaibberError> {
+        self.push(message.id.clone(), OutboxOp::Message { project_id: project_id.to_string(), message })
+    }
+
+    /// Queue a task update.
+    pub fn update_task(&self, task_id: &str, updates: Value) -> Result<(), JaibberError> {
+        self.push(uuid::Uuid::new_v4().to_string(), OutboxOp::TaskUpdate { task_id: task_id.to_string(), updates })
+    }
+
+    fn push(&self, id: String, op: OutboxOp) -> Result<(), JaibberError> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.file.pending.iter().any(|i| i.id == id) {
+                return Ok(());
+            }
+            inner.file.pending.push(OutboxItem { id, op, created_at: now_ms(), attempts: 0, last_error: None });
+            self.persist(&inner.file)?;
+        }
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    pub fn status(&self) -> OutboxStatus {
+        let inner = self.inner.lock().unwrap();
+        OutboxStatus {
+            pending: inner.file.pending.clone(),
+            failed: inner.file.failed.clone(),
+            online: inner.online,
+            last_flush_at: inner.last_flush_at,
+            last_error: inner.last_error.clone(),
+        }
+    }
+
+    /// Move failed items back to the queue (all, or one by ID) and flush.
+    pub fn retry_failed(&self, id: Option<&str>) -> Result<usize, JaibberError> {
+        let moved = {
+            let mut inner = self.inner.lock().unwrap();
+            let (retry, keep): (Vec<_>, Vec<_>) = std::mem::take(&mut inner.file.failed)
+                .into_iter()
+                .partition(|i| id.is_none_or(|id| i.id == id));
+            inner.file.failed = keep;
+            let moved = retry.len();
+            inner.file.pending.extend(retry.into_iter().map(|item| OutboxItem { attempts: 0, ..item }));
+            self.persist(&inner.file)?;
+            moved
+        };
+        self.wake.notify_one();
+        Ok(moved)
+    }
+
+    /// Drop failed items (all, or one by ID).
+    pub fn discard_failed(&self, id: Option<&str>) -> Result<usize, JaibberError> {
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.file.failed.len();
+        inner.file.failed.retain(|i| id.is_some_and(|id| i.id != id));
+        let removed = before - inner.file.failed.len();
+        self.persist(&inner.file)?;
+        Ok(removed)
+    }
+
+    /// Trigger a flush now (e.g. when the frontend sees the network return).
+    pub fn wake(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Write atomically so a crash mid-write can't corrupt the queue.
+    fn persist(&self, file: &OutboxFile) -> Result<(), JaibberError> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(file)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Send pending items in order. Stops at the first error that affects
+    /// every item; items that fail on their own are skipped until the next
+    /// flush. Returns true if the queue was drained.
+    async fn flush(&self, api: &ApiClient) -> bool {
+        let queued: Vec<String> = self.inner.lock().unwrap().file.pending.iter().map(|i| i.id.clone()).collect();
+        let mut drained = true;
+
+        for id in queued {
+            let Some(item) = self.inner.lock().unwrap().file.pending.iter().find(|i| i.id == id).cloned() else {
+                continue;
+            };
+
+            let result = match &item.op {
+                OutboxOp::Message { project_id, message } => api.create_message(project_id, message).await,
+                OutboxOp::TaskUpdate { task_id, updates } => {
+                    api.update_task(task_id, updates.clone(), Some(&item.id)).await.map(|_| ())
+                }
+            };
+
+            let mut inner = self.inner.lock().unwrap();
+            inner.last_flush_at = Some(now_ms());
+            let position = inner.file.pending.iter().position(|i| i.id == item.id);
+            match result {
+                Ok(()) => {
+                    inner.online = true;
+                    inner.last_error = None;
+                    if let Some(idx) = position {
+                        inner.file.pending.remove(idx);
+                    }
+                }
+                Err(e) => {
+                    let mut disposition = classify(&e);
+                    inner.online = !matches!(e, JaibberError::Http(_));
+                    inner.last_error = Some(e.to_string());
+                    let Some(idx) = position else { continue };
+                    let pending = &mut inner.file.pending[idx];
+                    pending.last_error = Some(e.to_string());
+                    if !matches!(disposition, Disposition::Retry) {
+                        pending.attempts += 1;
+                    }
+                    if matches!(disposition, Disposition::Defer) && pending.attempts >= MAX_ATTEMPTS {
+                        disposition = Disposition::Reject;
+                    }
+                    match disposition {
+                        Disposition::Retry => {
+                            let _ = self.persist(&inner.file);
+                            return false;
+                        }
+                        Disposition::Defer => {
+                            tracing::warn!("[outbox] {} failed (attempt {}), trying later: {e}", item.id, pending.attempts);
+                            drained = false;
+                        }
+                        Disposition::Reject => {
+                            tracing::warn!("[outbox] Giving up on {}: {e}", item.id);
+                            let rejected = inner.file.pending.remove(idx);
+                            inner.file.failed.push(rejected);
+                            if inner.file.failed.len() > MAX_FAILED {
+                                inner.file.failed.remove(0);
+                            }
+                        }
+                    }
+                }
+            }
+            if let Err(e) = self.persist(&inner.file) {
+                tracing::error!("[outbox] Failed to save outbox: {e}");
+            }
+        }
+        drained
+    }
+}
+
+fn classify(error: &JaibberError) -> Disposition {
+    match error {
+        JaibberError::Api { status, .. } if matches!(*status, 401 | 429 | 502..=504) => Disposition::Retry,
+        JaibberError::Api { status, .. } if *status == 408 || *status >= 500 => Disposition::Defer,
+        JaibberError::Api { .. } => Disposition::Reject,
+        _ => Disposition::Retry,
+    }
+}
+
+/// Background flusher: runs on every enqueue, every `FLUSH_INTERVAL_SECS`,
+/// and backs off exponentially while the server is unreachable.
+pub fn start(outbox: Arc<Outbox>, api: Arc<ApiClient>) {
+    tokio::spawn(async move {
+        let mut backoff = FLUSH_INTERVAL_SECS;
+        loop {
+            let drained = if api.is_signed_in() { outbox.flush(&api).await } else { false };
+            let wait = if drained {
+                backoff = FLUSH_INTERVAL_SECS;
+                FLUSH_INTERVAL_SECS
+            } else {
+                let current = backoff;
+                backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+                current
+            };
+            tokio::select! {
+                _ = outbox.wake.notified() => backoff = FLUSH_INTERVAL_SECS,
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+            }
+        }
+    });
+}
@@ -0,0 +1,631 @@
+//! Multi-step agent pipelines, e.g. "plan with Claude API → implement with
+//! Codex CLI → review with Gemini CLI".
+//!
+//! A pipeline is a list of steps. Without any `dependsOn`, steps run in
+//! order; otherwise they form a DAG and independent steps run in parallel.
+//! Each step is a normal runtime run (so the usual `agent-chunk` events
+//! stream under the step's response ID), and prompts can reference earlier
+//! outputs: `{{input}}`, `{{steps.<id>.output}}` and `{{prev}}`. `{{prev}}`
+//! is the step's single dependency: the previous step in an ordered
+//! pipeline, or its only `dependsOn` entry in a DAG. Steps with no or several
+//! dependencies must name the step they mean.
+//!
+//! The run stops at the first failed step (other in-flight steps are
+//! cancelled). A step with `requiresApproval` waits for
+//! `approve_pipeline_step` before it starts; rejecting it stops the run.
+//!
+//! Events: `pipeline-step` for every step status change and
+//! `pipeline-status` for the run as a whole.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+use crate::error::JaibberError;
+use crate::runtime::{self, now_ms, EventSink, RunRequest};
+use crate::state::AppState;
+
+pub const PIPELINES_KEY: &str = "pipelines";
+/// Finished pipeline runs kept in memory.
+const MAX_FINISHED_RUNS: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineDef {
+    /// Generated on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// Default working directory for steps that don't set their own.
+    #[serde(default)]
+    pub project_dir: String,
+    pub steps: Vec<PipelineStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineStep {
+    /// Referenced by templates (`{{steps.<id>.output}}`) and `dependsOn`.
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// `claude`, `claude-api`, `claude-cli`, `codex`, `gemini`, `openclaw` or `custom`.
+    pub provider: String,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    pub prompt: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub project_dir: Option<String>,
+    /// Steps that must complete first. When no step declares any, the
+    /// pipeline runs strictly in list order.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Pause for human approval before this step starts.
+    #[serde(default)]
+    pub requires_approval: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StepStatus {
+    Pending,
+    AwaitingApproval,
+    Running,
+    Completed,
+    Failed,
+    Rejected,
+    Cancelled,
+    /// Never started because the run stopped earlier.
+    Skipped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PipelineStatus {
+    Running,
+    AwaitingApproval,
+    Completed,
+    Failed,
+    Rejected,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepState {
+    pub id: String,
+    pub status: StepStatus,
+    pub response_id: Option<String>,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineRun {
+    pub id: String,
+    pub pipeline_id: String,
+    pub pipeline_name: String,
+    pub input: String,
+    pub status: PipelineStatus,
+    pub steps: Vec<StepState>,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+/// Active and recent pipeline runs, plus pending approval gates.
+#[derive(Default)]
+pub struct PipelineRegistry {
+    runs: Mutex<HashMap<String, PipelineRun>>,
+    approvals: Mutex<HashMap<(String, String), oneshot::Sender<bool>>>,
+    cancels: Mutex<HashMap<String, oneshot::Sender<()>>>,
+}
+
+impl PipelineRegistry {
+    pub fn list(&self) -> Vec<PipelineRun> {
+        let mut runs: Vec<PipelineRun> = self.runs.lock().unwrap().values().cloned().collect();
+        runs.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+        runs
+    }
+
+    pub fn get(&self, run_id: &str) -> Option<PipelineRun> {
+        self.runs.lock().unwrap().get(run_id).cloned()
+    }
+
+    /// Resolve an approval gate. Returns false if the step isn't waiting.
+    pub fn approve(&self, run_id: &str, step_id: &str, approved: bool) -> bool {
+        let sender = self.approvals.lock().unwrap().remove(&(run_id.to_string(), step_id.to_string()));
+        sender.is_some_and(|tx| tx.send(approved).is_ok())
+    }
+
+    pub fn cancel(&self, run_id: &str) -> bool {
+        let sender = self.cancels.lock().unwrap().remove(run_id);
+        sender.is_some_and(|tx| tx.send(()).is_ok())
+    }
+
+    fn update<F: FnOnce(&mut PipelineRun)>(&self, run_id: &str, f: F) -> Option<PipelineRun> {
+        let mut runs = self.runs.lock().unwrap();
+        let run = runs.get_mut(run_id)?;
+        f(run);
+        Some(run.clone())
+    }
+
+    fn prune(&self) {
+        let mut runs = self.runs.lock().unwrap();
+        let mut finished: Vec<(String, u64)> = runs.values()
+            .filter_map(|r| r.finished_at.map(|f| (r.id.clone(), f)))
+            .collect();
+        if finished.len() <= MAX_FINISHED_RUNS {
+            return;
+        }
+        finished.sort_by_key(|(_, f)| *f);
+        for (id, _) in finished.iter().take(finished.len() - MAX_FINISHED_RUNS) {
+            runs.remove(id);
+        }
+    }
+}
+
+// ── Validation ────────────────────────────────────────────────────────
+
+/// Effective dependencies: explicit `dependsOn`, or the previous step when
+/// the pipeline is purely ordered.
+fn dependencies(def: &PipelineDef) -> HashMap<String, Vec<String>> {
+    let ordered = def.steps.iter().all(|s| s.depends_on.is_empty());
+    def.steps.iter().enumerate()
+        .map(|(i, step)| {
+            let deps = if ordered {
+                i.checked_sub(1).map(|p| vec![def.steps[p].id.clone()]).unwrap_or_default()
+            } else {
+                step.depends_on.clone()
+            };
+            (step.id.clone(), deps)
+        })
+        .collect()
+}
+
+/// Check step IDs, dependencies and acyclicity.
+pub fn validate(def: &PipelineDef) -> Result<(), JaibberError> {
+    let fail = |msg: String| Err(JaibberError::Other(msg));
+    if def.steps.is_empty() {
+        return fail("A pipeline needs at least one step".into());
+    }
+    let mut ids = HashSet::new();
+    for step in &def.steps {
+        if step.id.is_empty() || !ids.insert(step.id.as_str()) {
+            return fail(format!("Step IDs must be unique and non-empty (\"{}\")", step.id));
+        }
+        if step.prompt.trim().is_empty() {
+            return fail(format!("Step \"{}\" has an empty prompt", step.id));
+        }
+    }
+    let deps = dependencies(def);
+    for (id, step_deps) in &deps {
+        if let Some(missing) = step_deps.iter().find(|d| !ids.contains(d.as_str())) {
+            return fail(format!("Step \"{id}\" depends on unknown step \"{missing}\""));
+        }
+    }
+    for step in &def.steps {
+        let uses_prev = ["prev", "prev.output"].iter()
+            .any(|name| crate::prompt_template::references(&step.prompt, name));
+        if uses_prev && deps[&step.id].len() != 1 {
+            return fail(format!(
+                "Step \"{}\" uses {{{{prev}}}} but has {} dependencies; use {{{{steps.<id>.output}}}} instead",
+                step.id,
+                deps[&step.id].len(),
+            ));
+        }
+    }
+
+    // Kahn's algorithm: every step must become ready eventually
+    let mut done: HashSet<&str> = HashSet::new();
+    while done.len() < def.steps.len() {
+        let ready: Vec<&str> = def.steps.iter()
+            .map(|s| s.id.as_str())
+            .filter(|id| !done.contains(id) && deps[*id].iter().all(|d| done.contains(d.as_str())))
+            .collect();
+        if ready.is_empty() {
+            return fail("Pipeline steps contain a dependency cycle".into());
+        }
+        done.extend(ready);
+    }
+    Ok(())
+}
+
+// ── Execution ─────────────────────────────────────────────────────────
+
+/// Start a pipeline run in the background. Returns its ID.
+pub fn start(
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    def: PipelineDef,
+    input: String,
+) -> Result<String, JaibberError> {
+    validate(&def)?;
+    let run_id = uuid::Uuid::new_v4().to_string();
+    let run = PipelineRun {
+        id: run_id.clone(),
+        pipeline_id: def.id.clone(),
+        pipeline_name: def.name.clone(),
+        input: input.clone(),
+        status: PipelineStatus::Running,
+        steps: def.steps.iter().map(|s| StepState {
+            id: s.id.clone(),
+            status: StepStatus::Pending,
+            response_id: None,
+            output: None,
+            error: None,
+            started_at: None,
+            finished_at: None,
+        }).collect(),
+        started_at: now_ms(),
+        finished_at: None,
+    };
+    state.pipelines.runs.lock().unwrap().insert(run_id.clone(), run);
+    state.pipelines.prune();
+
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    state.pipelines.cancels.lock().unwrap().insert(run_id.clone(), cancel_tx);
+
+    let id = run_id.clone();
+    tokio::spawn(async move {
+        execute(state.clone(), sink.clone(), def, id.clone(), input, cancel_rx).await;
+        state.pipelines.cancels.lock().unwrap().remove(&id);
+    });
+    Ok(run_id)
+}
+
+type StepResult = (String, Result<String, StepError>);
+
+enum StepError {
+    Failed(String),
+    Rejected,
+}
+
+async fn execute(
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    def: PipelineDef,
+    run_id: String,
+    input: String,
+    mut cancel_rx: oneshot::Receiver<()>,
+) {
+    let deps = dependencies(&def);
+    let mut outputs: HashMap<String, String> = HashMap::new();
+    let mut started: HashSet<String> = HashSet::new();
+    let mut tasks: JoinSet<StepResult> = JoinSet::new();
+    emit_status(&state, sink.as_ref(), &run_id, PipelineStatus::Running);
+
+    let final_status = loop {
+        // Launch every step whose dependencies are satisfied
+        for step in &def.steps {
+            let ready = !started.contains(&step.id)
+                && deps[&step.id].iter().all(|d| outputs.contains_key(d));
+            if !ready {
+                continue;
+            }
+            started.insert(step.id.clone());
+            let prev = prev_output(&deps[&step.id], &outputs);
+            let step_dir = step.project_dir.as_deref().filter(|d| !d.is_empty()).unwrap_or(&def.project_dir);
+            let prompt = render_prompt(&step.prompt, step_dir, &input, &prev, &outputs);
+            tasks.spawn(run_step(
+                state.clone(),
+                sink.clone(),
+                run_id.clone(),
+                step.clone(),
+                def.project_dir.clone(),
+                prompt,
+            ));
+        }
+
+        if tasks.is_empty() {
+            break PipelineStatus::Completed;
+        }
+
+        let joined = tokio::select! {
+            joined = tasks.join_next() => joined,
+            _ = &mut cancel_rx => break PipelineStatus::Cancelled,
+        };
+        match joined {
+            Some(Ok((step_id, Ok(output)))) => {
+                outputs.insert(step_id, output);
+            }
+            Some(Ok((_, Err(StepError::Rejected)))) => break PipelineStatus::Rejected,
+            Some(Ok((step_id, Err(StepError::Failed(e))))) => {
+                tracing::warn!("[pipeline] Step {step_id} failed: {e}");
+                break PipelineStatus::Failed;
+            }
+            Some(Err(e)) => {
+                tracing::error!("[pipeline] Step task panicked: {e}");
+                break PipelineStatus::Failed;
+            }
+            None => break PipelineStatus::Completed,
+        }
+    };
+
+    // Stop anything still in flight and mark untouched steps
+    tasks.abort_all();
+    let run = state.pipelines.update(&run_id, |run| {
+        for step in run.steps.iter_mut() {
+            match step.status {
+                StepStatus::Running => {
+                    if let Some(ref rid) = step.response_id {
+                        state.runs.cancel(rid);
+                    }
+                    step.status = StepStatus::Cancelled;
+                    step.finished_at = Some(now_ms());
+                }
+                StepStatus::AwaitingApproval => step.status = StepStatus::Cancelled,
+                StepStatus::Pending => step.status = StepStatus::Skipped,
+                _ => {}
+            }
+        }
+        run.status = final_status;
+        run.finished_at = Some(now_ms());
+    });
+    state.pipelines.approvals.lock().unwrap().retain(|(rid, _), _| rid != &run_id);
+    if let Some(run) = run {
+        for step in run.steps.iter().filter(|s| matches!(s.status, StepStatus::Cancelled | StepStatus::Skipped)) {
+            emit_step(sink.as_ref(), &run_id, step);
+        }
+    }
+    emit_status(&state, sink.as_ref(), &run_id, final_status);
+}
+
+async fn run_step(
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    run_id: String,
+    step: PipelineStep,
+    default_dir: String,
+    prompt: String,
+) -> StepResult {
+    let id = step.id.clone();
+    let set = |f: &dyn Fn(&mut StepState)| {
+        let run = state.pipelines.update(&run_id, |run| {
+            if let Some(s) = run.steps.iter_mut().find(|s| s.id == id) {
+                f(s);
+            }
+        });
+        if let Some(s) = run.as_ref().and_then(|r| r.steps.iter().find(|s| s.id == id)) {
+            emit_step(sink.as_ref(), &run_id, s);
+        }
+    };
+
+    if step.requires_approval {
+        let (tx, rx) = oneshot::channel();
+        state.pipelines.approvals.lock().unwrap().insert((run_id.clone(), id.clone()), tx);
+        set(&|s| s.status = StepStatus::AwaitingApproval);
+        emit_status(&state, sink.as_ref(), &run_id, PipelineStatus::AwaitingApproval);
+        sink.emit_event("pipeline-approval-required", json!({
+            "pipelineRunId": run_id,
+            "stepId": id,
+            "stepName": step.name,
+            "prompt": prompt,
+        }));
+        if !rx.await.unwrap_or(false) {
+            set(&|s| {
+                s.status = StepStatus::Rejected;
+                s.finished_at = Some(now_ms());
+            });
+            return (id, Err(StepError::Rejected));
+        }
+        emit_status(&state, sink.as_ref(), &run_id, PipelineStatus::Running);
+    }
+
+    let (provider, use_api) = match step.provider.as_str() {
+        "claude-api" => ("claude".to_string(), Some(true)),
+        "claude-cli" => ("claude".to_string(), Some(false)),
+        other => (other.to_string(), None),
+    };
+    let request = RunRequest {
+        prompt,
+        project_dir: step.project_dir.clone().filter(|d| !d.is_empty()).unwrap_or(default_dir),
+        system_prompt: step.system_prompt.clone(),
+        agent_provider: Some(provider),
+        custom_command: step.custom_command.clone(),
+        use_api,
+        ..Default::default()
+    };
+
+    let rid = match runtime::start_run(&state, request, "pipeline", Some(sink.clone())).await {
+        Ok(rid) => rid,
+        Err(e) => {
+            let msg = e.to_string();
+            set(&|s| {
+                s.status = StepStatus::Failed;
+                s.error = Some(msg.clone());
+                s.finished_at = Some(now_ms());
+            });
+            return (id, Err(StepError::Failed(msg)));
+        }
+    };
+    set(&|s| {
+        s.status = StepStatus::Running;
+        s.response_id = Some(rid.clone());
+        s.started_at = Some(now_ms());
+    });
+
+    match runtime::collect_output(&state, &rid).await {
+        Ok(output) => {
+            set(&|s| {
+                s.status = StepStatus::Completed;
+                s.output = Some(output.clone());
+                s.finished_at = Some(now_ms());
+            });
+            (id, Ok(output))
+        }
+        Err(e) => {
+            set(&|s| {
+                s.status = StepStatus::Failed;
+                s.error = Some(e.clone());
+                s.finished_at = Some(now_ms());
+            });
+            (id, Err(StepError::Failed(e)))
+        }
+    }
+}
+
+fn emit_step(sink: &dyn EventSink, run_id: &str, step: &StepState) {
+    let mut payload = serde_json::to_value(step).unwrap_or_default();
+    payload["pipelineRunId"] = json!(run_id);
+    sink.emit_event("pipeline-step", payload);
+}
+
+fn emit_status(state: &AppState, sink: &dyn EventSink, run_id: &str, status: PipelineStatus) {
+    state.pipelines.update(run_id, |run| run.status = status);
+    sink.emit_event("pipeline-status", json!({ "pipelineRunId": run_id, "status": status }));
+}
+
+/// Output for `{{prev}}`: the step's only dependency (`validate` rejects
+/// `{{prev}}` elsewhere).
+fn prev_output(deps: &[String], outputs: &HashMap<String, String>) -> String {
+    match deps {
+        [only] => outputs.get(only).cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Render a step prompt with `{{input}}`, `{{prev}}` and
+/// `{{steps.<id>.output}}`.
+fn render_prompt(
+    template: &str,
+    project_dir: &str,
+    input: &str,
+    prev: &str,
+    outputs: &HashMap<String, String>,
+) -> String {
+    crate::prompt_template::render_with(template, project_dir, &|key| match key {
+        "input" => Some(input.to_string()),
+        "prev" | "prev.output" => Some(prev.to_string()),
+        _ => key.strip_prefix("steps.")
+            .and_then(|k| k.strip_suffix(".output"))
+            .and_then(|id| outputs.get(id).cloned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(steps: serde_json::Value) -> PipelineDef {
+        serde_json::from_value(json!({ "name": "Test", "steps": steps })).unwrap()
+    }
+
+    #[test]
+    fn prev_is_the_single_dependency() {
+        let ordered = pipeline(json!([
+            { "id": "plan", "provider": "claude", "prompt": "Plan {{input}}" },
+            { "id": "build", "provider": "codex", "prompt": "Build {{prev}}" },
+        ]));
+        assert!(validate(&ordered).is_ok());
+
+        let dag = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A" },
+            { "id": "b", "provider": "claude", "prompt": "B" },
+            { "id": "c", "provider": "claude", "prompt": "Use {{prev}}", "dependsOn": ["a"] },
+        ]));
+        assert!(validate(&dag).is_ok());
+        let deps = dependencies(&dag);
+        let outputs = HashMap::from([("a".to_string(), "from a".to_string()), ("b".to_string(), "from b".to_string())]);
+        assert_eq!(prev_output(&deps["c"], &outputs), "from a");
+    }
+
+    #[test]
+    fn rejects_prev_without_a_single_dependency() {
+        let first = pipeline(json!([
+            { "id": "plan", "provider": "claude", "prompt": "Plan {{prev}}" },
+        ]));
+        assert!(validate(&first).is_err());
+
+        let join = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A" },
+            { "id": "b", "provider": "claude", "prompt": "B" },
+            { "id": "c", "provider": "claude", "prompt": "{{prev.output}}", "dependsOn": ["a", "b"] },
+        ]));
+        assert!(validate(&join).is_err());
+
+        // Next to a dependsOn step, a plain step has no implicit predecessor
+        let mixed = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A" },
+            { "id": "b", "provider": "claude", "prompt": "{{prev}}" },
+            { "id": "c", "provider": "claude", "prompt": "C", "dependsOn": ["a"] },
+        ]));
+        assert!(validate(&mixed).is_err());
+    }
+
+    #[test]
+    fn ordered_steps_depend_on_their_predecessor() {
+        let def = pipeline(json!([
+            { "id": "plan", "provider": "claude", "prompt": "P" },
+            { "id": "build", "provider": "codex", "prompt": "B" },
+            { "id": "review", "provider": "gemini", "prompt": "R" },
+        ]));
+        let deps = dependencies(&def);
+        assert!(deps["plan"].is_empty());
+        assert_eq!(deps["build"], ["plan"]);
+        assert_eq!(deps["review"], ["build"]);
+    }
+
+    #[test]
+    fn dag_steps_use_only_declared_dependencies() {
+        let def = pipeline(json!([
+            { "id": "lint", "provider": "claude", "prompt": "L" },
+            { "id": "test", "provider": "claude", "prompt": "T" },
+            { "id": "report", "provider": "claude", "prompt": "R", "dependsOn": ["lint", "test"] },
+        ]));
+        assert!(validate(&def).is_ok());
+        let deps = dependencies(&def);
+        assert!(deps["lint"].is_empty());
+        assert!(deps["test"].is_empty());
+        assert_eq!(deps["report"], ["lint", "test"]);
+    }
+
+    #[test]
+    fn rejects_invalid_graphs() {
+        let unknown = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A", "dependsOn": ["missing"] },
+        ]));
+        assert!(validate(&unknown).unwrap_err().to_string().contains("unknown step \"missing\""));
+
+        let cycle = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A", "dependsOn": ["c"] },
+            { "id": "b", "provider": "claude", "prompt": "B", "dependsOn": ["a"] },
+            { "id": "c", "provider": "claude", "prompt": "C", "dependsOn": ["b"] },
+        ]));
+        assert!(validate(&cycle).unwrap_err().to_string().contains("cycle"));
+
+        let self_loop = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A", "dependsOn": ["a"] },
+        ]));
+        assert!(validate(&self_loop).is_err());
+
+        let duplicate = pipeline(json!([
+            { "id": "a", "provider": "claude", "prompt": "A" },
+            { "id": "a", "provider": "claude", "prompt": "B" },
+        ]));
+        assert!(validate(&duplicate).is_err());
+
+        assert!(validate(&pipeline(json!([]))).is_err());
+        assert!(validate(&pipeline(json!([{ "id": "a", "provider": "claude", "prompt": "  " }]))).is_err());
+    }
+
+    #[test]
+    fn renders_step_outputs() {
+        let outputs = HashMap::from([("plan".to_string(), "1. Do it".to_string())]);
+        let prompt = render_prompt(
+            "Task: {{input}}\nPlan: {{steps.plan.output}}\nPrev: {{prev}}\nMissing: {{steps.nope.output}}",
+            "",
+            "Add login",
+            "last",
+            &outputs,
+        );
+        // Unknown variables are left as written
+        assert_eq!(prompt, "Task: Add login\nPlan: 1. Do it\nPrev: last\nMissing: {{steps.nope.output}}");
+    }
+}
@@ -0,0 +1,301 @@
+//! Prompt templating. System prompts are rendered in the backend right
+//! before a run spawns its CLI or calls an API; webhook, pipeline, schedule
+//! and file-watch prompts go through `render_with`, with their own variables.
+//!
+//! Syntax:
+//! - `{{var}}` — a variable (letters, digits, `_`, `.` and `-`); unknown
+//!   names are left untouched.
+//! - `{{#if var}}…{{else}}…{{/if}}` — truthy when the variable is non-empty.
+//!   `{{#unless var}}…{{/unless}}` is the inverse. Blocks can nest.
+//! - `{{include "CONTRIBUTING.md"}}` — a file from the project directory
+//!   (missing files render as nothing; paths may not leave the project).
+//!
+//! System prompt variables: `project_name`, `project_dir`, `git_branch`, `last_commit`,
+//! `dirty_files`, `date`, `time`, `machine_name`, `agent_name`, `provider`.
+//! Git variables are only computed when the template references them.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Largest file `{{include}}` will inline.
+const MAX_INCLUDE_BYTES: usize = 64 * 1024;
+/// Cap on the `dirty_files` list.
+const MAX_DIRTY_FILES: usize = 50;
+const GIT_VARS: &[&str] = &["git_branch", "last_commit", "dirty_files"];
+
+/// Values available to a template.
+#[derive(Debug, Default)]
+pub struct TemplateContext {
+    pub project_dir: String,
+    pub machine_name: String,
+    pub agent_name: String,
+    pub provider: String,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(String),
+    Include(String),
+    If { var: String, negate: bool, then: Vec<Node>, otherwise: Vec<Node> },
+}
+
+/// True if the text uses any template syntax.
+pub fn is_template(text: &str) -> bool {
+    text.contains("{{")
+}
+
+/// Render a system prompt. Rendering never fails: malformed tags are kept
+/// as text.
+pub async fn render(template: &str, ctx: &TemplateContext) -> String {
+    let vars = variables(template, ctx).await;
+    render_with(template, &ctx.project_dir, &|name| vars.get(name).cloned())
+}
+
+/// Render a template with the caller's own variables; `{{include}}` reads
+/// from `project_dir`. Names `vars` returns `None` for are left untouched.
+/// Values are inserted as-is, never parsed as template syntax.
+pub fn render_with(template: &str, project_dir: &str, vars: &dyn Fn(&str) -> Option<String>) -> String {
+    let nodes = parse(template);
+    let mut out = String::with_capacity(template.len());
+    render_nodes(&nodes, vars, Path::new(project_dir), &mut out);
+    out
+}
+
+/// True if the template uses `name` as a variable or condition.
+pub fn references(template: &str, name: &str) -> bool {
+    fn walk(nodes: &[Node], name: &str) -> bool {
+        nodes.iter().any(|node| match node {
+            Node::Var(var) => var == name,
+            Node::If { var, then, otherwise, .. } => var == name || walk(then, name) || walk(otherwise, name),
+            Node::Text(_) | Node::Include(_) => false,
+        })
+    }
+    walk(&parse(template), name)
+}
+
+async fn variables(template: &str, ctx: &TemplateContext) -> HashMap<&'static str, String> {
+    let now = chrono::Local::now();
+    let project_name = Path::new(&ctx.project_dir)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut vars = HashMap::from([
+        ("project_name", project_name),
+        ("project_dir", ctx.project_dir.clone()),
+        ("date", now.format("%Y-%m-%d").to_string()),
+        ("time", now.format("%H:%M").to_string()),
+        ("machine_name", ctx.machine_name.clone()),
+        ("agent_name", ctx.agent_name.clone()),
+        ("provider", ctx.provider.clone()),
+    ]);
+
+    if !ctx.project_dir.is_empty() && GIT_VARS.iter().any(|v| template.contains(v)) {
+        let dir = &ctx.project_dir;
+        vars.insert("git_branch", git(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).await);
+        vars.insert("last_commit", git(dir, &["log", "-1", "--format=%h %s (%an, %ar)"]).await);
+        let status = git(dir, &["status", "--porcelain"]).await;
+        let mut dirty: Vec<&str> = status.lines().map(|l| l.get(3..).unwrap_or(l)).collect();
+        if dirty.len() > MAX_DIRTY_FILES {
+            let more = dirty.len() - MAX_DIRTY_FILES;
+            dirty.truncate(MAX_DIRTY_FILES);
+            vars.insert("dirty_files", format!("{}\n… and {more} more", dirty.join("\n")));
+        } else {
+            vars.insert("dirty_files", dirty.join("\n"));
+        }
+    }
+    vars
+}
+
+/// Run a git command in `dir`; empty on any failure (not a repo, no git).
+async fn git(dir: &str, args: &[&str]) -> String {
+    tokio::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .await
+        .ok()
+        .filter(|o| o.status.success())
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+        .unwrap_or_default()
+}
+
+// ── Parsing ───────────────────────────────────────────────────────────
+
+fn parse(template: &str) -> Vec<Node> {
+    let mut rest = template;
+    let (nodes, _) = parse_block(&mut rest, &[]);
+    nodes
+}
+
+/// Parse until one of `terminators` (e.g. `else`, `/if`) or the end.
+/// Returns the nodes and the terminator that ended the block.
+fn parse_block(rest: &mut &str, terminators: &[&str]) -> (Vec<Node>, Option<String>) {
+    let mut nodes = Vec::new();
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let Some(len) = rest[start..].find("}}") else {
+            nodes.push(Node::Text(rest[start..].to_string()));
+            *rest = "";
+            return (nodes, None);
+        };
+        let raw = &rest[start..start + len + 2];
+        let tag = raw[2..raw.len() - 2].trim();
+        *rest = &rest[start + len + 2..];
+
+        if terminators.contains(&tag) {
+            return (nodes, Some(tag.to_string()));
+        }
+        if let Some((negate, var)) = tag.strip_prefix("#if ").map(|v| (false, v))
+            .or_else(|| tag.strip_prefix("#unless ").map(|v| (true, v)))
+        {
+            let close = if negate { "/unless" } else { "/if" };
+            let (then, end) = parse_block(rest, &["else", close]);
+            let otherwise = if end.as_deref() == Some("else") {
+                parse_block(rest, &[close]).0
+            } else {
+                Vec::new()
+            };
+            nodes.push(Node::If { var: var.trim().to_string(), negate, then, otherwise });
+        } else if let Some(path) = tag.strip_prefix("include ") {
+            nodes.push(Node::Include(path.trim().trim_matches('"').to_string()));
+        } else if !tag.is_empty() && tag.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-')) {
+            nodes.push(Node::Var(tag.to_string()));
+        } else {
+            nodes.push(Node::Text(raw.to_string()));
+        }
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_string()));
+        *rest = "";
+    }
+    (nodes, None)
+}
+
+// ── Rendering ─────────────────────────────────────────────────────────
+
+fn render_nodes(nodes: &[Node], vars: &dyn Fn(&str) -> Option<String>, project_dir: &Path, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(name) => match vars(name) {
+                Some(value) => out.push_str(&value),
+                None => {
+                    out.push_str("{{");
+                    out.push_str(name);
+                    out.push_str("}}");
+                }
+            },
+            Node::Include(path) => out.push_str(&read_include(project_dir, path)),
+            Node::If { var, negate, then, otherwise } => {
+                let truthy = vars(var).is_some_and(|v| !v.trim().is_empty());
+                let branch = if truthy != *negate { then } else { otherwise };
+                render_nodes(branch, vars, project_dir, out);
+            }
+        }
+    }
+}
+
+/// Read a file inside the project directory. Anything that resolves outside
+/// it (absolute paths, `..`, symlinks) renders as nothing.
+fn read_include(project_dir: &Path, path: &str) -> String {
+    if project_dir.as_os_str().is_empty() {
+        return String::new();
+    }
+    let Ok(root) = project_dir.canonicalize() else { return String::new() };
+    let Ok(full) = root.join(path).canonicalize() else { return String::new() };
+    if !full.starts_with(&root) {
+        tracing::warn!("[template] Refusing include outside the project: {path}");
+        return String::new();
+    }
+    match std::fs::read(&full) {
+        Ok(bytes) => {
+            let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_INCLUDE_BYTES)]).to_string();
+            if bytes.len() > MAX_INCLUDE_BYTES {
+                format!("{text}\n… (truncated)")
+            } else {
+                text
+            }
+        }
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh project directory with `docs/guide.md`, removed on drop.
+    struct Project(PathBuf);
+
+    impl Project {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("jaibber-template-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(dir.join("docs")).unwrap();
+            std::fs::write(dir.join("docs/guide.md"), "Be nice.").unwrap();
+            Self(dir.canonicalize().unwrap())
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn includes_files_inside_the_project() {
+        let project = Project::new();
+        assert_eq!(read_include(&project.0, "docs/guide.md"), "Be nice.");
+        assert_eq!(read_include(&project.0, "./docs/../docs/guide.md"), "Be nice.");
+        assert_eq!(read_include(&project.0, "missing.md"), "");
+        assert_eq!(read_include(Path::new(""), "docs/guide.md"), "");
+    }
+
+    #[test]
+    fn refuses_includes_outside_the_project() {
+        let project = Project::new();
+        let outside = Project::new();
+        let escape = format!("../{}/docs/guide.md", outside.0.file_name().unwrap().to_string_lossy());
+        assert_eq!(read_include(&project.0, &escape), "");
+        let absolute = outside.0.join("docs/guide.md");
+        assert_eq!(read_include(&project.0, absolute.to_str().unwrap()), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_includes_through_symlinks() {
+        let project = Project::new();
+        let outside = Project::new();
+        std::os::unix::fs::symlink(outside.0.join("docs/guide.md"), project.0.join("link.md")).unwrap();
+        assert_eq!(read_include(&project.0, "link.md"), "");
+    }
+
+    #[test]
+    fn renders_caller_variables_and_blocks() {
+        let vars = |key: &str| match key {
+            "input" => Some("hello".to_string()),
+            "steps.plan.output" => Some("{{input}}".to_string()),
+            "empty" => Some(String::new()),
+            _ => None,
+        };
+        let render = |template: &str| render_with(template, "", &vars);
+        assert_eq!(render("Say {{ input }}"), "Say hello");
+        assert_eq!(render("{{steps.plan.output}}"), "{{input}}");
+        assert_eq!(render("{{unknown}} {{ not a var }}"), "{{unknown}} {{ not a var }}");
+        assert_eq!(render("{{#if input}}yes{{else}}no{{/if}}"), "yes");
+        assert_eq!(render("{{#if empty}}yes{{else}}no{{/if}}"), "no");
+        assert_eq!(render("{{#unless unknown}}none{{/unless}}"), "none");
+        assert_eq!(render("{{include \"x.md\"}}"), "");
+    }
+
+    #[test]
+    fn finds_variable_references() {
+        assert!(references("Changed:\n{{paths}}", "paths"));
+        assert!(references("{{#if diff}}{{diff}}{{/if}}", "diff"));
+        assert!(!references("{{pathsx}} {{include \"paths\"}}", "paths"));
+    }
+}
@@ -0,0 +1,210 @@
+//! Retries for the streaming HTTP providers (Anthropic API, OpenClaw).
+//!
+//! Rate limits (429), overload (529) and 5xx responses are retried with
+//! exponential backoff and jitter, preferring the server's own hint:
+//! `Retry-After`, or for Anthropic the reset time of whichever
+//! `anthropic-ratelimit-*` budget ran out. Each wait is announced with an
+//! `agent-retrying` event so the UI can show why nothing is streaming yet.
+//!
+//! Callers only retry a request before it has streamed anything — once the
+//! first token is out, a retry would duplicate output.
+//!
+//! `retry_after` is also used by the Jaibber API client.
+
+use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use crate::runtime::EventSink;
+
+const MAX_RETRIES: u32 = 5;
+const BASE_BACKOFF_MS: u64 = 1_000;
+const MAX_BACKOFF_MS: u64 = 30_000;
+/// Total time a single request may spend waiting between attempts.
+const MAX_TOTAL_WAIT: Duration = Duration::from_secs(120);
+/// Rate limit budgets reported by the Anthropic API.
+const ANTHROPIC_LIMITS: &[&str] = &["requests", "tokens", "input-tokens", "output-tokens"];
+
+/// Whether a response status is worth retrying.
+pub fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == 529 || status.is_server_error()
+}
+
+/// How long the server asked us to wait, if it said.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = header(headers, "retry-after-ms").and_then(|v| v.parse::<u64>().ok()) {
+        return Some(Duration::from_millis(ms));
+    }
+    if let Some(value) = header(headers, reqwest::header::RETRY_AFTER.as_str()) {
+        if let Ok(secs) = value.parse::<u64>() {
+            return Some(Duration::from_secs(secs));
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            return Some(until(date.with_timezone(&chrono::Utc)));
+        }
+    }
+    // Anthropic: wait for the latest reset among the exhausted budgets
+    ANTHROPIC_LIMITS.iter()
+        .filter(|limit| header(headers, &format!("anthropic-ratelimit-{limit}-remaining")) == Some("0"))
+        .filter_map(|limit| header(headers, &format!("anthropic-ratelimit-{limit}-reset")))
+        .filter_map(|reset| chrono::DateTime::parse_from_rfc3339(reset).ok())
+        .map(|reset| until(reset.with_timezone(&chrono::Utc)))
+        .max()
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim)
+}
+
+fn until(time: chrono::DateTime<chrono::Utc>) -> Duration {
+    (time - chrono::Utc::now()).to_std().unwrap_or_default()
+}
+
+/// Exponential backoff with jitter: 1s, 2s, 4s ... (capped), 50–100% of each.
+fn backoff(attempt: u32) -> Duration {
+    let base = (BASE_BACKOFF_MS * 2u64.pow(attempt.saturating_sub(1))).min(MAX_BACKOFF_MS);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64)
+        .unwrap_or(0);
+    Duration::from_millis(base / 2 + nanos % (base / 2 + 1))
+}
+
+/// Tracks attempts and time spent waiting for one request.
+pub struct Retrier<'a> {
+    service: &'a str,
+    response_id: &'a str,
+    sink: &'a dyn EventSink,
+    attempt: u32,
+    waited: Duration,
+}
+
+impl<'a> Retrier<'a> {
+    pub fn new(service: &'a str, response_id: &'a str, sink: &'a dyn EventSink) -> Self {
+        Self { service, response_id, sink, attempt: 0, waited: Duration::ZERO }
+    }
+
+    /// Wait before the next attempt, using the server's hint when given.
+    /// Returns false (without waiting) once retries or the wait budget are
+    /// used up — the caller should then report `reason` as the error.
+    pub async fn wait(&mut self, hint: Option<Duration>, reason: &str) -> bool {
+        if self.attempt >= MAX_RETRIES {
+            return false;
+        }
+        self.attempt += 1;
+        let delay = hint.unwrap_or_else(|| backoff(self.attempt));
+        if self.waited + delay > MAX_TOTAL_WAIT {
+            tracing::warn!("[retry] {} asked to wait {}ms; over the retry budget", self.service, delay.as_millis());
+            return false;
+        }
+        self.waited += delay;
+
+        tracing::warn!(
+            "[retry] {} attempt {}/{} in {}ms: {reason}",
+            self.service, self.attempt + 1, MAX_RETRIES + 1, delay.as_millis(),
+        );
+        self.sink.emit_event("agent-retrying", serde_json::json!({
+            "responseId": self.response_id,
+            "attempt": self.attempt,
+            "maxAttempts": MAX_RETRIES,
+            "delayMs": delay.as_millis() as u64,
+            "reason": reason,
+        }));
+        tokio::time::sleep(delay).await;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use reqwest::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, String)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn reads_seconds_milliseconds_and_dates() {
+        assert_eq!(retry_after(&headers(&[("retry-after", " 7 ".into())])), Some(Duration::from_secs(7)));
+        // The millisecond hint is more precise and wins
+        let both = headers(&[("retry-after", "7".into()), ("retry-after-ms", "1500".into())]);
+        assert_eq!(retry_after(&both), Some(Duration::from_millis(1500)));
+
+        let in_a_minute = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let wait = retry_after(&headers(&[("retry-after", in_a_minute)])).unwrap();
+        assert!(wait > Duration::from_secs(55) && wait <= Duration::from_secs(60));
+
+        let past = (chrono::Utc::now() - chrono::Duration::seconds(60)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&[("retry-after", past)])), Some(Duration::ZERO));
+
+        assert_eq!(retry_after(&headers(&[("retry-after", "soon".into())])), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn waits_for_the_latest_exhausted_anthropic_budget() {
+        let reset = |secs: i64| (chrono::Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339();
+        let map = headers(&[
+            ("anthropic-ratelimit-requests-remaining", "0".into()),
+            ("anthropic-ratelimit-requests-reset", reset(10)),
+            ("anthropic-ratelimit-tokens-remaining", "0".into()),
+            ("anthropic-ratelimit-tokens-reset", reset(30)),
+            // Not exhausted, so its later reset doesn't matter
+            ("anthropic-ratelimit-output-tokens-remaining", "500".into()),
+            ("anthropic-ratelimit-output-tokens-reset", reset(90)),
+        ]);
+        let wait = retry_after(&map).unwrap();
+        assert!(wait > Duration::from_secs(25) && wait <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn retries_rate_limits_overload_and_server_errors() {
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::from_u16(529).unwrap()));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable_status(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        for attempt in 1..=10 {
+            let base = (BASE_BACKOFF_MS * 2u64.pow(attempt - 1)).min(MAX_BACKOFF_MS);
+            let delay = backoff(attempt).as_millis() as u64;
+            assert!(delay >= base / 2 && delay <= base, "attempt {attempt}: {delay}ms");
+        }
+    }
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<serde_json::Value>>);
+
+    impl EventSink for Events {
+        fn emit_event(&self, _event: &str, payload: serde_json::Value) {
+            self.0.lock().unwrap().push(payload);
+        }
+    }
+
+    #[tokio::test]
+    async fn stops_after_max_retries_or_budget() {
+        let events = Events::default();
+        let mut retrier = Retrier::new("test", "r1", &events);
+        for _ in 0..MAX_RETRIES {
+            assert!(retrier.wait(Some(Duration::ZERO), "overloaded").await);
+        }
+        assert!(!retrier.wait(Some(Duration::ZERO), "overloaded").await);
+        {
+            let announced = events.0.lock().unwrap();
+            assert_eq!(announced.len(), MAX_RETRIES as usize);
+            assert_eq!(announced[0]["attempt"], 1);
+            assert_eq!(announced[0]["reason"], "overloaded");
+        }
+
+        let mut retrier = Retrier::new("test", "r2", &events);
+        assert!(!retrier.wait(Some(MAX_TOTAL_WAIT + Duration::from_secs(1)), "rate limited").await);
+    }
+}
@@ -0,0 +1,1172 @@
+//! Agent run runtime — drives a single streaming agent invocation (CLI process,
+//! direct Claude API, or OpenClaw HTTP) and routes its events to a sink.
+//!
+//! Every run is tracked in the `RunRegistry`, regardless of who started it
+//! (chat UI, local API, ...), so runs can be listed, observed and cancelled.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use futures_util::StreamExt;
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::broadcast;
+use crate::state::{AppState, AttachmentInfo};
+use crate::context::ContextMessage;
+use crate::error::JaibberError;
+use crate::agent_providers::{ModelConfig, ProviderConfig, ProviderKind, extract_text_from_line, is_auth_error};
+
+/// Maximum number of finished runs kept around for `list` / late subscribers.
+const MAX_FINISHED_RUNS: usize = 100;
+/// Maximum number of events buffered per run for replay to late subscribers.
+/// Past this the oldest events are dropped; the terminal event is always kept.
+const MAX_RUN_HISTORY: usize = 4000;
+/// Event sent to subscribers in place of events they can no longer receive
+/// (dropped from the history, or skipped because the subscriber lagged).
+pub const EVENTS_MISSED: &str = "agent-events-missed";
+
+// ── Event sinks ───────────────────────────────────────────────────────
+
+/// Destination for run events. Payloads use the same schema as the Tauri
+/// events the webview listens to (`agent-chunk`, `agent-session`, ...).
+pub trait EventSink: Send + Sync + 'static {
+    fn emit_event(&self, event: &str, payload: serde_json::Value);
+}
+
+impl EventSink for tauri::Window {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        let _ = tauri::Emitter::emit(self, event, payload);
+    }
+}
+
+impl EventSink for tauri::AppHandle {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        let _ = tauri::Emitter::emit(self, event, payload);
+    }
+}
+
+/// Sink that records every event into the registry and forwards it to an
+/// optional inner sink (e.g. the window that started the run).
+struct RegistrySink {
+    response_id: String,
+    registry: Arc<RunRegistry>,
+    inner: Option<Arc<dyn EventSink>>,
+}
+
+impl EventSink for RegistrySink {
+    fn emit_event(&self, event: &str, payload: serde_json::Value) {
+        if let Some(ref inner) = self.inner {
+            inner.emit_event(event, payload.clone());
+        }
+        self.registry.record(&self.response_id, RunEvent {
+            event: event.to_string(),
+            payload,
+        });
+    }
+}
+
+/// Emit a standard `agent-chunk` event.
+pub fn emit_chunk(sink: &dyn EventSink, response_id: &str, chunk: &str, done: bool, error: Option<&str>) {
+    sink.emit_event("agent-chunk", serde_json::json!({
+        "responseId": response_id,
+        "chunk": chunk,
+        "done": done,
+        "error": error,
+    }));
+}
+
+/// Emit an `agent-thinking` event with a piece of the model's reasoning.
+/// Kept apart from `agent-chunk` so it never ends up in the answer text.
+pub fn emit_thinking(sink: &dyn EventSink, response_id: &str, chunk: &str) {
+    sink.emit_event("agent-thinking", serde_json::json!({
+        "responseId": response_id,
+        "chunk": chunk,
+    }));
+}
+
+/// Token usage reported by a provider at the end of a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Usage {
+    /// Uncached input tokens.
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    /// Input tokens served from the prompt cache.
+    pub cache_read_tokens: u64,
+    /// Input tokens written to the prompt cache.
+    pub cache_write_tokens: u64,
+    /// Reported by the provider, or estimated from the model's list price.
+    pub cost_usd: Option<f64>,
+}
+
+impl Usage {
+    /// Parse an Anthropic-style `usage` object (`input_tokens`, `output_tokens`,
+    /// `cache_read_input_tokens`, `cache_creation_input_tokens`).
+    pub fn from_api(usage: &serde_json::Value) -> Self {
+        let tokens = |key: &str| usage.get(key).and_then(|v| v.as_u64()).unwrap_or(0);
+        Self {
+            input_tokens: tokens("input_tokens"),
+            output_tokens: tokens("output_tokens"),
+            cache_read_tokens: tokens("cache_read_input_tokens"),
+            cache_write_tokens: tokens("cache_creation_input_tokens"),
+            cost_usd: None,
+        }
+    }
+
+    /// Add the token counts of another request (e.g. one tool-use round).
+    pub fn accumulate(&mut self, other: &Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+    }
+}
+
+/// Emit an `agent-usage` event. Must be sent before the final chunk, since
+/// observers stop listening at the terminal event.
+pub fn emit_usage(sink: &dyn EventSink, response_id: &str, usage: &Usage) {
+    let mut payload = serde_json::to_value(usage).unwrap_or_default();
+    payload["responseId"] = serde_json::json!(response_id);
+    sink.emit_event("agent-usage", payload);
+}
+
+// ── Run registry ──────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Public metadata about a run, returned by `list_agent_runs` and the local API.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunInfo {
+    pub response_id: String,
+    pub provider: String,
+    pub project_dir: String,
+    /// Who started the run: `"chat"`, `"api"`, ...
+    pub source: String,
+    pub status: RunStatus,
+    /// Unix timestamp in milliseconds.
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    /// Number of characters streamed so far.
+    pub output_chars: usize,
+    pub error: Option<String>,
+}
+
+/// A single event emitted by a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunEvent {
+    pub event: String,
+    pub payload: serde_json::Value,
+}
+
+impl RunEvent {
+    /// True if this event ends the run (final chunk or error).
+    pub fn is_terminal(&self) -> bool {
+        self.event == "agent-chunk"
+            && (self.payload.get("done").and_then(|d| d.as_bool()) == Some(true)
+                || self.payload.get("error").is_some_and(|e| !e.is_null()))
+    }
+
+    /// For an `EVENTS_MISSED` event, an error describing the gap. Consumers
+    /// that assemble the output text must fail rather than return it short.
+    pub fn missed_error(&self) -> Option<String> {
+        if self.event != EVENTS_MISSED {
+            return None;
+        }
+        let count = self.payload.get("count").and_then(|c| c.as_u64()).unwrap_or(0);
+        Some(format!("Missed {count} events of the run; its output is incomplete"))
+    }
+}
+
+struct RunEntry {
+    info: RunInfo,
+    history: VecDeque<RunEvent>,
+    /// Number of events dropped from the front of `history`, i.e. the
+    /// sequence number of its first event.
+    dropped: usize,
+    /// Live events with their sequence numbers.
+    tx: broadcast::Sender<(usize, RunEvent)>,
+    abort: Option<tokio::task::AbortHandle>,
+}
+
+impl RunEntry {
+    fn push(&mut self, event: RunEvent) {
+        let seq = self.dropped + self.history.len();
+        if self.history.len() >= MAX_RUN_HISTORY {
+            self.history.pop_front();
+            self.dropped += 1;
+        }
+        self.history.push_back(event.clone());
+        let _ = self.tx.send((seq, event));
+    }
+}
+
+/// In-memory registry of active and recently finished runs.
+pub struct RunRegistry {
+    runs: Mutex<HashMap<String, RunEntry>>,
+}
+
+impl RunRegistry {
+    pub fn new() -> Self {
+        Self { runs: Mutex::new(HashMap::new()) }
+    }
+
+    fn register(&self, info: RunInfo) -> Result<(), JaibberError> {
+        let mut runs = self.runs.lock().unwrap();
+        if runs.get(&info.response_id).is_some_and(|r| r.info.status == RunStatus::Running) {
+            return Err(JaibberError::Other(format!(
+                "A run with id {} is already in progress", info.response_id
+            )));
+        }
+        let (tx, _) = broadcast::channel(256);
+        runs.insert(info.response_id.clone(), RunEntry {
+            info,
+            history: VecDeque::new(),
+            dropped: 0,
+            tx,
+            abort: None,
+        });
+        prune_finished(&mut runs);
+        Ok(())
+    }
+
+    fn set_abort(&self, response_id: &str, abort: tokio::task::AbortHandle) {
+        if let Some(entry) = self.runs.lock().unwrap().get_mut(response_id) {
+            match entry.info.status {
+                RunStatus::Running => entry.abort = Some(abort),
+                // Cancelled before the task handle was registered
+                RunStatus::Cancelled => abort.abort(),
+                _ => {}
+            }
+        }
+    }
+
+    fn record(&self, response_id: &str, event: RunEvent) {
+        let mut runs = self.runs.lock().unwrap();
+        let Some(entry) = runs.get_mut(response_id) else { return };
+        // Finished or cancelled: the terminal event is already recorded
+        if entry.info.status != RunStatus::Running {
+            return;
+        }
+
+        if event.event == "agent-chunk" {
+            if let Some(chunk) = event.payload.get("chunk").and_then(|c| c.as_str()) {
+                entry.info.output_chars += chunk.chars().count();
+            }
+        }
+        if event.is_terminal() {
+            let error = event.payload.get("error").and_then(|e| e.as_str()).map(|s| s.to_string());
+            entry.info.status = if error.is_some() { RunStatus::Failed } else { RunStatus::Completed };
+            entry.info.error = error;
+            entry.info.finished_at = Some(now_ms());
+            entry.abort = None;
+        }
+        entry.push(event);
+    }
+
+    /// List all tracked runs, most recent first.
+    pub fn list(&self) -> Vec<RunInfo> {
+        let runs = self.runs.lock().unwrap();
+        let mut list: Vec<RunInfo> = runs.values().map(|r| r.info.clone()).collect();
+        list.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+        list
+    }
+
+    pub fn get(&self, response_id: &str) -> Option<RunInfo> {
+        self.runs.lock().unwrap().get(response_id).map(|r| r.info.clone())
+    }
+
+    /// The events from sequence number `from` on and a subscription to the
+    /// rest, taken under one lock so no event is missed or duplicated.
+    fn subscribe_from(&self, response_id: &str, from: usize) -> Option<Snapshot> {
+        let runs = self.runs.lock().unwrap();
+        let entry = runs.get(response_id)?;
+        let start = from.max(entry.dropped);
+        Some(Snapshot {
+            start,
+            events: entry.history.iter().skip(start - entry.dropped).cloned().collect(),
+            rx: entry.tx.subscribe(),
+        })
+    }
+
+    /// Stream of the run's events: replays history, then follows live events
+    /// until the terminal event. None if the run is unknown.
+    ///
+    /// A subscriber that falls behind the live channel catches up from the
+    /// history. Events that are gone from both are reported as a single
+    /// `EVENTS_MISSED` event (`{ "responseId", "count" }`) in their place.
+    pub fn events(self: &Arc<Self>, response_id: &str) -> Option<BoxStream<'static, RunEvent>> {
+        let Snapshot { start, events, rx } = self.subscribe_from(response_id, 0)?;
+        let mut cursor = Cursor {
+            registry: self.clone(),
+            response_id: response_id.to_string(),
+            next: 0,
+            pending: VecDeque::new(),
+            rx,
+            finished: false,
+        };
+        cursor.queue(start, events);
+
+        Some(futures_util::stream::unfold(cursor, |mut cursor| async move {
+            if cursor.finished {
+                return None;
+            }
+            let event = loop {
+                if let Some(event) = cursor.pending.pop_front() {
+                    break event;
+                }
+                match cursor.rx.recv().await {
+                    // Already replayed from history
+                    Ok((seq, _)) if seq < cursor.next => continue,
+                    Ok((seq, event)) if seq == cursor.next => {
+                        cursor.next += 1;
+                        break event;
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        if !cursor.resync() {
+                            return None;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            };
+            cursor.finished = event.is_terminal();
+            Some((event, cursor))
+        }).boxed())
+    }
+
+    /// Cancel a running run. Aborting the task drops the agent process
+    /// (spawned with `kill_on_drop`) or the in-flight HTTP stream, and the
+    /// terminal event is recorded right away so observers stop waiting.
+    /// Returns false if the run is unknown or already finished.
+    pub fn cancel(&self, response_id: &str) -> bool {
+        let mut runs = self.runs.lock().unwrap();
+        let Some(entry) = runs.get_mut(response_id) else { return false };
+        if entry.info.status != RunStatus::Running {
+            return false;
+        }
+        entry.info.status = RunStatus::Cancelled;
+        entry.info.finished_at = Some(now_ms());
+        if let Some(abort) = entry.abort.take() {
+            abort.abort();
+        }
+        entry.push(RunEvent {
+            event: "agent-chunk".to_string(),
+            payload: serde_json::json!({
+                "responseId": response_id,
+                "chunk": "",
+                "done": true,
+                "error": "Run cancelled",
+            }),
+        });
+        true
+    }
+}
+
+impl Default for RunRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A run's recorded events from sequence number `start` on, plus a
+/// subscription to the ones that follow.
+struct Snapshot {
+    start: usize,
+    events: Vec<RunEvent>,
+    rx: broadcast::Receiver<(usize, RunEvent)>,
+}
+
+/// Read position of one `RunRegistry::events` stream.
+struct Cursor {
+    registry: Arc<RunRegistry>,
+    response_id: String,
+    /// Sequence number of the next event to yield.
+    next: usize,
+    pending: VecDeque<RunEvent>,
+    rx: broadcast::Receiver<(usize, RunEvent)>,
+    finished: bool,
+}
+
+impl Cursor {
+    /// Re-read everything from `next` on after falling behind. False if the
+    /// run is no longer tracked.
+    fn resync(&mut self) -> bool {
+        let Some(Snapshot { start, events, rx }) = self.registry.subscribe_from(&self.response_id, self.next) else {
+            return false;
+        };
+        self.rx = rx;
+        self.queue(start, events);
+        true
+    }
+
+    /// Queue `events` (starting at sequence number `start`), preceded by a
+    /// missed-events notice if `start` is past `next`.
+    fn queue(&mut self, start: usize, events: Vec<RunEvent>) {
+        if start > self.next {
+            self.pending.push_back(RunEvent {
+                event: EVENTS_MISSED.to_string(),
+                payload: serde_json::json!({
+                    "responseId": self.response_id,
+                    "count": start - self.next,
+                }),
+            });
+        }
+        self.next = start + events.len();
+        self.pending.extend(events);
+    }
+}
+
+/// Drop the oldest finished runs once more than `MAX_FINISHED_RUNS` are kept.
+fn prune_finished(runs: &mut HashMap<String, RunEntry>) {
+    let mut finished: Vec<(u64, String)> = runs.values()
+        .filter(|r| r.info.status != RunStatus::Running)
+        .map(|r| (r.info.started_at, r.info.response_id.clone()))
+        .collect();
+    if finished.len() <= MAX_FINISHED_RUNS {
+        return;
+    }
+    finished.sort();
+    let excess = finished.len() - MAX_FINISHED_RUNS;
+    for (_, id) in finished.into_iter().take(excess) {
+        runs.remove(&id);
+    }
+}
+
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// ── Run requests ──────────────────────────────────────────────────────
+
+/// Everything needed to start a streaming agent run. Mirrors the parameters
+/// of the `run_agent_stream` command.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunRequest {
+    pub prompt: String,
+    #[serde(default)]
+    pub project_dir: String,
+    /// Generated when empty.
+    #[serde(default)]
+    pub response_id: String,
+    #[serde(default)]
+    pub system_prompt: String,
+    #[serde(default)]
+    pub conversation_context: String,
+    #[serde(default)]
+    pub agent_provider: Option<String>,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentInfo>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub continue_session: bool,
+    /// Claude only: `Some(true)` requires the direct API, `Some(false)` forces
+    /// the CLI, `None` uses the API whenever an Anthropic key is configured.
+    #[serde(default)]
+    pub use_api: Option<bool>,
+    /// Display name of the agent, for `{{agent_name}}` in system prompts.
+    #[serde(default)]
+    pub agent_name: Option<String>,
+    /// Structured history. When set, it replaces `conversation_context`.
+    #[serde(default)]
+    pub context_messages: Vec<ContextMessage>,
+    /// Token budget for the history (defaults to the settings value).
+    #[serde(default)]
+    pub context_budget: Option<usize>,
+    /// Conversation the history belongs to; keys the rolling summary cache.
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Extended thinking budget in tokens (Claude only); None disables it.
+    #[serde(default)]
+    pub thinking_budget: Option<u32>,
+    /// The agent's model and sampling settings.
+    #[serde(default)]
+    pub model_config: ModelConfig,
+    /// Overrides for this run only; fields set here win over `model_config`.
+    #[serde(default)]
+    pub model_override: ModelConfig,
+}
+
+/// Start a streaming agent run in the background and return its response ID.
+///
+/// Events are recorded in the run registry and forwarded to `sink` (if any).
+/// Errors that happen before the run starts (validation, spawn failure) are
+/// returned directly; everything after is reported as `agent-chunk` events.
+///
+/// Auth fallback: if the CLI fails with an auth error and a fallback API key
+/// is configured for this provider, automatically retries with the key set
+/// as an env var and emits an `"agent-auth-fallback"` event so the frontend
+/// can show a subtle notice.
+pub async fn start_run(
+    state: &Arc<AppState>,
+    mut request: RunRequest,
+    source: &str,
+    sink: Option<Arc<dyn EventSink>>,
+) -> Result<String, JaibberError> {
+    if request.response_id.is_empty() {
+        request.response_id = uuid::Uuid::new_v4().to_string();
+    }
+    let provider_str = request.agent_provider.clone().unwrap_or_else(|| "claude".to_string());
+    let model_config = request.model_config.merged(&request.model_override);
+    let model_name = model_config.model_name().map(|m| m.to_string());
+    let provider = ProviderConfig {
+        kind: ProviderKind::from_str(&provider_str),
+        custom_command: request.custom_command.clone(),
+        model: model_name.clone(),
+    };
+
+    // OpenClaw uses HTTP — doesn't need a project_dir. CLI providers do.
+    if provider.kind != ProviderKind::OpenClaw && request.project_dir.is_empty() {
+        return Err(JaibberError::Other("project_dir must not be empty".into()));
+    }
+
+    // Read fallback keys from settings
+    let settings = state.settings.read().await;
+    let fallback_key = settings.fallback_key_for(&provider_str).map(|s| s.to_string());
+    let machine_name = settings.machine_name.clone();
+    let context_budget = request.context_budget.unwrap_or(settings.context_token_budget);
+    let tool_policy = settings.api_tools.clone();
+    let attachment_retention_hours = settings.attachment_retention_hours;
+    drop(settings);
+
+    // Keep the newest history within the budget so the question isn't pushed out
+    let mut history_note = None;
+    if !request.context_messages.is_empty() {
+        let thread_id = request.thread_id.clone().unwrap_or_default();
+        let summary = crate::history_summary::summary_for(
+            state, &thread_id, &request.context_messages, context_budget, &provider_str, model_name.as_deref(),
+        ).await;
+        let budget = match summary {
+            Some(_) => context_budget.saturating_sub(crate::history_summary::SUMMARY_RESERVE_TOKENS),
+            None => context_budget,
+        };
+        let (kept, dropped) = crate::context::select_within_budget(
+            &request.context_messages, budget, &provider_str, model_name.as_deref(),
+        );
+        history_note = crate::context::history_note(dropped, summary.as_deref());
+        request.conversation_context = crate::context::render_history(kept, history_note.as_deref());
+        request.context_messages = kept.to_vec();
+    } else if !request.conversation_context.is_empty() {
+        request.conversation_context = crate::context::trim_to_budget(
+            &request.conversation_context, context_budget, &provider_str, model_name.as_deref(),
+        );
+    }
+
+    if crate::prompt_template::is_template(&request.system_prompt) {
+        let ctx = crate::prompt_template::TemplateContext {
+            project_dir: request.project_dir.clone(),
+            machine_name,
+            agent_name: request.agent_name.clone().unwrap_or_default(),
+            provider: provider_str.to_lowercase(),
+        };
+        request.system_prompt = crate::prompt_template::render(&request.system_prompt, &ctx).await;
+    }
+
+    let claude_api_key = match (&provider.kind, request.use_api) {
+        (ProviderKind::Claude, Some(false)) => None,
+        (ProviderKind::Claude, Some(true)) => Some(fallback_key.clone().ok_or(JaibberError::NoApiKey)?),
+        (ProviderKind::Claude, None) => fallback_key.clone(),
+        _ => None,
+    };
+
+    // The direct API sends attachments as content blocks. CLI agents get
+    // them saved under the project directory; OpenClaw gets them as text.
+    let rid = request.response_id.clone();
+    let mut staged = None;
+    let prompt = if claude_api_key.is_some() || request.attachments.is_empty() {
+        request.prompt.clone()
+    } else {
+        let loader = crate::attachments::AttachmentLoader::new(state, &request.project_dir);
+        let section = if provider.kind == ProviderKind::OpenClaw {
+            loader.prompt_section(&request.attachments).await
+        } else {
+            let files = loader.stage(&rid, &request.attachments, attachment_retention_hours).await;
+            let prompt = files.prompt.clone();
+            staged = Some(files);
+            prompt
+        };
+        format!("{section}\n\n{}", request.prompt)
+    };
+    let full_prompt = crate::context::with_context(&request.conversation_context, &prompt);
+
+    // ── OpenClaw: HTTP path (no CLI process) ─────────────────────────
+    let (sink, task): (Arc<dyn EventSink>, BoxFuture<'static, ()>) =
+        if provider.kind == ProviderKind::OpenClaw {
+            let oc_config = crate::openclaw::discover_openclaw()
+                .map_err(JaibberError::Other)?;
+            let sink = register_run(state, &request, &provider_str, source, sink)?;
+            let task_sink = sink.clone();
+            let sys = request.system_prompt.clone();
+            let rid = rid.clone();
+            (sink, Box::pin(async move {
+                if let Err(e) = crate::openclaw::stream_openclaw(
+                    &oc_config, &sys, &full_prompt, &model_config, &rid, task_sink.as_ref(),
+                ).await {
+                    emit_chunk(task_sink.as_ref(), &rid, "", false, Some(&e));
+                }
+            }))
+        }
+        // ── Claude: direct API (supports multimodal when API key is set) ──
+        else if let Some(api_key) = claude_api_key {
+            let interactive = sink.is_some();
+            let sink = register_run(state, &request, &provider_str, source, sink)?;
+            let task_sink = sink.clone();
+            let req = request.clone();
+            let rid = rid.clone();
+            let tools = (!req.project_dir.is_empty()).then(|| crate::claude_tools::ToolContext {
+                project_dir: req.project_dir.clone().into(),
+                policy: tool_policy,
+                approvals: state.tool_approvals.clone(),
+                interactive,
+            });
+            let loader = crate::attachments::AttachmentLoader::new(state, &req.project_dir);
+            (sink, Box::pin(async move {
+                let api_request = crate::claude_api::ApiRequest {
+                    system_prompt: &req.system_prompt,
+                    prompt: &req.prompt,
+                    conversation_context: &req.conversation_context,
+                    history: &req.context_messages,
+                    history_note: history_note.as_deref(),
+                    attachments: &req.attachments,
+                    loader: &loader,
+                    tools: tools.as_ref(),
+                    thinking_budget: req.thinking_budget,
+                    model: &model_config,
+                };
+                if let Err(e) = crate::claude_api::stream_claude_api(
+                    &api_key, &api_request, &rid, task_sink.as_ref(),
+                ).await {
+                    emit_chunk(task_sink.as_ref(), &rid, "", false, Some(&e));
+                }
+            }))
+        }
+        // ── CLI providers (no API key → Claude falls through here too) ───
+        else {
+            // Images go to the CLIs that take them natively; Claude gets them
+            // inline on stdin, so they must fit the API's size limit
+            let images: Vec<&crate::attachments::StagedImage> = staged.iter()
+                .flat_map(|s| &s.images)
+                .filter(|image| match provider.kind {
+                    ProviderKind::Claude => image.bytes.len() <= crate::attachments::MAX_INLINE_IMAGE_BYTES,
+                    ProviderKind::Codex => true,
+                    _ => false,
+                })
+                .collect();
+            let pcmd = provider.build_stream_cmd(
+                !request.system_prompt.is_empty(),
+                request.session_id.as_deref(),
+                request.continue_session,
+                images.len(),
+            );
+            // Claude Code reads its thinking budget from the environment
+            let bash_command = match (&provider.kind, request.thinking_budget) {
+                (ProviderKind::Claude, Some(budget)) => {
+                    format!("export MAX_THINKING_TOKENS={budget}\n{}", pcmd.bash_command)
+                }
+                _ => pcmd.bash_command,
+            };
+            let stdin = (provider.kind == ProviderKind::Claude && !images.is_empty()).then(|| {
+                let images: Vec<(&str, &[u8])> = images.iter().map(|i| (i.mime.as_str(), i.bytes.as_slice())).collect();
+                crate::agent_providers::claude_stream_input(&full_prompt, &images)
+            });
+            let cli = CliRun {
+                bash_command,
+                project_dir: request.project_dir.clone(),
+                full_prompt,
+                system_prompt: request.system_prompt.clone(),
+                images: images.iter().map(|i| i.path.clone()).collect(),
+                stdin,
+                provider_kind: provider.kind.clone(),
+                fallback_env_var: pcmd.api_key_env_var.map(|s| s.to_string()),
+                fallback_key,
+                reauth_hint: provider.reauth_hint().to_string(),
+                install_hint: provider.install_hint().to_string(),
+            };
+            // With no retention, the run's files go as soon as it's done
+            let cleanup = staged.and_then(|s| s.dir).filter(|_| attachment_retention_hours == 0);
+
+            // Spawn before registering so spawn failures surface as command errors.
+            // No API key on first attempt — use CLI's own auth.
+            let child = spawn_agent_process(&cli, None)?;
+            let sink = register_run(state, &request, &provider_str, source, sink)?;
+            let task_sink = sink.clone();
+            let rid = rid.clone();
+            (sink, Box::pin(async move {
+                stream_cli_process(&cli, child, &rid, task_sink.as_ref()).await;
+                if let Some(dir) = cleanup {
+                    if let Err(e) = tokio::fs::remove_dir_all(&dir).await {
+                        tracing::warn!("[runtime] Could not remove {}: {e}", dir.display());
+                    }
+                }
+            }))
+        };
+
+    let handle = tokio::spawn(task);
+    state.runs.set_abort(&rid, handle.abort_handle());
+
+    // Supervisor: report panics and cancellations as a final chunk
+    let rid_supervisor = rid.clone();
+    tokio::spawn(async move {
+        if let Err(e) = handle.await {
+            let error = if e.is_cancelled() {
+                "Run cancelled".to_string()
+            } else {
+                format!("Agent task panicked: {e}")
+            };
+            emit_chunk(sink.as_ref(), &rid_supervisor, "", true, Some(&error));
+        }
+    });
+
+    Ok(rid)
+}
+
+/// Start a run and wait for it to finish, returning the full streamed text.
+/// Used by callers outside the chat UI that need the final answer.
+pub async fn run_to_completion(
+    state: &Arc<AppState>,
+    request: RunRequest,
+    source: &str,
+) -> Result<String, String> {
+    let rid = start_run(state, request, source, None).await.map_err(|e| e.to_string())?;
+    collect_output(state, &rid).await
+}
+
+/// Wait for an already started run to finish and return its streamed text.
+pub async fn collect_output(state: &Arc<AppState>, rid: &str) -> Result<String, String> {
+    let mut events = state.runs.events(rid)
+        .ok_or_else(|| format!("Run {rid} is no longer tracked"))?;
+
+    let mut output = String::new();
+    while let Some(event) = events.next().await {
+        if let Some(error) = event.missed_error() {
+            return Err(error);
+        }
+        if event.event != "agent-chunk" {
+            continue;
+        }
+        if let Some(chunk) = event.payload.get("chunk").and_then(|c| c.as_str()) {
+            output.push_str(chunk);
+        }
+        if let Some(error) = event.payload.get("error").and_then(|e| e.as_str()) {
+            return Err(error.to_string());
+        }
+    }
+    Ok(output)
+}
+
+/// Register the run and wrap `sink` so every event is also recorded.
+fn register_run(
+    state: &Arc<AppState>,
+    request: &RunRequest,
+    provider: &str,
+    source: &str,
+    sink: Option<Arc<dyn EventSink>>,
+) -> Result<Arc<dyn EventSink>, JaibberError> {
+    state.runs.register(RunInfo {
+        response_id: request.response_id.clone(),
+        provider: provider.to_lowercase(),
+        project_dir: request.project_dir.clone(),
+        source: source.to_string(),
+        status: RunStatus::Running,
+        started_at: now_ms(),
+        finished_at: None,
+        output_chars: 0,
+        error: None,
+    })?;
+    Ok(Arc::new(RegistrySink {
+        response_id: request.response_id.clone(),
+        registry: state.runs.clone(),
+        inner: sink,
+    }))
+}
+
+// ── CLI process streaming ─────────────────────────────────────────────
+
+/// Everything needed to (re)spawn a CLI agent for a streaming run.
+struct CliRun {
+    bash_command: String,
+    project_dir: String,
+    full_prompt: String,
+    system_prompt: String,
+    /// Image paths for `$JAIBBER_IMAGE_<n>`, relative to the project.
+    images: Vec<String>,
+    /// Written to the agent's stdin (Claude stream-json input).
+    stdin: Option<String>,
+    provider_kind: ProviderKind,
+    fallback_env_var: Option<String>,
+    fallback_key: Option<String>,
+    reauth_hint: String,
+    install_hint: String,
+}
+
+/// Read stream lines from a spawned CLI agent and emit chunks.
+async fn stream_cli_process(
+    cli: &CliRun,
+    mut child: tokio::process::Child,
+    rid: &str,
+    sink: &dyn EventSink,
+) {
+    use tokio::time::{timeout, Duration};
+
+    let Some(stdout) = child.stdout.take() else {
+        emit_chunk(sink, rid, "", false, Some("Failed to capture stdout"));
+        return;
+    };
+    let stderr_pipe = child.stderr.take();
+
+    // Collect stderr in parallel so we can include it in error messages
+    let stderr_handle = stderr_pipe.map(|pipe| {
+        tokio::spawn(async move {
+            let mut buf = String::new();
+            let reader = BufReader::new(pipe);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                buf.push_str(&line);
+                buf.push('\n');
+                if buf.len() > 4000 { break; } // cap stderr collection
+            }
+            buf
+        })
+    });
+
+    let reader = BufReader::new(stdout);
+    let mut lines = reader.lines();
+    let mut got_output = false;
+    let mut emitted_session_id = false;
+    let idle_timeout_initial = Duration::from_secs(300);
+    let idle_timeout_after_output = Duration::from_secs(60);
+
+    loop {
+        let wait = if got_output { idle_timeout_after_output } else { idle_timeout_initial };
+        match timeout(wait, lines.next_line()).await {
+            Ok(Ok(Some(line))) => {
+                let parsed = extract_text_from_line(&cli.provider_kind, &line);
+
+                // Emit session ID once when first discovered in stream output
+                if !emitted_session_id {
+                    if let Some(ref sid) = parsed.session_id {
+                        emitted_session_id = true;
+                        sink.emit_event("agent-session", serde_json::json!({
+                            "responseId": rid,
+                            "sessionId": sid,
+                        }));
+                    }
+                }
+
+                if let Some(ref usage) = parsed.usage {
+                    emit_usage(sink, rid, usage);
+                }
+
+                if !parsed.thinking.is_empty() {
+                    emit_thinking(sink, rid, &parsed.thinking);
+                }
+
+                if !parsed.text.is_empty() {
+                    got_output = true;
+                    emit_chunk(sink, rid, &parsed.text, false, None);
+                }
+            }
+            Ok(Ok(None)) => break, // EOF — process finished
+            Ok(Err(_)) => break,   // Read error
+            Err(_) => {
+                // Idle timeout fired — kill the lingering process
+                let _ = child.kill().await;
+                if got_output {
+                    emit_chunk(sink, rid, "", true, None);
+                } else {
+                    emit_chunk(sink, rid, "", false, Some("Agent timed out (no output for 5 minutes)"));
+                }
+                return;
+            }
+        }
+    }
+
+    // Collect stderr for error reporting
+    let stderr_text = match stderr_handle {
+        Some(handle) => handle.await.unwrap_or_default(),
+        None => String::new(),
+    };
+
+    // Wait for exit status with a 30-second timeout
+    match timeout(Duration::from_secs(30), child.wait()).await {
+        Ok(Ok(status)) => {
+            if status.success() || got_output {
+                emit_chunk(sink, rid, "", true, None);
+            } else {
+                // Process failed with no output — check for auth error
+                if is_auth_error(&stderr_text) {
+                    if let (Some(ref env_var), Some(ref key)) = (&cli.fallback_env_var, &cli.fallback_key) {
+                        // ── AUTH FALLBACK: retry with API key ──
+                        sink.emit_event("agent-auth-fallback", serde_json::json!({
+                            "responseId": rid,
+                            "provider": format!("{:?}", cli.provider_kind),
+                            "message": format!(
+                                "CLI auth expired — using fallback API key. {}",
+                                cli.reauth_hint
+                            ),
+                        }));
+
+                        retry_with_fallback(cli, env_var, key, rid, sink).await;
+                        return;
+                    }
+                }
+
+                // Not an auth error, or no fallback available
+                let code = status.code().map(|c| c.to_string()).unwrap_or_else(|| "?".into());
+                let err_detail = if stderr_text.contains("command not found") || stderr_text.contains("not recognized") {
+                    cli.install_hint.to_string()
+                } else if is_auth_error(&stderr_text) {
+                    // Auth error but no fallback key configured
+                    format!(
+                        "Agent auth expired (exit code {code}). {} or add a fallback API key in Settings.\n{}",
+                        cli.reauth_hint,
+                        stderr_text.trim()
+                    )
+                } else if stderr_text.trim().is_empty() {
+                    format!("Agent process exited with code {code}")
+                } else {
+                    format!("Agent process exited with code {code}\n{}", stderr_text.trim())
+                };
+                emit_chunk(sink, rid, "", false, Some(&err_detail));
+            }
+        }
+        _ => {
+            let _ = child.kill().await;
+            if got_output {
+                emit_chunk(sink, rid, "", true, None);
+            } else {
+                emit_chunk(sink, rid, "", false, Some("Agent process timed out"));
+            }
+        }
+    }
+}
+
+/// Helper: spawn an agent CLI process with the given configuration.
+/// The child is killed when dropped so that cancelling a run stops the agent.
+fn spawn_agent_process(
+    cli: &CliRun,
+    api_key_env: Option<(&str, &str)>,
+) -> Result<tokio::process::Child, JaibberError> {
+    let mut cmd = tokio::process::Command::new("bash");
+    cmd.arg("-c")
+       .arg(&cli.bash_command)
+       .current_dir(&cli.project_dir)
+       .env("JAIBBER_PROMPT", &cli.full_prompt)
+       .env("JAIBBER_SYSTEM", &cli.system_prompt)
+       .stdout(std::process::Stdio::piped())
+       .stderr(std::process::Stdio::piped())
+       .kill_on_drop(true);
+
+    for (i, path) in cli.images.iter().enumerate() {
+        cmd.env(format!("JAIBBER_IMAGE_{}", i + 1), path);
+    }
+    if cli.stdin.is_some() {
+        cmd.stdin(std::process::Stdio::piped());
+    }
+    if let Some((var, key)) = api_key_env {
+        cmd.env(var, key);
+    }
+
+    let mut child = cmd.spawn()
+        .map_err(|e| JaibberError::Shell(format!("Failed to spawn bash: {e}")))?;
+    // Write the input and close stdin so the agent knows it's complete
+    if let (Some(mut pipe), Some(input)) = (child.stdin.take(), cli.stdin.clone()) {
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            if let Err(e) = pipe.write_all(input.as_bytes()).await {
+                tracing::warn!("[runtime] Could not write agent stdin: {e}");
+            }
+        });
+    }
+    Ok(child)
+}
+
+/// Retry a streaming agent invocation with a fallback API key.
+/// This is called when the first attempt failed with an auth error.
+async fn retry_with_fallback(
+    cli: &CliRun,
+    env_var: &str,
+    api_key: &str,
+    response_id: &str,
+    sink: &dyn EventSink,
+) {
+    use tokio::time::{timeout, Duration};
+
+    let spawn_result = spawn_agent_process(cli, Some((env_var, api_key)));
+
+    let mut child = match spawn_result {
+        Ok(c) => c,
+        Err(e) => {
+            emit_chunk(sink, response_id, "", false, Some(&format!("Auth fallback retry failed to spawn: {e}")));
+            return;
+        }
+    };
+
+    let stdout = match child.stdout.take() {
+        Some(s) => s,
+        None => {
+            emit_chunk(sink, response_id, "", false, Some("Auth fallback retry: failed to capture stdout"));
+            return;
+        }
+    };
+
+    let reader = BufReader::new(stdout);
+    let mut lines = reader.lines();
+    let mut got_output = false;
+    let idle_timeout_initial = Duration::from_secs(300);
+    let idle_timeout_after_output = Duration::from_secs(60);
+
+    loop {
+        let wait = if got_output { idle_timeout_after_output } else { idle_timeout_initial };
+        match timeout(wait, lines.next_line()).await {
+            Ok(Ok(Some(line))) => {
+                let parsed = extract_text_from_line(&cli.provider_kind, &line);
+                if let Some(ref usage) = parsed.usage {
+                    emit_usage(sink, response_id, usage);
+                }
+                if !parsed.thinking.is_empty() {
+                    emit_thinking(sink, response_id, &parsed.thinking);
+                }
+                if !parsed.text.is_empty() {
+                    got_output = true;
+                    emit_chunk(sink, response_id, &parsed.text, false, None);
+                }
+            }
+            Ok(Ok(None)) => break,
+            Ok(Err(_)) => break,
+            Err(_) => {
+                let _ = child.kill().await;
+                if got_output {
+                    emit_chunk(sink, response_id, "", true, None);
+                } else {
+                    emit_chunk(sink, response_id, "", false, Some("Agent timed out on fallback retry"));
+                }
+                return;
+            }
+        }
+    }
+
+    match timeout(Duration::from_secs(30), child.wait()).await {
+        Ok(Ok(status)) => {
+            if status.success() || got_output {
+                emit_chunk(sink, response_id, "", true, None);
+            } else {
+                emit_chunk(sink, response_id, "", false, Some("Agent failed even with fallback API key"));
+            }
+        }
+        _ => {
+            let _ = child.kill().await;
+            if got_output {
+                emit_chunk(sink, response_id, "", true, None);
+            } else {
+                emit_chunk(sink, response_id, "", false, Some("Agent process timed out on fallback retry"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn start(state: &AppState, rid: &str) {
+        state.runs.register(RunInfo {
+            response_id: rid.to_string(),
+            provider: "claude".into(),
+            project_dir: String::new(),
+            source: "test".into(),
+            status: RunStatus::Running,
+            started_at: now_ms(),
+            finished_at: None,
+            output_chars: 0,
+            error: None,
+        }).unwrap();
+    }
+
+    fn chunk(state: &AppState, rid: &str, text: &str, done: bool) {
+        state.runs.record(rid, RunEvent {
+            event: "agent-chunk".into(),
+            payload: serde_json::json!({ "responseId": rid, "chunk": text, "done": done, "error": null }),
+        });
+    }
+
+    #[tokio::test]
+    async fn cancel_wakes_output_waiters() {
+        let state = Arc::new(AppState::new());
+        start(&state, "r1");
+        let task = tokio::spawn(std::future::pending::<()>());
+        state.runs.set_abort("r1", task.abort_handle());
+        chunk(&state, "r1", "partial", false);
+
+        let waiter = tokio::spawn({
+            let state = state.clone();
+            async move { collect_output(&state, "r1").await }
+        });
+        tokio::task::yield_now().await;
+        assert!(state.runs.cancel("r1"));
+
+        let result = tokio::time::timeout(Duration::from_secs(5), waiter).await.unwrap().unwrap();
+        assert_eq!(result, Err("Run cancelled".to_string()));
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(state.runs.get("r1").unwrap().status, RunStatus::Cancelled);
+
+        // Late events from the aborted task are ignored; replay still ends
+        chunk(&state, "r1", "late", true);
+        let replay = tokio::time::timeout(Duration::from_secs(5), collect_output(&state, "r1")).await.unwrap();
+        assert_eq!(replay, Err("Run cancelled".to_string()));
+    }
+
+    #[tokio::test]
+    async fn long_history_keeps_the_terminal_event() {
+        let state = Arc::new(AppState::new());
+        start(&state, "r2");
+        for _ in 0..MAX_RUN_HISTORY + 10 {
+            chunk(&state, "r2", "x", false);
+        }
+        chunk(&state, "r2", "", true);
+        assert_eq!(state.runs.get("r2").unwrap().status, RunStatus::Completed);
+
+        let events: Vec<RunEvent> = state.runs.events("r2").unwrap().collect().await;
+        assert_eq!(events[0].event, EVENTS_MISSED);
+        assert_eq!(events[0].payload["count"], 11);
+        assert!(events.last().unwrap().is_terminal());
+        assert_eq!(events.len(), MAX_RUN_HISTORY + 1);
+
+        let result = collect_output(&state, "r2").await;
+        assert!(result.unwrap_err().contains("Missed 11 events"));
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_catch_up_from_history() {
+        let state = Arc::new(AppState::new());
+        start(&state, "r3");
+        let events = state.runs.events("r3").unwrap();
+        // More than the live channel holds, before the subscriber reads any
+        for i in 0..1000 {
+            chunk(&state, "r3", &format!("{i},"), false);
+        }
+        chunk(&state, "r3", "", true);
+
+        let events: Vec<RunEvent> = events.collect().await;
+        assert_eq!(events.len(), 1001);
+        assert!(events.iter().all(|e| e.missed_error().is_none()));
+        let expected: String = (0..1000).map(|i| format!("{i},")).collect();
+        assert_eq!(collect_output(&state, "r3").await.unwrap(), expected);
+    }
+}
@@ -0,0 +1,410 @@
+//! Scheduled (cron-style) agent runs — e.g. nightly dependency audits or a
+//! morning "summarize yesterday's commits" report.
+//!
+//! Jobs and their run history live in the store (`scheduled_jobs`,
+//! `scheduled_job_history`). A background loop started at app setup checks
+//! once per tick for due jobs and runs them through the normal streaming
+//! runtime. Missed runs while the app was closed are not replayed.
+//!
+//! Run events are emitted app-wide (`agent-chunk` with the run's response ID)
+//! plus `scheduled-run-started` / `scheduled-run-finished`. The result is
+//! posted to the job's project through the outbox, so it lands in the chat
+//! even when no window is open.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use crate::api_client::NewMessage;
+use crate::error::JaibberError;
+use crate::local_store;
+use crate::prompt_template;
+use crate::runtime::{self, RunRequest};
+use crate::state::AppState;
+
+const JOBS_KEY: &str = "scheduled_jobs";
+const HISTORY_KEY: &str = "scheduled_job_history";
+/// Entries kept in the run history, across all jobs.
+const MAX_HISTORY: usize = 200;
+/// Output stored per history entry (the full text goes to the channel).
+const MAX_HISTORY_OUTPUT: usize = 20_000;
+const TICK_SECS: u64 = 20;
+
+/// A scheduled agent run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledJob {
+    /// Generated on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// Standard 5-field cron (`min hour dom month dow`) or 6/7-field with seconds/years.
+    /// Evaluated in the machine's local timezone.
+    pub cron: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Target project (and its chat channel) for the result.
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub project_dir: String,
+    #[serde(default)]
+    pub agent_provider: Option<String>,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    #[serde(default)]
+    pub agent_name: Option<String>,
+    /// Prompt template. Supports `{{date}}`, `{{time}}`, `{{yesterday}}`,
+    /// `{{job_name}}` and `{{project_dir}}`.
+    pub prompt: String,
+    #[serde(default)]
+    pub system_prompt: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// One execution of a scheduled job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRunRecord {
+    pub job_id: String,
+    pub response_id: String,
+    /// `"manual"` or `"schedule"`.
+    pub trigger: String,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub success: bool,
+    pub output: String,
+    pub error: Option<String>,
+}
+
+/// Job plus computed schedule info, returned to the frontend.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub job: ScheduledJob,
+    pub next_run_at: Option<u64>,
+    pub last_run: Option<JobRunRecord>,
+    pub running: bool,
+}
+
+/// Mark a job as executing; false when it already is (runs of the same job
+/// never overlap).
+fn mark_running(state: &AppState, job_id: &str) -> bool {
+    state.running_jobs.lock().unwrap().insert(job_id.to_string())
+}
+
+fn mark_finished(state: &AppState, job_id: &str) {
+    state.running_jobs.lock().unwrap().remove(job_id);
+}
+
+fn is_running(state: &AppState, job_id: &str) -> bool {
+    state.running_jobs.lock().unwrap().contains(job_id)
+}
+
+// ── Cron ──────────────────────────────────────────────────────────────
+
+/// Parse a cron expression, accepting the common 5-field form by adding a
+/// leading seconds field.
+pub fn parse_cron(expr: &str) -> Result<cron::Schedule, String> {
+    let trimmed = expr.trim();
+    let normalized = if trimmed.split_whitespace().count() == 5 {
+        format!("0 {trimmed}")
+    } else {
+        trimmed.to_string()
+    };
+    cron::Schedule::from_str(&normalized).map_err(|e| format!("Invalid cron expression \"{expr}\": {e}"))
+}
+
+fn next_run_after(job: &ScheduledJob, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+    parse_cron(&job.cron).ok()?.after(after).next()
+}
+
+/// True when the job has an occurrence in `(last_tick, now]`.
+fn is_due(job: &ScheduledJob, last_tick: &DateTime<Local>, now: &DateTime<Local>) -> bool {
+    next_run_after(job, last_tick).is_some_and(|t| t <= *now)
+}
+
+fn render_prompt(job: &ScheduledJob, now: &DateTime<Local>) -> String {
+    let yesterday = *now - chrono::Duration::days(1);
+    prompt_template::render_with(&job.prompt, &job.project_dir, &|key| match key {
+        "date" => Some(now.format("%Y-%m-%d").to_string()),
+        "time" => Some(now.format("%H:%M").to_string()),
+        "yesterday" => Some(yesterday.format("%Y-%m-%d").to_string()),
+        "job_name" => Some(job.name.clone()),
+        "project_dir" => Some(job.project_dir.clone()),
+        _ => None,
+    })
+}
+
+// ── Persistence ───────────────────────────────────────────────────────
+
+pub fn load_jobs(app: &tauri::AppHandle) -> Vec<ScheduledJob> {
+    local_store::read_list(app, JOBS_KEY)
+}
+
+pub fn load_history(app: &tauri::AppHandle) -> Vec<JobRunRecord> {
+    local_store::read_list(app, HISTORY_KEY)
+}
+
+/// Insert or update a job (matched by ID). Validates the cron expression.
+pub fn save_job(app: &tauri::AppHandle, mut job: ScheduledJob) -> Result<ScheduledJob, JaibberError> {
+    parse_cron(&job.cron).map_err(JaibberError::Other)?;
+    if job.prompt.trim().is_empty() {
+        return Err(JaibberError::Other("Scheduled job prompt must not be empty".into()));
+    }
+    if job.id.is_empty() {
+        job.id = uuid::Uuid::new_v4().to_string();
+    }
+    let mut jobs = load_jobs(app);
+    match jobs.iter_mut().find(|j| j.id == job.id) {
+        Some(existing) => *existing = job.clone(),
+        None => jobs.push(job.clone()),
+    }
+    local_store::write_list(app, JOBS_KEY, &jobs)?;
+    Ok(job)
+}
+
+pub fn delete_job(app: &tauri::AppHandle, job_id: &str) -> Result<bool, JaibberError> {
+    let mut jobs = load_jobs(app);
+    let before = jobs.len();
+    jobs.retain(|j| j.id != job_id);
+    if jobs.len() == before {
+        return Ok(false);
+    }
+    local_store::write_list(app, JOBS_KEY, &jobs)?;
+    Ok(true)
+}
+
+fn append_history(app: &tauri::AppHandle, record: JobRunRecord) {
+    let mut history = load_history(app);
+    history.push(record);
+    if history.len() > MAX_HISTORY {
+        let excess = history.len() - MAX_HISTORY;
+        history.drain(..excess);
+    }
+    if let Err(e) = local_store::write_list(app, HISTORY_KEY, &history) {
+        tracing::warn!("[scheduler] Failed to save run history: {e}");
+    }
+}
+
+/// All jobs with their next run time and most recent run.
+pub fn job_statuses(app: &tauri::AppHandle) -> Vec<JobStatus> {
+    let state = app.state::<Arc<AppState>>();
+    let history = load_history(app);
+    let now = Local::now();
+    load_jobs(app)
+        .into_iter()
+        .map(|job| JobStatus {
+            next_run_at: job.enabled
+                .then(|| next_run_after(&job, &now))
+                .flatten()
+                .map(|t| t.timestamp_millis() as u64),
+            last_run: history.iter().rev().find(|r| r.job_id == job.id).cloned(),
+            running: is_running(&state, &job.id),
+            job,
+        })
+        .collect()
+}
+
+// ── Execution ─────────────────────────────────────────────────────────
+
+/// Run a job now through the streaming runtime and record the result.
+/// Returns the run's response ID once it has started.
+pub async fn run_job(app: tauri::AppHandle, job: ScheduledJob, trigger: &str) -> Result<String, JaibberError> {
+    let state = app.state::<Arc<AppState>>().inner().clone();
+    if !mark_running(&state, &job.id) {
+        return Err(JaibberError::Other(format!("Job \"{}\" is already running", job.name)));
+    }
+
+    let now = Local::now();
+    let request = RunRequest {
+        prompt: render_prompt(&job, &now),
+        project_dir: job.project_dir.clone(),
+        system_prompt: job.system_prompt.clone(),
+        agent_provider: job.agent_provider.clone(),
+        custom_command: job.custom_command.clone(),
+        agent_name: job.agent_name.clone(),
+        ..Default::default()
+    };
+
+    let started_at = runtime::now_ms();
+    let rid = match runtime::start_run(&state, request, "schedule", Some(Arc::new(app.clone()))).await {
+        Ok(rid) => rid,
+        Err(e) => {
+            mark_finished(&state, &job.id);
+            append_history(&app, JobRunRecord {
+                job_id: job.id.clone(),
+                response_id: String::new(),
+                trigger: trigger.to_string(),
+                started_at,
+                finished_at: runtime::now_ms(),
+                success: false,
+                output: String::new(),
+                error: Some(e.to_string()),
+            });
+            return Err(e);
+        }
+    };
+
+    let _ = app.emit("scheduled-run-started", serde_json::json!({
+        "jobId": job.id,
+        "jobName": job.name,
+        "responseId": rid,
+        "projectId": job.project_id,
+        "channel": job.channel,
+        "agentName": job.agent_name,
+        "trigger": trigger,
+    }));
+
+    let trigger = trigger.to_string();
+    let rid_task = rid.clone();
+    tokio::spawn(async move {
+        let result = runtime::collect_output(&state, &rid_task).await;
+        mark_finished(&state, &job.id);
+
+        let (output, error) = match result {
+            Ok(text) => (text, None),
+            Err(e) => (String::new(), Some(e)),
+        };
+        post_result(&state, &job, &rid_task, &output, error.as_deref());
+        let _ = app.emit("scheduled-run-finished", serde_json::json!({
+            "jobId": job.id,
+            "jobName": job.name,
+            "responseId": rid_task,
+            "projectId": job.project_id,
+            "channel": job.channel,
+            "agentName": job.agent_name,
+            "output": output,
+            "error": error,
+        }));
+        append_history(&app, JobRunRecord {
+            job_id: job.id.clone(),
+            response_id: rid_task,
+            trigger,
+            started_at,
+            finished_at: runtime::now_ms(),
+            success: error.is_none(),
+            output: truncate_chars(&output, MAX_HISTORY_OUTPUT),
+            error,
+        });
+    });
+
+    Ok(rid)
+}
+
+/// Post the run's output (or error) to the job's project chat.
+fn post_result(state: &AppState, job: &ScheduledJob, rid: &str, output: &str, error: Option<&str>) {
+    let (kind, text) = match error {
+        Some(e) => ("error", format!("Agent error: {e}")),
+        None => ("response", output.to_string()),
+    };
+    if job.project_id.is_empty() || text.is_empty() {
+        return;
+    }
+    let message = NewMessage {
+        id: rid.to_string(),
+        sender_type: "agent".into(),
+        sender_name: job.agent_name.clone().unwrap_or_else(|| job.name.clone()),
+        kind: kind.to_string(),
+        text,
+        parent_message_id: None,
+    };
+    if let Err(e) = state.outbox.send_message(&job.project_id, message) {
+        tracing::warn!("[scheduler] Failed to queue result: {e}");
+    }
+}
+
+fn truncate_chars(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}... (truncated)", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+/// Background loop: every tick, run enabled jobs with an occurrence in
+/// `(last_tick, now]`.
+pub fn start(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<Arc<AppState>>().inner().clone();
+        let mut last_tick = Local::now();
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(TICK_SECS));
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let now = Local::now();
+            for job in load_jobs(&app).into_iter().filter(|j| j.enabled) {
+                if !is_due(&job, &last_tick, &now) || is_running(&state, &job.id) {
+                    continue;
+                }
+                tracing::info!("[scheduler] Running job \"{}\" ({})", job.name, job.cron);
+                if let Err(e) = run_job(app.clone(), job, "schedule").await {
+                    tracing::warn!("[scheduler] Job failed to start: {e}");
+                }
+            }
+            last_tick = now;
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn job(cron: &str, prompt: &str) -> ScheduledJob {
+        serde_json::from_value(serde_json::json!({
+            "name": "Standup",
+            "cron": cron,
+            "prompt": prompt,
+            "projectDir": "/work/site",
+        })).unwrap()
+    }
+
+    fn at(hour: u32, min: u32, sec: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2026, 3, 10, hour, min, sec).unwrap()
+    }
+
+    #[test]
+    fn parses_five_and_six_field_cron() {
+        let five = parse_cron("30 9 * * 1-5").unwrap();
+        assert_eq!(five.after(&at(8, 0, 0)).next(), Some(at(9, 30, 0)));
+        let six = parse_cron(" */15 * * * * * ").unwrap();
+        assert_eq!(six.after(&at(8, 0, 0)).next(), Some(at(8, 0, 15)));
+        let err = parse_cron("every morning").unwrap_err();
+        assert!(err.contains("every morning"));
+    }
+
+    #[test]
+    fn fires_once_per_occurrence_in_the_tick_window() {
+        let daily = job("30 9 * * *", "Hi");
+        assert!(is_due(&daily, &at(9, 29, 50), &at(9, 30, 10)));
+        // Exactly on the boundary belongs to the window that ends there
+        assert!(is_due(&daily, &at(9, 29, 40), &at(9, 30, 0)));
+        assert!(!is_due(&daily, &at(9, 30, 0), &at(9, 30, 20)));
+        assert!(!is_due(&daily, &at(9, 0, 0), &at(9, 29, 59)));
+        assert!(!is_due(&job("bogus", "Hi"), &at(9, 0, 0), &at(10, 0, 0)));
+    }
+
+    #[test]
+    fn renders_date_variables() {
+        let job = job("0 9 * * *", "{{job_name}} for {{date}} (since {{yesterday}}) in {{project_dir}} at {{time}}");
+        assert_eq!(
+            render_prompt(&job, &at(9, 5, 0)),
+            "Standup for 2026-03-10 (since 2026-03-09) in /work/site at 09:05",
+        );
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        assert_eq!(truncate_chars("héllo", 10), "héllo");
+        assert_eq!(truncate_chars("héllo", 2), "hé... (truncated)");
+    }
+}
@@ -1,18 +1,80 @@
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
+use crate::api_client::ApiClient;
+use crate::outbox::Outbox;
+use crate::runtime::RunRegistry;
+use crate::local_api::LocalApiHandle;
+use crate::task_worker::TaskWorkerHandle;
+use crate::webhooks::WebhookServerHandle;
+
+/// Default localhost port for the local agent API.
+pub const DEFAULT_LOCAL_API_PORT: u16 = 7420;
+/// Default port for the inbound webhook listener.
+pub const DEFAULT_WEBHOOK_PORT: u16 = 7421;
 
 /// Central application state, shared via Arc across all Tauri commands.
 pub struct AppState {
     pub settings: Arc<RwLock<AppSettings>>,
+    /// Active and recently finished agent runs, from every source.
+    pub runs: Arc<RunRegistry>,
+    /// Handle to the running local API server (None when disabled).
+    pub local_api: Mutex<Option<LocalApiHandle>>,
+    /// Handle to the background task worker (None when disabled).
+    pub task_worker: Mutex<Option<TaskWorkerHandle>>,
+    /// Handle to the inbound webhook listener (None when disabled).
+    pub webhook_server: Mutex<Option<WebhookServerHandle>>,
+    /// Location of the `jaibber.json` store file (read by background services).
+    pub store_path: PathBuf,
+    /// Client for the Jaibber REST API (base URL follows `api_base_url`).
+    pub api: Arc<ApiClient>,
+    /// Durable queue of API writes that must survive network drops.
+    pub outbox: Arc<Outbox>,
+    /// Scheduled jobs with a run in flight, by job ID.
+    pub running_jobs: std::sync::Mutex<std::collections::HashSet<String>>,
+    /// OS watchers for enabled file-watch triggers, by watch ID.
+    pub file_watches: std::sync::Mutex<crate::watcher::FileWatchMap>,
+    /// Active and recent pipeline runs, with their pending approval gates.
+    pub pipelines: crate::pipeline::PipelineRegistry,
+    /// Finished multi-provider comparisons, with every candidate's output.
+    pub comparisons: crate::compare::CompareRegistry,
+    /// Cached rolling summaries of older chat history, per thread.
+    pub summaries: Arc<crate::history_summary::SummaryCache>,
+    /// Tool calls from API runs waiting for the user's approval.
+    pub tool_approvals: Arc<crate::claude_tools::ToolApprovals>,
+    /// Attachments already uploaded to the Anthropic Files API.
+    pub file_ids: Arc<crate::attachments::FileIdCache>,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let settings = AppSettings::default();
+        let store_path = crate::local_store::default_store_path();
         Self {
-            settings: Arc::new(RwLock::new(AppSettings::default())),
+            api: Arc::new(ApiClient::new(&settings.api_base_url, store_path.clone())),
+            outbox: Arc::new(Outbox::load(&store_path)),
+            tool_approvals: Default::default(),
+            file_ids: Arc::new(crate::attachments::FileIdCache::load(&store_path)),
+            summaries: Arc::new(crate::history_summary::SummaryCache::load(&store_path)),
+            settings: Arc::new(RwLock::new(settings)),
+            runs: Arc::new(RunRegistry::new()),
+            local_api: Mutex::new(None),
+            task_worker: Mutex::new(None),
+            webhook_server: Mutex::new(None),
+            store_path,
+            running_jobs: std::sync::Mutex::new(Default::default()),
+            file_watches: std::sync::Mutex::new(Default::default()),
+            pipelines: Default::default(),
+            comparisons: Default::default(),
         }
     }
+
+    /// Replace the in-memory settings and update everything derived from them.
+    pub async fn set_settings(&self, settings: AppSettings) {
+        self.api.set_base_url(&settings.api_base_url);
+        *self.settings.write().await = settings;
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +87,79 @@ pub struct AppSettings {
     pub google_api_key: Option<String>,
     pub machine_name: String,
     pub api_base_url: String,
+    /// Expose the agent runtime on a localhost HTTP/WebSocket API.
+    #[serde(default)]
+    pub local_api_enabled: bool,
+    #[serde(default = "default_local_api_port")]
+    pub local_api_port: u16,
+    /// Bearer token required by the local API. Generated when first enabled.
+    #[serde(default)]
+    pub local_api_token: Option<String>,
+    /// Execute tasks assigned to this machine's agents in the backend
+    /// (instead of the webview).
+    #[serde(default)]
+    pub task_worker_enabled: bool,
+    #[serde(default = "default_task_worker_poll_secs")]
+    pub task_worker_poll_secs: u64,
+    /// Accept HMAC-signed webhooks that start agent runs.
+    #[serde(default)]
+    pub webhook_enabled: bool,
+    /// Interface for the webhook listener; use 0.0.0.0 to accept from the network.
+    #[serde(default = "default_webhook_bind")]
+    pub webhook_bind: String,
+    #[serde(default = "default_webhook_port")]
+    pub webhook_port: u16,
+    /// Shared HMAC-SHA256 secret (routes may override). Generated when first enabled.
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    /// Token budget for conversation history included with each prompt.
+    #[serde(default = "default_context_token_budget")]
+    pub context_token_budget: usize,
+    /// Summarize history that falls outside the budget (needs an Anthropic key).
+    #[serde(default = "default_true")]
+    pub history_summary_enabled: bool,
+    /// Cheap model used for history summaries.
+    #[serde(default = "default_summary_model")]
+    pub summary_model: String,
+    /// Which local tools the direct Claude API provider may use.
+    #[serde(default)]
+    pub api_tools: crate::claude_tools::ApiToolPolicy,
+    /// Hours to keep attachments saved for CLI agents; 0 deletes them when
+    /// the run ends.
+    #[serde(default = "default_attachment_retention_hours")]
+    pub attachment_retention_hours: u64,
+}
+
+fn default_local_api_port() -> u16 {
+    DEFAULT_LOCAL_API_PORT
+}
+
+fn default_task_worker_poll_secs() -> u64 {
+    15
+}
+
+fn default_webhook_bind() -> String {
+    String::from("127.0.0.1")
+}
+
+fn default_webhook_port() -> u16 {
+    DEFAULT_WEBHOOK_PORT
+}
+
+fn default_context_token_budget() -> usize {
+    16_000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_attachment_retention_hours() -> u64 {
+    24
+}
+
+fn default_summary_model() -> String {
+    String::from("claude-3-5-haiku-20241022")
 }
 
 impl Default for AppSettings {
@@ -35,6 +170,20 @@ impl Default for AppSettings {
             google_api_key: None,
             machine_name: String::new(),
             api_base_url: String::from("https://api.jaibber.com"),
+            local_api_enabled: false,
+            local_api_port: DEFAULT_LOCAL_API_PORT,
+            local_api_token: None,
+            task_worker_enabled: false,
+            task_worker_poll_secs: default_task_worker_poll_secs(),
+            webhook_enabled: false,
+            webhook_bind: default_webhook_bind(),
+            webhook_port: DEFAULT_WEBHOOK_PORT,
+            webhook_secret: None,
+            context_token_budget: default_context_token_budget(),
+            history_summary_enabled: true,
+            summary_model: default_summary_model(),
+            api_tools: Default::default(),
+            attachment_retention_hours: default_attachment_retention_hours(),
         }
     }
 }
@@ -43,11 +192,67 @@ impl Default for AppSettings {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AttachmentInfo {
+    #[serde(default)]
     pub id: String,
     pub filename: String,
+    /// Guessed from the file name when empty.
+    #[serde(default)]
     pub mime_type: String,
+    #[serde(default)]
     pub file_size: u64,
+    /// Empty for local files.
+    #[serde(default)]
     pub blob_url: String,
+    /// A file on this machine, relative to (or inside) the project directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>,
+}
+
+/// A locally linked agent, as saved by the frontend under `local_projects`.
+/// One entry per (project, agent) pair on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalProject {
+    pub project_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub project_dir: String,
+    #[serde(default)]
+    pub ably_channel_name: String,
+    pub agent_name: String,
+    /// System prompt prepended to every agent call.
+    #[serde(default)]
+    pub agent_instructions: String,
+    #[serde(default)]
+    pub agent_provider: String,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    #[serde(default)]
+    pub current_session_id: Option<String>,
+    /// Claude extended thinking budget in tokens.
+    #[serde(default)]
+    pub thinking_budget: Option<u32>,
+    /// Model and sampling settings for this agent.
+    #[serde(default)]
+    pub model_config: crate::agent_providers::ModelConfig,
+}
+
+/// Session saved by the frontend under `auth` after login.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredAuth {
+    pub token: String,
+    #[serde(default)]
+    pub user_id: String,
+    #[serde(default)]
+    pub username: String,
+}
+
+impl LocalProject {
+    /// Provider name, defaulting to Claude like the frontend does.
+    pub fn provider(&self) -> &str {
+        if self.agent_provider.is_empty() { "claude" } else { &self.agent_provider }
+    }
 }
 
 impl AppSettings {
@@ -62,4 +267,30 @@ impl AppSettings {
         // Only return non-empty keys
         key.filter(|k| !k.is_empty())
     }
+
+    /// Generate a local API token if the API is enabled without one.
+    /// Returns true if a new token was generated.
+    pub fn ensure_local_api_token(&mut self) -> bool {
+        let missing = self.local_api_token.as_deref().is_none_or(|t| t.is_empty());
+        if self.local_api_enabled && missing {
+            self.local_api_token = Some(uuid::Uuid::new_v4().simple().to_string());
+            return true;
+        }
+        false
+    }
+
+    /// Generate a webhook secret if webhooks are enabled without one.
+    /// Returns true if a new secret was generated.
+    pub fn ensure_webhook_secret(&mut self) -> bool {
+        let missing = self.webhook_secret.as_deref().is_none_or(|s| s.is_empty());
+        if self.webhook_enabled && missing {
+            self.webhook_secret = Some(format!(
+                "{}{}",
+                uuid::Uuid::new_v4().simple(),
+                uuid::Uuid::new_v4().simple(),
+            ));
+            return true;
+        }
+        false
+    }
 }
@@ -0,0 +1,449 @@
+//! Native task worker — executes server-side tasks assigned to this
+//! machine's agents without going through the webview.
+//!
+//! Every poll it lists `submitted` tasks for each local agent, plus `working`
+//! tasks whose lease has expired, claims them and runs them through the
+//! provider runtime. Progress and results are posted back as task status
+//! transitions and chat messages.
+//!
+//! Leases: a claim PATCHes the task to `working` with `leaseOwner` /
+//! `leaseExpiresAt`, and every heartbeat pushes the expiry out again. The
+//! API has no conditional update, so after claiming the worker waits
+//! `CLAIM_SETTLE_SECS`, re-reads the task and backs off unless it still owns
+//! the lease (the last writer wins). Only `working` tasks whose lease has
+//! lapsed are reclaimed; tasks the frontend set to `working` carry no lease
+//! and are never taken over.
+//!
+//! When enabled, the frontend stops auto-executing tasks itself.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde_json::json;
+use tokio::sync::oneshot;
+use crate::api_client::{NewMessage, NewTask, Task, TaskFilter};
+use crate::error::JaibberError;
+use crate::runtime::{self, EventSink, RunRequest};
+use crate::state::{AppState, LocalProject};
+
+/// Seconds between heartbeats while a task runs.
+const HEARTBEAT_SECS: u64 = 30;
+/// Lease length; a lease not renewed for this long marks a crashed worker.
+const LEASE_TTL_SECS: u64 = 120;
+/// Wait between claiming a task and checking the claim stuck.
+const CLAIM_SETTLE_SECS: u64 = 2;
+/// Tasks executed concurrently by this worker.
+const MAX_CONCURRENT_TASKS: usize = 4;
+/// Max depth for task chaining. Prevents infinite HANDOFF loops (matches the frontend).
+const MAX_TASK_CHAIN_DEPTH: usize = 5;
+/// Marker an agent appends when it needs more information to continue.
+const INPUT_REQUIRED_MARKER: &str = "[INPUT_REQUIRED]";
+
+/// Handle to the running worker. Sending on (or dropping) `shutdown` stops polling;
+/// tasks already running finish normally.
+pub struct TaskWorkerHandle {
+    poll_secs: u64,
+    shutdown: oneshot::Sender<()>,
+}
+
+/// Everything a task run needs to talk to the server.
+#[derive(Clone)]
+struct WorkerContext {
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    worker_id: String,
+    /// Task IDs currently executing on this worker.
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+impl WorkerContext {
+    async fn list_tasks(&self, project_id: &str, status: &str, agent_name: Option<&str>) -> Result<Vec<Task>, JaibberError> {
+        let filter = TaskFilter {
+            status: Some(status),
+            assigned_agent_name: agent_name,
+            limit: Some(50),
+            ..Default::default()
+        };
+        self.state.api.list_tasks(project_id, &filter).await.map(|page| page.items)
+    }
+
+    /// Persist a chat message from the agent in the task's project (via the
+    /// outbox, so it survives a dropped connection).
+    fn post_message(&self, task: &Task, agent_name: &str, kind: &str, text: &str) {
+        let message = NewMessage {
+            id: uuid::Uuid::new_v4().to_string(),
+            sender_type: "agent".into(),
+            sender_name: agent_name.to_string(),
+            kind: kind.to_string(),
+            text: text.to_string(),
+            parent_message_id: task.source_message_id.clone(),
+        };
+        if let Err(e) = self.state.outbox.send_message(&task.project_id, message) {
+            tracing::warn!("[task_worker] Failed to queue message: {e}");
+        }
+    }
+
+    fn lease_body(&self, status: &str) -> serde_json::Value {
+        let expires = chrono::Utc::now() + chrono::Duration::seconds(LEASE_TTL_SECS as i64);
+        json!({
+            "status": status,
+            "leaseOwner": self.worker_id,
+            "leaseExpiresAt": expires.to_rfc3339(),
+        })
+    }
+
+    /// Take the lease on `task`. False if another worker holds it once the
+    /// dust settles.
+    async fn claim(&self, task: &Task) -> Result<bool, JaibberError> {
+        let claimed = self.state.api.update_task(&task.id, self.lease_body("working"), None).await?;
+        if claimed.lease_owner.as_deref().is_some_and(|owner| owner != self.worker_id) {
+            return Ok(false);
+        }
+        // A competing claim sent at the same time may land after ours
+        tokio::time::sleep(Duration::from_secs(CLAIM_SETTLE_SECS)).await;
+        let agent = task.assigned_agent_name.as_deref();
+        let current = self.list_tasks(&task.project_id, "working", agent).await?
+            .into_iter()
+            .find(|t| t.id == task.id);
+        Ok(current.is_some_and(|t| holds_lease(&t, &self.worker_id)))
+    }
+}
+
+// ── Lifecycle ─────────────────────────────────────────────────────────
+
+/// Start, restart or stop the worker so it matches the current settings.
+/// Called at startup and whenever settings are saved.
+pub async fn apply_settings(state: &Arc<AppState>, sink: Arc<dyn EventSink>) {
+    let settings = state.settings.read().await.clone();
+    let mut current = state.task_worker.lock().await;
+
+    if let Some(ref handle) = *current {
+        if settings.task_worker_enabled && handle.poll_secs == settings.task_worker_poll_secs {
+            return;
+        }
+    }
+    if let Some(handle) = current.take() {
+        let _ = handle.shutdown.send(());
+        tracing::info!("[task_worker] Stopped");
+    }
+    if !settings.task_worker_enabled {
+        return;
+    }
+
+    let poll_secs = settings.task_worker_poll_secs.max(5);
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    let machine = if settings.machine_name.is_empty() { "jaibber" } else { &settings.machine_name };
+    let ctx = WorkerContext {
+        state: state.clone(),
+        sink,
+        worker_id: format!("{machine}:{}", uuid::Uuid::new_v4().simple()),
+        active: Arc::new(Mutex::new(HashSet::new())),
+    };
+    tracing::info!("[task_worker] Started as {} (poll every {poll_secs}s)", ctx.worker_id);
+    tokio::spawn(poll_loop(ctx, poll_secs, shutdown_rx));
+    *current = Some(TaskWorkerHandle { poll_secs: settings.task_worker_poll_secs, shutdown });
+}
+
+async fn poll_loop(ctx: WorkerContext, poll_secs: u64, mut shutdown: oneshot::Receiver<()>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(poll_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => return,
+            _ = interval.tick() => poll_once(&ctx).await,
+        }
+    }
+}
+
+async fn poll_once(ctx: &WorkerContext) {
+    if !ctx.state.api.is_signed_in() {
+        return;
+    }
+    let projects = crate::local_store::load_local_projects(&ctx.state.store_path);
+    for agent in projects.iter().filter(|p| !p.agent_name.is_empty() && !p.project_id.is_empty()) {
+        let mut candidates = match ctx.list_tasks(&agent.project_id, "submitted", Some(&agent.agent_name)).await {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                tracing::debug!("[task_worker] {}: {e}", agent.project_id);
+                continue;
+            }
+        };
+        if let Ok(working) = ctx.list_tasks(&agent.project_id, "working", Some(&agent.agent_name)).await {
+            candidates.extend(working.into_iter().filter(lease_expired));
+        }
+
+        for task in candidates {
+            let assigned = task.assigned_agent_name.as_deref().unwrap_or("");
+            if !assigned.eq_ignore_ascii_case(&agent.agent_name) {
+                continue;
+            }
+            {
+                let mut active = ctx.active.lock().unwrap();
+                if active.len() >= MAX_CONCURRENT_TASKS {
+                    return;
+                }
+                if !active.insert(task.id.clone()) {
+                    continue;
+                }
+            }
+            tokio::spawn(execute_task(ctx.clone(), agent.clone(), task));
+        }
+    }
+}
+
+/// True when a `working` task carries a lease that has lapsed. Tasks without
+/// a lease were claimed elsewhere (e.g. by the frontend) and are left alone.
+fn lease_expired(task: &Task) -> bool {
+    task.lease_expires_at.as_deref()
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .is_some_and(|expires| expires < chrono::Utc::now())
+}
+
+/// True when `worker_id` owns the task's lease. A server that doesn't store
+/// lease fields returns none, and then the claim PATCH is all we have.
+fn holds_lease(task: &Task, worker_id: &str) -> bool {
+    task.status == "working" && task.lease_owner.as_deref().is_none_or(|owner| owner == worker_id)
+}
+
+// ── Execution ─────────────────────────────────────────────────────────
+
+async fn execute_task(ctx: WorkerContext, agent: LocalProject, task: Task) {
+    let result = run_task(&ctx, &agent, &task).await;
+    if let Err(e) = result {
+        tracing::warn!("[task_worker] Task {} failed: {e}", task.id);
+    }
+    ctx.active.lock().unwrap().remove(&task.id);
+}
+
+async fn run_task(ctx: &WorkerContext, agent: &LocalProject, task: &Task) -> Result<(), JaibberError> {
+    if !ctx.claim(task).await? {
+        tracing::info!("[task_worker] Task {} was claimed by another worker", task.id);
+        return Ok(());
+    }
+
+    let reclaimed = task.status == "working";
+    tracing::info!("[task_worker] {} task {} for {}", if reclaimed { "Reclaimed" } else { "Picked up" }, task.id, agent.agent_name);
+    let priority = task.priority.as_deref().filter(|p| *p != "medium")
+        .map(|p| format!(" [{p}]"))
+        .unwrap_or_default();
+    ctx.post_message(task, &agent.agent_name, "message", &format!("Picking up task{priority}: {}", task.title));
+
+    let request = RunRequest {
+        prompt: task_prompt(task),
+        project_dir: agent.project_dir.clone(),
+        system_prompt: agent.agent_instructions.clone(),
+        agent_provider: Some(agent.provider().to_string()),
+        custom_command: agent.custom_command.clone(),
+        agent_name: Some(agent.agent_name.clone()),
+        thinking_budget: agent.thinking_budget,
+        model_config: agent.model_config.clone(),
+        ..Default::default()
+    };
+    let rid = match runtime::start_run(&ctx.state, request, "task", Some(ctx.sink.clone())).await {
+        Ok(rid) => rid,
+        Err(e) => {
+            ctx.post_message(task, &agent.agent_name, "error", &e.to_string());
+            ctx.state.outbox.update_task(&task.id, json!({ "status": "failed" }))?;
+            return Err(e);
+        }
+    };
+    ctx.sink.emit_event("task-worker-started", json!({
+        "taskId": task.id,
+        "projectId": task.project_id,
+        "agentName": agent.agent_name,
+        "responseId": rid,
+    }));
+
+    // Heartbeat until the run finishes
+    let output = {
+        let collect = runtime::collect_output(&ctx.state, &rid);
+        tokio::pin!(collect);
+        let mut heartbeat = tokio::time::interval(Duration::from_secs(HEARTBEAT_SECS));
+        heartbeat.tick().await;
+        loop {
+            tokio::select! {
+                result = &mut collect => break result,
+                _ = heartbeat.tick() => {
+                    if let Err(e) = ctx.state.api.update_task(&task.id, ctx.lease_body("working"), None).await {
+                        tracing::warn!("[task_worker] Heartbeat for {} failed: {e}", task.id);
+                    }
+                }
+            }
+        }
+    };
+
+    let status = match &output {
+        Ok(text) if text.trim_end().ends_with(INPUT_REQUIRED_MARKER) => "input-required",
+        Ok(_) => "completed",
+        Err(_) => "failed",
+    };
+    match &output {
+        Ok(text) => {
+            let text = text.trim_end().trim_end_matches(INPUT_REQUIRED_MARKER).trim_end();
+            ctx.post_message(task, &agent.agent_name, "response", text);
+        }
+        Err(e) => ctx.post_message(task, &agent.agent_name, "error", e),
+    }
+    ctx.state.outbox.update_task(&task.id, json!({ "status": status, "leaseOwner": null, "leaseExpiresAt": null }))?;
+
+    ctx.sink.emit_event("task-worker-finished", json!({
+        "taskId": task.id,
+        "projectId": task.project_id,
+        "agentName": agent.agent_name,
+        "responseId": rid,
+        "status": status,
+    }));
+
+    if let Ok(text) = &output {
+        if status == "completed" {
+            create_handoffs(ctx, agent, task, text).await;
+        }
+    }
+    Ok(())
+}
+
+fn task_prompt(task: &Task) -> String {
+    let mut prompt = match task.description.as_deref().filter(|d| !d.is_empty()) {
+        Some(desc) => format!("Task: {}\n\n{desc}", task.title),
+        None => format!("Task: {}", task.title),
+    };
+    prompt.push_str(&format!(
+        "\n\nIf you cannot finish without more information from the user, ask for it and end your reply with {INPUT_REQUIRED_MARKER}."
+    ));
+    prompt
+}
+
+/// Parse `[HANDOFF: @Agent "description"]` markers from the output.
+fn parse_handoffs(text: &str) -> Vec<(String, String)> {
+    let mut handoffs = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[HANDOFF:") {
+        rest = &rest[start + "[HANDOFF:".len()..];
+        let Some(end) = rest.find(']') else { break };
+        let inner = rest[..end].trim();
+        rest = &rest[end..];
+
+        let Some(after_at) = inner.strip_prefix('@') else { continue };
+        let name_len = after_at
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(after_at.len());
+        let (agent, remainder) = after_at.split_at(name_len);
+        let remainder = remainder.trim();
+        if agent.is_empty() || !remainder.starts_with('"') || !remainder.ends_with('"') || remainder.len() < 2 {
+            continue;
+        }
+        handoffs.push((agent.to_string(), remainder[1..remainder.len() - 1].to_string()));
+    }
+    handoffs
+}
+
+/// Create follow-up tasks for HANDOFF markers, bounded by chain depth.
+async fn create_handoffs(ctx: &WorkerContext, agent: &LocalProject, task: &Task, output: &str) {
+    let handoffs = parse_handoffs(output);
+    if handoffs.is_empty() {
+        return;
+    }
+
+    let mut depth = 0;
+    if task.parent_task_id.is_some() {
+        let mut all = Vec::new();
+        for status in ["completed", "working", "submitted", "failed", "input-required"] {
+            if let Ok(tasks) = ctx.list_tasks(&task.project_id, status, None).await {
+                all.extend(tasks);
+            }
+        }
+        let mut parent = task.parent_task_id.clone();
+        while let Some(pid) = parent {
+            depth += 1;
+            if depth >= MAX_TASK_CHAIN_DEPTH {
+                break;
+            }
+            parent = all.iter().find(|t| t.id == pid).and_then(|t| t.parent_task_id.clone());
+        }
+    }
+    if depth >= MAX_TASK_CHAIN_DEPTH {
+        tracing::warn!("[task_worker] Task chain depth limit ({MAX_TASK_CHAIN_DEPTH}) reached, skipping HANDOFF");
+        return;
+    }
+
+    for (target, description) in handoffs {
+        let follow_up = NewTask {
+            title: description,
+            description: Some(format!("Follow-up from task \"{}\" completed by @{}", task.title, agent.agent_name)),
+            assigned_agent_name: Some(target.clone()),
+            parent_task_id: Some(task.id.clone()),
+            created_by_type: Some("agent".into()),
+            created_by_name: Some(agent.agent_name.clone()),
+            ..Default::default()
+        };
+        // Keyed by parent + target so a retried POST can't create duplicates
+        let key = format!("handoff:{}:{target}", task.id);
+        if let Err(e) = ctx.state.api.create_task(&task.project_id, &follow_up, Some(&key)).await {
+            tracing::warn!("[task_worker] createTask(handoff) failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(status: &str, owner: Option<&str>, expires_in_secs: Option<i64>) -> Task {
+        Task {
+            id: "t1".into(),
+            project_id: "p1".into(),
+            title: "Fix it".into(),
+            description: None,
+            status: status.into(),
+            priority: None,
+            assigned_agent_name: Some("Coder".into()),
+            source_message_id: None,
+            parent_task_id: None,
+            created_at: None,
+            updated_at: None,
+            lease_owner: owner.map(String::from),
+            lease_expires_at: expires_in_secs
+                .map(|secs| (chrono::Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339()),
+        }
+    }
+
+    #[test]
+    fn reclaims_only_lapsed_leases() {
+        assert!(lease_expired(&task("working", Some("a"), Some(-5))));
+        assert!(!lease_expired(&task("working", Some("a"), Some(60))));
+        // Set to working by the frontend: no lease, never reclaimed
+        assert!(!lease_expired(&task("working", None, None)));
+    }
+
+    #[test]
+    fn checks_the_lease_owner_after_claiming() {
+        assert!(holds_lease(&task("working", Some("me"), Some(60)), "me"));
+        assert!(!holds_lease(&task("working", Some("other"), Some(60)), "me"));
+        assert!(!holds_lease(&task("cancelled", Some("me"), Some(60)), "me"));
+        // Server without lease fields
+        assert!(holds_lease(&task("working", None, None), "me"));
+    }
+
+    #[test]
+    fn parses_handoff_markers() {
+        let output = "Done.\n[HANDOFF: @Tester \"Run the suite\"]\nThen [HANDOFF:@code-review_2 \"Check the diff\"]";
+        assert_eq!(parse_handoffs(output), [
+            ("Tester".to_string(), "Run the suite".to_string()),
+            ("code-review_2".to_string(), "Check the diff".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn skips_malformed_handoffs() {
+        assert!(parse_handoffs("No handoff here").is_empty());
+        assert!(parse_handoffs("[HANDOFF: Tester \"no at sign\"]").is_empty());
+        assert!(parse_handoffs("[HANDOFF: @ \"no name\"]").is_empty());
+        assert!(parse_handoffs("[HANDOFF: @Tester unquoted]").is_empty());
+        assert!(parse_handoffs("[HANDOFF: @Tester \"]").is_empty());
+        assert!(parse_handoffs("[HANDOFF: @Tester \"never closed\"").is_empty());
+        // The first `]` ends the marker, so a bracket in the description cuts it short
+        assert!(parse_handoffs("[HANDOFF: @Tester \"Check [the] diff\"]").is_empty());
+        // A bad marker doesn't hide the good one after it
+        assert_eq!(parse_handoffs("[HANDOFF: @X y] [HANDOFF: @Y \"z\"]"), [("Y".to_string(), "z".to_string())]);
+    }
+}
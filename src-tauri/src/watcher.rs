@@ -0,0 +1,505 @@
+//! File-watch triggers — an agent subscribes to changes in its project
+//! directory through glob patterns (e.g. "when `migrations/*.sql` changes,
+//! ask the reviewer agent to check it").
+//!
+//! Watches live in the store (`file_watches`). Each enabled watch gets a
+//! recursive OS watcher (inotify on Linux) on its `project_dir`; matching
+//! changes are debounced into one batch, and the batch's paths and `git diff`
+//! are templated into the prompt of a new run.
+//!
+//! Loop protection: changes are ignored while the watch's own run is active
+//! and for `cooldown_secs` afterwards, so the agent's edits don't retrigger it.
+//! Only one run per watch is ever in flight. The result is posted to the
+//! watch's project through the outbox.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tokio::sync::mpsc;
+use crate::api_client::NewMessage;
+use crate::error::JaibberError;
+use crate::local_store;
+use crate::prompt_template;
+use crate::runtime::{self, RunRequest};
+use crate::state::AppState;
+
+const WATCHES_KEY: &str = "file_watches";
+/// Paths never worth triggering on, in addition to a watch's own excludes.
+const DEFAULT_EXCLUDES: &[&str] = &[".git/**", "**/node_modules/**", "**/target/**", "**/.DS_Store"];
+/// Upper bound for the diff included in the prompt.
+const MAX_DIFF_CHARS: usize = 30_000;
+/// Lines shown for new (untracked) files, which have no diff.
+const NEW_FILE_PREVIEW_LINES: usize = 200;
+
+/// A glob-triggered agent run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileWatch {
+    /// Generated on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub project_dir: String,
+    /// Globs relative to `project_dir`, e.g. `migrations/*.sql` or `**/*.proto`.
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+    /// Quiet period after the watch's own run finishes.
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// Target project (and its chat channel) for the result.
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub agent_name: Option<String>,
+    #[serde(default)]
+    pub agent_provider: Option<String>,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    /// Prompt template. Supports `{{paths}}`, `{{diff}}`, `{{watch_name}}`
+    /// and `{{project_dir}}`. When it mentions neither `{{paths}}` nor
+    /// `{{diff}}`, both are appended.
+    pub prompt: String,
+    #[serde(default)]
+    pub system_prompt: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_debounce_ms() -> u64 {
+    2000
+}
+
+fn default_cooldown_secs() -> u64 {
+    10
+}
+
+/// A running watch. Dropping it stops the OS watcher and the debounce task;
+/// a run it already started finishes on its own.
+pub struct FileWatchHandle {
+    /// The configuration it was started with, to detect edits.
+    watch: FileWatch,
+    /// Shared with in-flight runs and carried over when the watch restarts.
+    suppression: Arc<Mutex<Suppression>>,
+    _watcher: RecommendedWatcher,
+    task: tokio::task::AbortHandle,
+}
+
+impl Drop for FileWatchHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Map of watch ID → handle, kept in `AppState`.
+pub type FileWatchMap = HashMap<String, FileWatchHandle>;
+
+/// Loop-protection state shared between the debounce task and its runs.
+#[derive(Default)]
+struct Suppression {
+    running: bool,
+    until: Option<Instant>,
+}
+
+impl Suppression {
+    fn active(&self) -> bool {
+        self.running || self.until.is_some_and(|t| Instant::now() < t)
+    }
+}
+
+// ── Persistence ───────────────────────────────────────────────────────
+
+pub fn load_watches(app: &tauri::AppHandle) -> Vec<FileWatch> {
+    local_store::read_list(app, WATCHES_KEY)
+}
+
+/// Insert or update a watch (matched by ID) after validating it.
+pub fn save_watch(app: &tauri::AppHandle, mut watch: FileWatch) -> Result<FileWatch, JaibberError> {
+    if !Path::new(&watch.project_dir).is_dir() {
+        return Err(JaibberError::Other(format!("Not a directory: {}", watch.project_dir)));
+    }
+    if watch.patterns.is_empty() {
+        return Err(JaibberError::Other("A file watch needs at least one pattern".into()));
+    }
+    if watch.prompt.trim().is_empty() {
+        return Err(JaibberError::Other("File watch prompt must not be empty".into()));
+    }
+    build_globset(&watch.patterns).map_err(JaibberError::Other)?;
+    build_globset(&watch.exclude).map_err(JaibberError::Other)?;
+    if watch.id.is_empty() {
+        watch.id = uuid::Uuid::new_v4().to_string();
+    }
+
+    let mut watches = load_watches(app);
+    match watches.iter_mut().find(|w| w.id == watch.id) {
+        Some(existing) => *existing = watch.clone(),
+        None => watches.push(watch.clone()),
+    }
+    local_store::write_list(app, WATCHES_KEY, &watches)?;
+    Ok(watch)
+}
+
+pub fn delete_watch(app: &tauri::AppHandle, watch_id: &str) -> Result<bool, JaibberError> {
+    let mut watches = load_watches(app);
+    let before = watches.len();
+    watches.retain(|w| w.id != watch_id);
+    if watches.len() == before {
+        return Ok(false);
+    }
+    local_store::write_list(app, WATCHES_KEY, &watches)?;
+    Ok(true)
+}
+
+// ── Lifecycle ─────────────────────────────────────────────────────────
+
+/// Start, restart or stop watchers so they match the saved watches. Called
+/// at startup and after every change; unchanged watches keep running, and a
+/// restarted watch keeps its loop protection. Must run inside the async
+/// runtime.
+pub fn apply(app: &tauri::AppHandle) {
+    let state = app.state::<Arc<AppState>>().inner().clone();
+    let mut active = state.file_watches.lock().unwrap();
+    let wanted: HashMap<String, FileWatch> = load_watches(app)
+        .into_iter()
+        .filter(|w| w.enabled)
+        .map(|w| (w.id.clone(), w))
+        .collect();
+    active.retain(|id, _| wanted.contains_key(id));
+
+    for (id, watch) in wanted {
+        if active.get(&id).is_some_and(|handle| handle.watch == watch) {
+            continue;
+        }
+        let suppression = active.remove(&id)
+            .map(|handle| handle.suppression.clone())
+            .unwrap_or_default();
+        match start_watch(app.clone(), watch, suppression) {
+            Ok(handle) => {
+                active.insert(id, handle);
+            }
+            Err(e) => tracing::warn!("[watcher] Cannot watch {id}: {e}"),
+        }
+    }
+}
+
+/// IDs of watches that currently have an OS watcher attached.
+pub fn active_watch_ids(state: &AppState) -> Vec<String> {
+    state.file_watches.lock().unwrap().keys().cloned().collect()
+}
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| format!("Invalid pattern \"{pattern}\": {e}"))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// A watch's include patterns minus its excludes (and `DEFAULT_EXCLUDES`),
+/// matched against paths relative to the project directory.
+struct PathFilter {
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+impl PathFilter {
+    fn new(watch: &FileWatch) -> Result<Self, String> {
+        let mut exclude: Vec<String> = DEFAULT_EXCLUDES.iter().map(|s| s.to_string()).collect();
+        exclude.extend(watch.exclude.iter().cloned());
+        Ok(Self { include: build_globset(&watch.patterns)?, exclude: build_globset(&exclude)? })
+    }
+
+    fn matches(&self, rel: &Path) -> bool {
+        self.include.is_match(rel) && !self.exclude.is_match(rel)
+    }
+}
+
+fn start_watch(
+    app: tauri::AppHandle,
+    watch: FileWatch,
+    suppression: Arc<Mutex<Suppression>>,
+) -> Result<FileWatchHandle, String> {
+    let root = std::fs::canonicalize(&watch.project_dir).map_err(|e| e.to_string())?;
+    let filter = PathFilter::new(&watch)?;
+
+    let (tx, rx) = mpsc::unbounded_channel::<String>();
+    let watch_root = root.clone();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else { return };
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            return;
+        }
+        for path in event.paths {
+            let Ok(rel) = path.strip_prefix(&watch_root) else { continue };
+            if filter.matches(rel) {
+                let _ = tx.send(rel.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }).map_err(|e| e.to_string())?;
+    watcher.watch(&root, RecursiveMode::Recursive).map_err(|e| e.to_string())?;
+
+    tracing::info!("[watcher] Watching {} for {:?}", root.display(), watch.patterns);
+    let task = tokio::spawn(debounce_loop(app, watch.clone(), root, rx, suppression.clone())).abort_handle();
+    Ok(FileWatchHandle { watch, suppression, _watcher: watcher, task })
+}
+
+/// Collect matching paths until `debounce_ms` passes without a new event,
+/// then trigger one run for the batch.
+async fn debounce_loop(
+    app: tauri::AppHandle,
+    watch: FileWatch,
+    root: PathBuf,
+    mut rx: mpsc::UnboundedReceiver<String>,
+    suppression: Arc<Mutex<Suppression>>,
+) {
+    let debounce = Duration::from_millis(watch.debounce_ms.max(100));
+    let suppressed = |s: &Arc<Mutex<Suppression>>| s.lock().unwrap().active();
+
+    while let Some(first) = rx.recv().await {
+        if suppressed(&suppression) {
+            continue;
+        }
+        let mut pending = BTreeSet::from([first]);
+        loop {
+            match tokio::time::timeout(debounce, rx.recv()).await {
+                Ok(Some(path)) => {
+                    if !suppressed(&suppression) {
+                        pending.insert(path);
+                    }
+                }
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+        if suppressed(&suppression) {
+            continue;
+        }
+
+        suppression.lock().unwrap().running = true;
+        let paths: Vec<String> = pending.into_iter().collect();
+        tokio::spawn(trigger(app.clone(), watch.clone(), root.clone(), paths, suppression.clone()));
+    }
+}
+
+async fn trigger(
+    app: tauri::AppHandle,
+    watch: FileWatch,
+    root: PathBuf,
+    paths: Vec<String>,
+    suppression: Arc<Mutex<Suppression>>,
+) {
+    tracing::info!("[watcher] \"{}\" triggered by {} change(s)", watch.name, paths.len());
+    let state = app.state::<Arc<AppState>>().inner().clone();
+    let diff = collect_diff(&root, &paths).await;
+    let request = RunRequest {
+        prompt: render_prompt(&watch, &paths, &diff),
+        project_dir: watch.project_dir.clone(),
+        system_prompt: watch.system_prompt.clone(),
+        agent_provider: watch.agent_provider.clone(),
+        custom_command: watch.custom_command.clone(),
+        agent_name: watch.agent_name.clone(),
+        ..Default::default()
+    };
+
+    let result = match runtime::start_run(&state, request, "watch", Some(Arc::new(app.clone()))).await {
+        Ok(rid) => {
+            let _ = app.emit("file-watch-triggered", serde_json::json!({
+                "watchId": watch.id,
+                "watchName": watch.name,
+                "responseId": rid,
+                "projectId": watch.project_id,
+                "channel": watch.channel,
+                "agentName": watch.agent_name,
+                "paths": paths,
+            }));
+            let output = runtime::collect_output(&state, &rid).await;
+            Some((rid, output))
+        }
+        Err(e) => {
+            tracing::warn!("[watcher] \"{}\" failed to start: {e}", watch.name);
+            None
+        }
+    };
+
+    {
+        let mut s = suppression.lock().unwrap();
+        s.running = false;
+        s.until = Some(Instant::now() + Duration::from_secs(watch.cooldown_secs));
+    }
+
+    if let Some((rid, output)) = result {
+        let (output, error) = match output {
+            Ok(text) => (text, None),
+            Err(e) => (String::new(), Some(e)),
+        };
+        post_result(&state, &watch, &rid, &output, error.as_deref());
+        let _ = app.emit("file-watch-finished", serde_json::json!({
+            "watchId": watch.id,
+            "watchName": watch.name,
+            "responseId": rid,
+            "projectId": watch.project_id,
+            "channel": watch.channel,
+            "agentName": watch.agent_name,
+            "paths": paths,
+            "output": output,
+            "error": error,
+        }));
+    }
+}
+
+/// Post the run's output (or error) to the watch's project chat.
+fn post_result(state: &AppState, watch: &FileWatch, rid: &str, output: &str, error: Option<&str>) {
+    let (kind, text) = match error {
+        Some(e) => ("error", format!("Agent error: {e}")),
+        None => ("response", output.to_string()),
+    };
+    if watch.project_id.is_empty() || text.is_empty() {
+        return;
+    }
+    let message = NewMessage {
+        id: rid.to_string(),
+        sender_type: "agent".into(),
+        sender_name: watch.agent_name.clone().unwrap_or_else(|| watch.name.clone()),
+        kind: kind.to_string(),
+        text,
+        parent_message_id: None,
+    };
+    if let Err(e) = state.outbox.send_message(&watch.project_id, message) {
+        tracing::warn!("[watcher] Failed to queue result: {e}");
+    }
+}
+
+// ── Prompt ────────────────────────────────────────────────────────────
+
+fn render_prompt(watch: &FileWatch, paths: &[String], diff: &str) -> String {
+    let path_list = paths.iter().map(|p| format!("- {p}")).collect::<Vec<_>>().join("\n");
+    let diff_block = if diff.is_empty() {
+        "(no diff available)".to_string()
+    } else {
+        format!("```diff\n{diff}\n```")
+    };
+
+    let explicit = prompt_template::references(&watch.prompt, "paths")
+        || prompt_template::references(&watch.prompt, "diff");
+    let mut prompt = prompt_template::render_with(&watch.prompt, &watch.project_dir, &|key| match key {
+        "paths" => Some(path_list.clone()),
+        "diff" => Some(diff_block.clone()),
+        "watch_name" => Some(watch.name.clone()),
+        "project_dir" => Some(watch.project_dir.clone()),
+        _ => None,
+    });
+    if !explicit {
+        prompt.push_str(&format!("\n\nChanged files:\n{path_list}\n\n{diff_block}"));
+    }
+    prompt
+}
+
+/// `git diff HEAD` for tracked paths plus a preview of new files. Empty when
+/// the directory isn't a git repository.
+async fn collect_diff(root: &Path, paths: &[String]) -> String {
+    let mut diff = git(root, &["diff", "--no-color", "HEAD", "--"], paths).await.unwrap_or_default();
+
+    let untracked = git(root, &["ls-files", "--others", "--exclude-standard", "--"], paths)
+        .await
+        .unwrap_or_default();
+    for rel in untracked.lines().filter(|l| !l.is_empty()) {
+        let Ok(contents) = tokio::fs::read_to_string(root.join(rel)).await else { continue };
+        let preview: Vec<&str> = contents.lines().take(NEW_FILE_PREVIEW_LINES).collect();
+        diff.push_str(&format!("\n--- /dev/null\n+++ b/{rel}\n"));
+        for line in preview {
+            diff.push('+');
+            diff.push_str(line);
+            diff.push('\n');
+        }
+    }
+
+    match diff.char_indices().nth(MAX_DIFF_CHARS) {
+        Some((idx, _)) => format!("{}\n... (diff truncated)", &diff[..idx]),
+        None => diff.trim_end().to_string(),
+    }
+}
+
+async fn git(root: &Path, args: &[&str], paths: &[String]) -> Option<String> {
+    let output = tokio::process::Command::new("git")
+        .args(args)
+        .args(paths)
+        .current_dir(root)
+        .output()
+        .await
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watch(patterns: &[&str], exclude: &[&str], prompt: &str) -> FileWatch {
+        serde_json::from_value(serde_json::json!({
+            "name": "Migrations",
+            "projectDir": "/work/site",
+            "patterns": patterns,
+            "exclude": exclude,
+            "prompt": prompt,
+        })).unwrap()
+    }
+
+    #[test]
+    fn matches_includes_minus_excludes() {
+        let filter = PathFilter::new(&watch(&["migrations/*.sql", "**/*.proto"], &["migrations/draft_*"], "")).unwrap();
+        assert!(filter.matches(Path::new("migrations/001_init.sql")));
+        assert!(filter.matches(Path::new("api/v1/user.proto")));
+        assert!(!filter.matches(Path::new("migrations/draft_002.sql")));
+        assert!(!filter.matches(Path::new("src/main.rs")));
+        // Default excludes apply on top of the watch's own
+        assert!(!filter.matches(Path::new("node_modules/pkg/api.proto")));
+        assert!(!filter.matches(Path::new(".git/objects/a.proto")));
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let err = PathFilter::new(&watch(&["src/[.rs"], &[], "")).err().unwrap();
+        assert!(err.contains("src/[.rs"));
+    }
+
+    #[test]
+    fn appends_changes_unless_the_prompt_places_them() {
+        let paths = vec!["migrations/001.sql".to_string()];
+        let implicit = render_prompt(&watch(&["*"], &[], "Review {{watch_name}}"), &paths, "+ALTER TABLE");
+        assert!(implicit.starts_with("Review Migrations\n\nChanged files:\n- migrations/001.sql"));
+        assert!(implicit.ends_with("```diff\n+ALTER TABLE\n```"));
+
+        let explicit = render_prompt(&watch(&["*"], &[], "Check:\n{{paths}}"), &paths, "");
+        assert_eq!(explicit, "Check:\n- migrations/001.sql");
+
+        let no_diff = render_prompt(&watch(&["*"], &[], "{{diff}}"), &paths, "");
+        assert_eq!(no_diff, "(no diff available)");
+    }
+
+    #[test]
+    fn suppresses_while_running_and_cooling_down() {
+        let mut suppression = Suppression::default();
+        assert!(!suppression.active());
+        suppression.running = true;
+        assert!(suppression.active());
+        suppression.running = false;
+        suppression.until = Some(Instant::now() + Duration::from_secs(60));
+        assert!(suppression.active());
+        suppression.until = Some(Instant::now() - Duration::from_secs(1));
+        assert!(!suppression.active());
+    }
+}
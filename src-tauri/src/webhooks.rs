@@ -0,0 +1,496 @@
+//! Inbound webhook listener — lets external systems (Jaibber's own outbound
+//! webhooks, CI, issue trackers) start agent runs on this machine.
+//!
+//! `POST /hooks/{route}` is matched against the routes saved in the store
+//! (`webhook_routes`). Each route maps a payload to a run template: the
+//! prompt can reference `{{payload}}`, `{{event}}` and any field by dotted
+//! path (`{{data.title}}`, `{{workflow_run.html_url}}`), with the usual
+//! `prompt_template` blocks and includes.
+//!
+//! Security:
+//! - Every request must carry an HMAC-SHA256 signature of the raw body
+//!   (`X-Jaibber-Signature` or `X-Hub-Signature-256`, hex, optional `sha256=`
+//!   prefix). When `X-Jaibber-Timestamp` is sent, the signed content is
+//!   `{timestamp}.{body}`.
+//! - A signed timestamp is required — the header, or a top-level `timestamp`
+//!   field in the (signed) payload — and must be within five minutes. Routes
+//!   for senders that sign no timestamp (GitHub) must opt out with
+//!   `allowUntimestamped`.
+//! - Verified signatures are remembered in `webhook_deliveries.json`, so a
+//!   captured request can't be replayed, even across restarts. Delivery ID
+//!   headers are not signed and play no part in this.
+//!
+//! Results go back through the normal response path: the agent's answer is
+//! posted to the route's project via the outbox, and `webhook-run-*` events
+//! are emitted app-wide.
+
+use std::collections::HashMap;
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::{Arc, Mutex};
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::oneshot;
+use crate::api_client::NewMessage;
+use crate::local_api::{api_error, constant_time_eq};
+use crate::prompt_template;
+use crate::runtime::{self, EventSink, RunRequest};
+use crate::state::AppState;
+
+pub const WEBHOOK_ROUTES_KEY: &str = "webhook_routes";
+/// Max clock skew / replay window for signed timestamps.
+const TOLERANCE_SECS: i64 = 300;
+/// How long signatures of untimestamped deliveries are remembered.
+const UNTIMESTAMPED_MEMORY_SECS: i64 = 30 * 24 * 3600;
+/// Remembered signatures (bounded; they also expire by time).
+const MAX_SEEN_DELIVERIES: usize = 10_000;
+const DELIVERIES_FILE: &str = "webhook_deliveries.json";
+/// Payload size included via `{{payload}}`.
+const MAX_PAYLOAD_CHARS: usize = 20_000;
+
+/// Maps `POST /hooks/{path}` to an agent run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookRoute {
+    /// Generated on first save when empty.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// URL segment, e.g. `ci-failure` → `/hooks/ci-failure`.
+    pub path: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Per-route secret; falls back to `AppSettings.webhook_secret`.
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Accept deliveries without a signed timestamp. Only for senders that
+    /// can't send one; a replay is then caught only while its signature is
+    /// still remembered.
+    #[serde(default)]
+    pub allow_untimestamped: bool,
+    /// Only trigger for these events (from `X-Jaibber-Event`, `X-GitHub-Event`,
+    /// `X-Gitlab-Event` or the payload's `event` field). Empty = any.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Extra conditions: dotted payload path → expected value, e.g.
+    /// `{"workflow_run.conclusion": "failure"}`.
+    #[serde(default)]
+    pub conditions: HashMap<String, String>,
+    #[serde(default)]
+    pub project_id: String,
+    #[serde(default)]
+    pub channel: String,
+    #[serde(default)]
+    pub agent_name: Option<String>,
+    #[serde(default)]
+    pub project_dir: String,
+    #[serde(default)]
+    pub agent_provider: Option<String>,
+    #[serde(default)]
+    pub custom_command: Option<String>,
+    pub prompt: String,
+    #[serde(default)]
+    pub system_prompt: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Handle to the running listener. Sending on `shutdown` stops it.
+pub struct WebhookServerHandle {
+    bind: String,
+    port: u16,
+    shutdown: oneshot::Sender<()>,
+}
+
+#[derive(Clone)]
+struct WebhookContext {
+    state: Arc<AppState>,
+    sink: Arc<dyn EventSink>,
+    seen: Arc<SeenDeliveries>,
+}
+
+// ── Lifecycle ─────────────────────────────────────────────────────────
+
+/// Start, restart or stop the listener so it matches the current settings.
+pub async fn apply_settings(state: &Arc<AppState>, sink: Arc<dyn EventSink>) {
+    let settings = state.settings.read().await.clone();
+    let mut current = state.webhook_server.lock().await;
+
+    if let Some(ref handle) = *current {
+        if settings.webhook_enabled && handle.port == settings.webhook_port && handle.bind == settings.webhook_bind {
+            return;
+        }
+    }
+    if let Some(handle) = current.take() {
+        let _ = handle.shutdown.send(());
+        tracing::info!("[webhooks] Stopped listener on {}:{}", handle.bind, handle.port);
+    }
+    if !settings.webhook_enabled {
+        return;
+    }
+
+    let listener = match tokio::net::TcpListener::bind((settings.webhook_bind.as_str(), settings.webhook_port)).await {
+        Ok(l) => l,
+        Err(e) => {
+            tracing::error!("[webhooks] Cannot bind {}:{}: {e}", settings.webhook_bind, settings.webhook_port);
+            return;
+        }
+    };
+    let seen = Arc::new(SeenDeliveries::load(&state.store_path));
+    let ctx = WebhookContext { state: state.clone(), sink, seen };
+    let app = Router::new()
+        .route("/hooks/{route}", post(receive))
+        .with_state(ctx);
+    let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let result = axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            })
+            .await;
+        if let Err(e) = result {
+            tracing::error!("[webhooks] Server error: {e}");
+        }
+    });
+
+    tracing::info!("[webhooks] Listening on http://{}:{}/hooks/", settings.webhook_bind, settings.webhook_port);
+    *current = Some(WebhookServerHandle {
+        bind: settings.webhook_bind.clone(),
+        port: settings.webhook_port,
+        shutdown,
+    });
+}
+
+// ── Handler ───────────────────────────────────────────────────────────
+
+async fn receive(
+    State(ctx): State<WebhookContext>,
+    Path(route_path): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let routes: Vec<WebhookRoute> = crate::local_store::read_key(&ctx.state.store_path, WEBHOOK_ROUTES_KEY)
+        .unwrap_or_default();
+    let Some(route) = routes.into_iter().find(|r| r.enabled && r.path == route_path) else {
+        return api_error(StatusCode::NOT_FOUND, format!("No webhook route {route_path}"));
+    };
+
+    let global_secret = ctx.state.settings.read().await.webhook_secret.clone();
+    let Some(secret) = route.secret.clone().or(global_secret).filter(|s| !s.is_empty()) else {
+        return api_error(StatusCode::SERVICE_UNAVAILABLE, "No webhook secret configured");
+    };
+
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|s| s.to_string());
+    let Some(signature) = header("x-jaibber-signature").or_else(|| header("x-hub-signature-256")) else {
+        return api_error(StatusCode::UNAUTHORIZED, "Missing signature");
+    };
+    let timestamp = header("x-jaibber-timestamp");
+    if let Err(e) = verify_signature(&secret, timestamp.as_deref(), &body, &signature, now_secs()) {
+        return api_error(StatusCode::UNAUTHORIZED, e);
+    }
+
+    let payload: Value = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, format!("Invalid JSON: {e}")),
+    };
+
+    // Replay protection: the header timestamp was checked with the
+    // signature; a payload timestamp is covered by the body's signature.
+    let timestamped = match (&timestamp, payload.get("timestamp").and_then(|t| t.as_str())) {
+        (Some(_), _) => true,
+        (None, Some(ts)) => match check_timestamp(ts, now_secs()) {
+            Ok(()) => true,
+            Err(e) => return api_error(StatusCode::UNAUTHORIZED, e),
+        },
+        (None, None) => false,
+    };
+    if !timestamped && !route.allow_untimestamped {
+        return api_error(StatusCode::UNAUTHORIZED, "Missing X-Jaibber-Timestamp");
+    }
+    let memory = if timestamped { TOLERANCE_SECS * 2 } else { UNTIMESTAMPED_MEMORY_SECS };
+    if !ctx.seen.remember(&normalize_signature(&signature), memory) {
+        return api_error(StatusCode::CONFLICT, "Duplicate delivery");
+    }
+
+    let event = header("x-jaibber-event")
+        .or_else(|| header("x-github-event"))
+        .or_else(|| header("x-gitlab-event"))
+        .or_else(|| payload.get("event").and_then(|e| e.as_str()).map(|s| s.to_string()))
+        .unwrap_or_default();
+    if !route.events.is_empty() && !route.events.iter().any(|e| e == &event) {
+        return (StatusCode::OK, Json(json!({ "ignored": true, "reason": "event not routed" }))).into_response();
+    }
+    let unmet = route.conditions.iter().find(|(path, expected)| {
+        lookup(&payload, path).map(value_text).as_deref() != Some(expected.as_str())
+    });
+    if let Some((path, _)) = unmet {
+        return (StatusCode::OK, Json(json!({ "ignored": true, "reason": format!("condition {path} not met") }))).into_response();
+    }
+
+    let request = RunRequest {
+        prompt: render_template(&route, &payload, &event),
+        project_dir: route.project_dir.clone(),
+        system_prompt: route.system_prompt.clone(),
+        agent_provider: route.agent_provider.clone(),
+        custom_command: route.custom_command.clone(),
+        agent_name: route.agent_name.clone(),
+        ..Default::default()
+    };
+    let rid = match runtime::start_run(&ctx.state, request, "webhook", Some(ctx.sink.clone())).await {
+        Ok(rid) => rid,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    tracing::info!("[webhooks] {} ({event}) → run {rid}", route.name);
+
+    ctx.sink.emit_event("webhook-run-started", json!({
+        "routeId": route.id,
+        "routeName": route.name,
+        "event": event,
+        "responseId": rid,
+        "projectId": route.project_id,
+        "channel": route.channel,
+        "agentName": route.agent_name,
+    }));
+    tokio::spawn(report(ctx.clone(), route, rid.clone()));
+
+    (StatusCode::ACCEPTED, Json(json!({ "responseId": rid }))).into_response()
+}
+
+/// Wait for the run, then post the answer to the route's project.
+async fn report(ctx: WebhookContext, route: WebhookRoute, rid: String) {
+    let result = runtime::collect_output(&ctx.state, &rid).await;
+    let (kind, text, error) = match &result {
+        Ok(text) => ("response", text.clone(), None),
+        Err(e) => ("error", format!("Agent error: {e}"), Some(e.clone())),
+    };
+
+    if !route.project_id.is_empty() && !text.is_empty() {
+        let message = NewMessage {
+            id: rid.clone(),
+            sender_type: "agent".into(),
+            sender_name: route.agent_name.clone().unwrap_or_else(|| route.name.clone()),
+            kind: kind.to_string(),
+            text: text.clone(),
+            parent_message_id: None,
+        };
+        if let Err(e) = ctx.state.outbox.send_message(&route.project_id, message) {
+            tracing::warn!("[webhooks] Failed to queue response: {e}");
+        }
+    }
+
+    ctx.sink.emit_event("webhook-run-finished", json!({
+        "routeId": route.id,
+        "routeName": route.name,
+        "responseId": rid,
+        "projectId": route.project_id,
+        "channel": route.channel,
+        "agentName": route.agent_name,
+        "output": result.unwrap_or_default(),
+        "error": error,
+    }));
+}
+
+/// Signatures of accepted deliveries and when they expire, persisted
+/// beside the store so a restart doesn't reopen the replay window.
+struct SeenDeliveries {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, i64>>,
+}
+
+impl SeenDeliveries {
+    fn load(store_path: &FsPath) -> Self {
+        let path = store_path
+            .parent()
+            .map(|dir| dir.join(DELIVERIES_FILE))
+            .unwrap_or_else(|| PathBuf::from(DELIVERIES_FILE));
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { path, entries: Mutex::new(entries) }
+    }
+
+    /// Record a signature for `memory_secs`. Returns false if it was already
+    /// recorded and hasn't expired.
+    fn remember(&self, signature: &str, memory_secs: i64) -> bool {
+        let now = now_secs();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, expires| *expires > now);
+        if entries.contains_key(signature) {
+            return false;
+        }
+        while entries.len() >= MAX_SEEN_DELIVERIES {
+            let Some(oldest) = entries.iter().min_by_key(|(_, e)| **e).map(|(k, _)| k.clone()) else { break };
+            entries.remove(&oldest);
+        }
+        entries.insert(signature.to_string(), now + memory_secs);
+        if let Err(e) = persist(&self.path, &entries) {
+            tracing::error!("[webhooks] Failed to save delivery log: {e}");
+        }
+        true
+    }
+}
+
+fn persist(path: &FsPath, entries: &HashMap<String, i64>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(entries).unwrap_or_default())?;
+    std::fs::rename(&tmp, path)
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+// ── Signatures ────────────────────────────────────────────────────────
+
+/// Check an HMAC-SHA256 signature over the body (or `{timestamp}.{body}`).
+fn verify_signature(secret: &str, timestamp: Option<&str>, body: &[u8], signature: &str, now: i64) -> Result<(), String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_err(|e| e.to_string())?;
+    if let Some(ts) = timestamp {
+        check_timestamp(ts, now)?;
+        mac.update(ts.as_bytes());
+        mac.update(b".");
+    }
+    mac.update(body);
+    let expected = hex::encode(mac.finalize().into_bytes());
+    if constant_time_eq(expected.as_bytes(), normalize_signature(signature).as_bytes()) {
+        Ok(())
+    } else {
+        Err("Invalid signature".into())
+    }
+}
+
+/// Signature as lowercase hex without the `sha256=` prefix.
+fn normalize_signature(signature: &str) -> String {
+    signature.trim().trim_start_matches("sha256=").to_lowercase()
+}
+
+fn check_timestamp(ts: &str, now: i64) -> Result<(), String> {
+    let sent = parse_timestamp(ts).ok_or("Invalid timestamp")?;
+    if (now - sent).abs() > TOLERANCE_SECS {
+        return Err("Timestamp outside tolerance".into());
+    }
+    Ok(())
+}
+
+/// Unix seconds or RFC 3339.
+fn parse_timestamp(ts: &str) -> Option<i64> {
+    ts.parse::<i64>().ok().or_else(|| {
+        chrono::DateTime::parse_from_rfc3339(ts).ok().map(|d| d.timestamp())
+    })
+}
+
+// ── Templates ─────────────────────────────────────────────────────────
+
+/// Look up a dotted path (`data.items.0.title`) in a JSON value.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |v, key| match v {
+        Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+        _ => v.get(key),
+    })
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Render a route's prompt: `{{payload}}`, `{{event}}`, `{{route}}`, and
+/// any other name as a dotted path into the payload (empty when absent).
+fn render_template(route: &WebhookRoute, payload: &Value, event: &str) -> String {
+    prompt_template::render_with(&route.prompt, &route.project_dir, &|key| {
+        Some(match key {
+            "payload" => {
+                let pretty = serde_json::to_string_pretty(payload).unwrap_or_default();
+                match pretty.char_indices().nth(MAX_PAYLOAD_CHARS) {
+                    Some((idx, _)) => format!("{}\n... (truncated)", &pretty[..idx]),
+                    None => pretty,
+                }
+            }
+            "event" => event.to_string(),
+            "route" => route.name.clone(),
+            path => lookup(payload, path).map(value_text).unwrap_or_default(),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "test-secret";
+    const BODY: &[u8] = br#"{"event":"ci.failed"}"#;
+    const NOW: i64 = 1_700_000_000;
+
+    fn sign(timestamp: Option<&str>, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+        if let Some(ts) = timestamp {
+            mac.update(ts.as_bytes());
+            mac.update(b".");
+        }
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn accepts_valid_signatures() {
+        let ts = NOW.to_string();
+        assert!(verify_signature(SECRET, Some(&ts), BODY, &sign(Some(&ts), BODY), NOW).is_ok());
+        assert!(verify_signature(SECRET, None, BODY, &sign(None, BODY), NOW).is_ok());
+        let prefixed = format!("sha256={}", sign(None, BODY).to_uppercase());
+        assert!(verify_signature(SECRET, None, BODY, &prefixed, NOW).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_signatures() {
+        let ts = NOW.to_string();
+        let signature = sign(Some(&ts), BODY);
+        assert!(verify_signature("other-secret", Some(&ts), BODY, &signature, NOW).is_err());
+        assert!(verify_signature(SECRET, Some(&ts), br#"{"event":"ci.passed"}"#, &signature, NOW).is_err());
+        // Signed without the timestamp, so a different timestamp can't be swapped in
+        assert!(verify_signature(SECRET, Some(&ts), BODY, &sign(None, BODY), NOW).is_err());
+        assert!(verify_signature(SECRET, None, BODY, "", NOW).is_err());
+    }
+
+    #[test]
+    fn rejects_stale_timestamps() {
+        let stale = (NOW - TOLERANCE_SECS - 1).to_string();
+        assert!(verify_signature(SECRET, Some(&stale), BODY, &sign(Some(&stale), BODY), NOW).is_err());
+        let future = (NOW + TOLERANCE_SECS + 1).to_string();
+        assert!(check_timestamp(&future, NOW).is_err());
+        assert!(check_timestamp("yesterday", NOW).is_err());
+        assert!(check_timestamp(&(NOW - TOLERANCE_SECS).to_string(), NOW).is_ok());
+        assert!(check_timestamp("2023-11-14T22:13:20Z", NOW).is_ok());
+    }
+
+    #[test]
+    fn remembers_deliveries_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("jaibber-webhooks-{}", uuid::Uuid::new_v4()));
+        let store_path = dir.join("jaibber.json");
+
+        let seen = SeenDeliveries::load(&store_path);
+        assert!(seen.remember("abc", 600));
+        assert!(!seen.remember("abc", 600));
+        assert!(SeenDeliveries::load(&store_path).remember("def", 600));
+        assert!(!SeenDeliveries::load(&store_path).remember("abc", 600));
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
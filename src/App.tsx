@@ -11,6 +11,7 @@ import { useOrgStore } from "@/stores/orgStore";
 import { syncRegistrations } from "@/lib/agentSync";
 import { AppShell } from "@/components/layout/AppShell";
 import { LoginScreen } from "@/components/auth/LoginScreen";
+import { ToolApprovalDialog } from "@/components/chat/ToolApprovalDialog";
 
 // Schema version — bump this to clear stale local data from old app versions
 const SCHEMA_VERSION = 2;
@@ -68,14 +69,12 @@ function App({ onRequireLogin }: AppProps = {}) {
           const savedSettings = await storage.get<typeof settings>("app_settings");
           if (savedSettings?.apiBaseUrl) {
             apiBaseUrl = savedSettings.apiBaseUrl;
-            useSettingsStore.getState().setSettings(savedSettings);
-            await saveSettings(savedSettings);
+            useSettingsStore.getState().setSettings(await saveSettings(savedSettings));
           } else {
             const savedUrl = await storage.get<string>("api_base_url");
             apiBaseUrl = savedUrl || "https://api.jaibber.com";
             const recovered = { ...settings, apiBaseUrl };
-            useSettingsStore.getState().setSettings(recovered);
-            await saveSettings(recovered);
+            useSettingsStore.getState().setSettings(await saveSettings(recovered));
           }
         } else {
           useSettingsStore.getState().setSettings(settings);
@@ -157,7 +156,7 @@ function App({ onRequireLogin }: AppProps = {}) {
       await storage.set(SCHEMA_KEY, SCHEMA_VERSION);
       await storage.set("auth", { token: auth.token, userId: auth.userId, username: auth.username });
       await storage.set("api_base_url", settings.apiBaseUrl);
-      await saveSettings(settings);
+      useSettingsStore.getState().setSettings(await saveSettings(settings));
 
       if (settings.apiBaseUrl) {
         try {
@@ -222,6 +221,7 @@ function App({ onRequireLogin }: AppProps = {}) {
         </div>
       )}
       <AppShell />
+      {isTauri && <ToolApprovalDialog />}
     </>
   );
 }
@@ -55,8 +55,7 @@ export function LoginScreen({ onLogin }: Props) {
       useAuthStore.getState().setAuth(data.token, data.userId, data.username);
       await storage.set("auth", { token: data.token, userId: data.userId, username: data.username });
       const updatedSettings = { ...useSettingsStore.getState().settings, apiBaseUrl };
-      useSettingsStore.getState().setSettings(updatedSettings);
-      await saveSettings(updatedSettings);
+      useSettingsStore.getState().setSettings(await saveSettings(updatedSettings));
       onLogin();
     } catch (e) {
       setError(`Network error: ${e}`);
@@ -145,8 +144,7 @@ export function LoginScreen({ onLogin }: Props) {
       useAuthStore.getState().setAuth(trimmed, data.userId, data.username);
       await storage.set("auth", { token: trimmed, userId: data.userId, username: data.username });
       const updatedSettings = { ...useSettingsStore.getState().settings, apiBaseUrl };
-      useSettingsStore.getState().setSettings(updatedSettings);
-      await saveSettings(updatedSettings);
+      useSettingsStore.getState().setSettings(await saveSettings(updatedSettings));
       onLogin();
     } catch (e) {
       setError(`Network error: ${e}`);
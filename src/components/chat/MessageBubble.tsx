@@ -1,5 +1,5 @@
 import { cn } from "@/lib/cn";
-import type { Message } from "@/types/message";
+import type { Message, ToolCall } from "@/types/message";
 import type { MessageAttachment } from "@/types/attachment";
 import { formatFileSize, isImageMime } from "@/lib/attachmentApi";
 import { TypingIndicator } from "./TypingIndicator";
@@ -91,6 +91,40 @@ function renderAttachments(attachments: MessageAttachment[] | undefined, isMe: b
   );
 }
 
+/** One-line summary of a tool call's main argument. */
+export function toolCallSummary(name: string, input: Record<string, unknown>) {
+  const arg = input.command ?? input.path ?? input.pattern;
+  return typeof arg === "string" ? `${name} ${arg}` : name;
+}
+
+function renderToolCalls(calls: ToolCall[] | undefined) {
+  if (!calls?.length) return null;
+  return (
+    <details className="mb-1.5 text-xs text-muted-foreground">
+      <summary className="cursor-pointer select-none">
+        Tools ({calls.length}){calls.some((c) => c.status === "running") && " …"}
+      </summary>
+      <div className="mt-1 pl-2 border-l-2 border-border space-y-1">
+        {calls.map((call) => (
+          <div key={call.id}>
+            <div className="font-mono truncate">
+              <span className={cn(call.status === "error" && "text-destructive")}>
+                {call.status === "running" ? "⋯" : call.status === "error" ? "✗" : "✓"}
+              </span>{" "}
+              {toolCallSummary(call.name, call.input)}
+            </div>
+            {call.output && (
+              <pre className="mt-0.5 max-h-32 overflow-y-auto whitespace-pre-wrap font-mono text-[10px] opacity-80">
+                {call.output}
+              </pre>
+            )}
+          </div>
+        ))}
+      </div>
+    </details>
+  );
+}
+
 export function MessageBubble({ message, onCreateTask, onReply }: Props) {
   const isMe = message.sender === "me";
   const isStreaming = message.status === "streaming";
@@ -120,10 +154,19 @@ export function MessageBubble({ message, onCreateTask, onReply }: Props) {
             <div className="truncate max-w-[250px]">{message.parentMessage.text}</div>
           </div>
         )}
-        {isStreaming && !message.text ? (
+        {isStreaming && !message.text && !message.thinking && !message.toolCalls?.length ? (
           <TypingIndicator />
         ) : (
           <>
+            {message.thinking && (
+              <details className="mb-1.5 text-xs text-muted-foreground">
+                <summary className="cursor-pointer select-none">Thinking</summary>
+                <div className="mt-1 pl-2 border-l-2 border-border whitespace-pre-wrap">
+                  {message.thinking}
+                </div>
+              </details>
+            )}
+            {renderToolCalls(message.toolCalls)}
             {renderText(message.text)}
             {renderAttachments(message.attachments, isMe)}
             {isStreaming && <TypingIndicator inline />}
@@ -0,0 +1,85 @@
+import { useState } from "react";
+import {
+  AlertDialog,
+  AlertDialogAction,
+  AlertDialogCancel,
+  AlertDialogContent,
+  AlertDialogDescription,
+  AlertDialogFooter,
+  AlertDialogHeader,
+  AlertDialogTitle,
+} from "@/components/ui/alert-dialog";
+import { useTauriEvent } from "@/hooks/useTauriEvent";
+import { approveToolCall } from "@/lib/platform";
+import { toolCallSummary } from "./MessageBubble";
+
+interface PendingCall {
+  responseId: string;
+  toolUseId: string;
+  name: string;
+  input: Record<string, unknown>;
+}
+
+const PREVIEW_CHARS = 2000;
+
+function preview(value: unknown) {
+  const text = typeof value === "string" ? value : JSON.stringify(value, null, 2);
+  return text.length > PREVIEW_CHARS ? text.slice(0, PREVIEW_CHARS) + "\n…" : text;
+}
+
+/**
+ * Approval prompts for tool calls from Claude API runs whose policy is
+ * "ask". Prompts queue up and are shown one at a time; a prompt that times
+ * out in the backend disappears when its result arrives.
+ */
+export function ToolApprovalDialog() {
+  const [queue, setQueue] = useState<PendingCall[]>([]);
+  const current = queue[0];
+
+  useTauriEvent<PendingCall>("agent-tool-approval", (call) => {
+    setQueue((q) => [...q, call]);
+  });
+  useTauriEvent<{ responseId: string; toolUseId: string }>("agent-tool-result", (result) => {
+    setQueue((q) => q.filter((c) => !(c.responseId === result.responseId && c.toolUseId === result.toolUseId)));
+  });
+
+  const answer = (approved: boolean) => {
+    if (!current) return;
+    setQueue((q) => q.slice(1));
+    approveToolCall(current.responseId, current.toolUseId, approved)
+      .catch((e) => console.error("[tools] approve_tool_call failed:", e));
+  };
+
+  const { path, command, content, old_string, new_string } = current?.input ?? {};
+  const details = command ?? content ?? (old_string !== undefined ? { old_string, new_string } : undefined);
+
+  return (
+    <AlertDialog open={!!current}>
+      <AlertDialogContent>
+        <AlertDialogHeader>
+          <AlertDialogTitle>Allow {current?.name}?</AlertDialogTitle>
+          <AlertDialogDescription>
+            An agent wants to {current?.name === "run_command" ? "run a command" : "change a file"} in its project
+            {queue.length > 1 && ` (${queue.length - 1} more waiting)`}.
+          </AlertDialogDescription>
+        </AlertDialogHeader>
+        {current && (
+          <div className="space-y-2 min-w-0">
+            <div className="font-mono text-xs truncate">
+              {typeof path === "string" ? path : toolCallSummary(current.name, current.input)}
+            </div>
+            {details !== undefined && (
+              <pre className="bg-muted/40 rounded-md p-3 text-xs font-mono max-h-64 overflow-auto whitespace-pre-wrap">
+                {preview(details)}
+              </pre>
+            )}
+          </div>
+        )}
+        <AlertDialogFooter>
+          <AlertDialogCancel onClick={() => answer(false)}>Decline</AlertDialogCancel>
+          <AlertDialogAction onClick={() => answer(true)}>Allow</AlertDialogAction>
+        </AlertDialogFooter>
+      </AlertDialogContent>
+    </AlertDialog>
+  );
+}
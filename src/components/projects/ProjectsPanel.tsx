@@ -1,5 +1,5 @@
-import { useState } from "react";
-import { storage, isTauri } from "@/lib/platform";
+import { useEffect, useState } from "react";
+import { storage, isTauri, listModels, type ModelConfig } from "@/lib/platform";
 import { useProjectStore } from "@/stores/projectStore";
 import { useContactStore } from "@/stores/contactStore";
 import { useChatStore } from "@/stores/chatStore";
@@ -55,6 +55,99 @@ function ProviderSelect({ value, onChange, inputClass }: { value: string; onChan
   );
 }
 
+/** Model picker plus optional sampling settings for an agent. */
+function ModelConfigFields({ provider, value, onChange, inputClass }: {
+  provider: string;
+  value: ModelConfig;
+  onChange: (v: ModelConfig) => void;
+  inputClass: string;
+}) {
+  const [models, setModels] = useState<string[]>([]);
+
+  useEffect(() => {
+    let cancelled = false;
+    setModels([]);
+    if (provider === "custom") return;
+    listModels(provider)
+      .then((result) => {
+        if (!cancelled) setModels(result.flatMap((p) => p.models.map((m) => m.id)));
+      })
+      .catch(() => {});
+    return () => { cancelled = true; };
+  }, [provider]);
+
+  if (provider === "custom") return null;
+
+  const num = (v: string) => (v.trim() === "" || isNaN(Number(v)) ? undefined : Number(v));
+  const listId = `models-${provider}`;
+
+  return (
+    <div className="space-y-1.5">
+      <div>
+        <label className="block text-[10px] text-muted-foreground mb-0.5">
+          Model <span className="opacity-60">(empty for the provider default)</span>
+        </label>
+        <input
+          type="text"
+          list={listId}
+          value={value.model ?? ""}
+          onChange={(e) => onChange({ ...value, model: e.target.value.trim() || undefined })}
+          placeholder="e.g. claude-sonnet-4-20250514"
+          className={inputClass + " text-xs font-mono"}
+        />
+        <datalist id={listId}>
+          {models.map((m) => <option key={m} value={m} />)}
+        </datalist>
+      </div>
+      <details className="text-[10px] text-muted-foreground">
+        <summary className="cursor-pointer select-none">
+          Sampling <span className="opacity-60">(direct API and OpenClaw only)</span>
+        </summary>
+        <div className="grid grid-cols-3 gap-1.5 mt-1">
+          <input
+            type="number"
+            min={1}
+            value={value.maxTokens ?? ""}
+            onChange={(e) => onChange({ ...value, maxTokens: num(e.target.value) })}
+            placeholder="Max tokens"
+            className={inputClass + " text-xs"}
+          />
+          <input
+            type="number"
+            min={0}
+            max={1}
+            step={0.1}
+            value={value.temperature ?? ""}
+            onChange={(e) => onChange({ ...value, temperature: num(e.target.value) })}
+            placeholder="Temperature"
+            className={inputClass + " text-xs"}
+          />
+          <input
+            type="number"
+            min={0}
+            max={1}
+            step={0.05}
+            value={value.topP ?? ""}
+            onChange={(e) => onChange({ ...value, topP: num(e.target.value) })}
+            placeholder="Top p"
+            className={inputClass + " text-xs"}
+          />
+        </div>
+        <input
+          type="text"
+          value={(value.stopSequences ?? []).join(", ")}
+          onChange={(e) => {
+            const stops = e.target.value.split(",").map((x) => x.trim()).filter(Boolean);
+            onChange({ ...value, stopSequences: stops.length ? stops : undefined });
+          }}
+          placeholder="Stop sequences, comma-separated"
+          className={inputClass + " text-xs mt-1.5"}
+        />
+      </details>
+    </div>
+  );
+}
+
 function TemplateSelector({ onSelect }: { onSelect: (t: AgentTemplate) => void }) {
   return (
     <div>
@@ -104,6 +197,8 @@ function ProjectCard({ contact }: { contact: Contact }) {
   const [editAgentInstructions, setEditAgentInstructions] = useState("");
   const [editAgentProvider, setEditAgentProvider] = useState("claude");
   const [editCustomCommand, setEditCustomCommand] = useState("");
+  const [editThinkingBudget, setEditThinkingBudget] = useState("");
+  const [editModelConfig, setEditModelConfig] = useState<ModelConfig>({});
 
   // Link state for registering a new agent on this machine
   const [linking, setLinking] = useState(false);
@@ -232,6 +327,8 @@ function ProjectCard({ contact }: { contact: Contact }) {
     setEditAgentInstructions(lp.agentInstructions || "");
     setEditAgentProvider(lp.agentProvider || "claude");
     setEditCustomCommand(lp.customCommand || "");
+    setEditThinkingBudget(lp.thinkingBudget ? String(lp.thinkingBudget) : "");
+    setEditModelConfig(lp.modelConfig ?? {});
     setEditingAgent(lp.agentName);
   };
 
@@ -258,6 +355,12 @@ function ProjectCard({ contact }: { contact: Contact }) {
       agentInstructions: editAgentInstructions.trim(),
       agentProvider: editAgentProvider,
       customCommand: editAgentProvider === "custom" ? editCustomCommand.trim() : undefined,
+      thinkingBudget: editAgentProvider === "claude" && Number(editThinkingBudget) > 0
+        ? Math.floor(Number(editThinkingBudget))
+        : undefined,
+      modelConfig: editAgentProvider !== "custom" && Object.values(editModelConfig).some((v) => v !== undefined)
+        ? editModelConfig
+        : undefined,
     };
     useProjectStore.getState().addProject(updated);
     saveProjects(useProjectStore.getState().projects);
@@ -620,6 +723,28 @@ function ProjectCard({ contact }: { contact: Contact }) {
                 />
               </div>
             )}
+            <ModelConfigFields
+              provider={editAgentProvider}
+              value={editModelConfig}
+              onChange={setEditModelConfig}
+              inputClass={inputClass}
+            />
+            {editAgentProvider === "claude" && (
+              <div>
+                <label className="block text-[10px] text-muted-foreground mb-0.5">
+                  Extended thinking budget <span className="opacity-60">(tokens, min 1024 — empty to disable)</span>
+                </label>
+                <input
+                  type="number"
+                  min={0}
+                  step={1024}
+                  value={editThinkingBudget}
+                  onChange={(e) => setEditThinkingBudget(e.target.value)}
+                  placeholder="e.g. 8000"
+                  className={inputClass + " text-xs"}
+                />
+              </div>
+            )}
             <textarea
               value={editAgentInstructions}
               onChange={(e) => setEditAgentInstructions(e.target.value)}
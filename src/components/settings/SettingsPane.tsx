@@ -2,6 +2,8 @@ import { useState, useEffect, useRef } from "react";
 import { isTauri } from "@/lib/platform";
 import { useOrgStore } from "@/stores/orgStore";
 import { GeneralSection } from "./sections/GeneralSection";
+import { AgentToolsSection } from "./sections/AgentToolsSection";
+import { LocalServicesSection } from "./sections/LocalServicesSection";
 import { SecuritySection } from "./sections/SecuritySection";
 import { ProjectsSection } from "./sections/ProjectsSection";
 import { OrganizationSection } from "./sections/OrganizationSection";
@@ -12,6 +14,8 @@ import { cn } from "@/lib/cn";
 
 type Section =
   | "general"
+  | "agent-tools"
+  | "local-services"
   | "security"
   | "projects"
   | "organization"
@@ -28,6 +32,8 @@ interface NavItem {
 
 const NAV_ITEMS: NavItem[] = [
   { id: "general", label: "General" },
+  { id: "agent-tools", label: "Agent Tools", desktopOnly: true },
+  { id: "local-services", label: "Local Services", desktopOnly: true },
   { id: "security", label: "Security" },
   { id: "projects", label: "Projects" },
   { id: "organization", label: "Organization" },
@@ -188,6 +194,8 @@ export function SettingsPane({ onClose, initialSection, onSectionChange }: Props
 
     switch (activeSection) {
       case "general": return <GeneralSection />;
+      case "agent-tools": return <AgentToolsSection />;
+      case "local-services": return <LocalServicesSection />;
       case "security": return <SecuritySection />;
       case "projects": return <ProjectsSection />;
       case "organization": return <OrganizationSection />;
@@ -0,0 +1,115 @@
+import { useState } from "react";
+import { saveSettings } from "@/lib/platform";
+import { useSettingsStore } from "@/stores/settingsStore";
+import type { AppSettings } from "@/types/settings";
+
+type ApiTools = NonNullable<AppSettings["apiTools"]>;
+type Permission = ApiTools["read"];
+
+/** Mirrors `ApiToolPolicy::default()` in the backend. */
+const DEFAULT_API_TOOLS: ApiTools = {
+  enabled: true,
+  read: "allow",
+  write: "ask",
+  runCommand: "ask",
+  allowedCommands: [],
+};
+
+const CLASSES: Array<{ key: "read" | "write" | "runCommand"; label: string; tools: string }> = [
+  { key: "read", label: "Read", tools: "read_file, list_dir, grep" },
+  { key: "write", label: "Write", tools: "write_file, apply_patch" },
+  { key: "runCommand", label: "Run commands", tools: "run_command" },
+];
+
+export function AgentToolsSection() {
+  const settings = useSettingsStore((s) => s.settings);
+  const [tools, setTools] = useState<ApiTools>({ ...DEFAULT_API_TOOLS, ...settings.apiTools });
+  const [allowedText, setAllowedText] = useState((settings.apiTools?.allowedCommands ?? []).join("\n"));
+  const [saving, setSaving] = useState(false);
+  const [saved, setSaved] = useState(false);
+
+  const handleSave = async () => {
+    setSaving(true);
+    try {
+      const apiTools: ApiTools = {
+        ...tools,
+        allowedCommands: allowedText.split("\n").map((l) => l.trim()).filter(Boolean),
+      };
+      const stored = await saveSettings({ ...settings, apiTools });
+      useSettingsStore.getState().setSettings(stored);
+      setSaved(true);
+      setTimeout(() => setSaved(false), 2000);
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  const selectClass = "bg-muted/40 border border-input rounded-lg px-2 py-1.5 text-sm text-foreground focus:outline-none focus:ring-1 focus:ring-primary/50";
+
+  return (
+    <div className="space-y-8">
+      <div className="border-b border-border pb-6">
+        <h2 className="text-lg font-semibold text-foreground mb-1">Agent Tools</h2>
+        <p className="text-sm text-muted-foreground mb-4">
+          Local tools for Claude agents that run through the Anthropic API (with a fallback key) instead of the CLI.
+          Calls set to &ldquo;Ask&rdquo; wait for your approval; runs started from the local API or MCP can&rsquo;t ask and are refused.
+        </p>
+        <div className="space-y-4 max-w-md">
+          <label className="flex items-center gap-2 text-sm text-foreground">
+            <input
+              type="checkbox"
+              checked={tools.enabled}
+              onChange={(e) => setTools({ ...tools, enabled: e.target.checked })}
+            />
+            Offer tools to API agents
+          </label>
+
+          {CLASSES.map(({ key, label, tools: names }) => (
+            <div key={key} className="flex items-center justify-between gap-4">
+              <div>
+                <div className="text-sm text-foreground">{label}</div>
+                <div className="text-[11px] text-muted-foreground font-mono">{names}</div>
+              </div>
+              <select
+                value={tools[key]}
+                disabled={!tools.enabled}
+                onChange={(e) => setTools({ ...tools, [key]: e.target.value as Permission })}
+                className={selectClass}
+              >
+                <option value="allow">Allow</option>
+                <option value="ask">Ask</option>
+                <option value="deny">Deny</option>
+              </select>
+            </div>
+          ))}
+
+          <div>
+            <label className="block text-xs font-medium text-muted-foreground mb-1.5">
+              Commands that run without asking <span className="font-normal opacity-60">(one per line)</span>
+            </label>
+            <textarea
+              value={allowedText}
+              onChange={(e) => setAllowedText(e.target.value)}
+              disabled={!tools.enabled}
+              rows={4}
+              placeholder={"cargo test\nnpm run lint"}
+              className="w-full bg-muted/40 border border-input rounded-lg px-3 py-2 text-sm font-mono text-foreground placeholder:text-muted-foreground focus:outline-none focus:ring-1 focus:ring-primary/50"
+            />
+            <p className="text-[11px] text-muted-foreground/70 mt-1">
+              Matches the command followed by plain arguments. Commands with <code>;</code>, <code>&amp;</code>,{" "}
+              <code>|</code>, redirects or substitutions always ask.
+            </p>
+          </div>
+
+          <button
+            onClick={handleSave}
+            disabled={saving}
+            className="bg-primary text-primary-foreground rounded-lg px-4 py-2 text-sm font-medium hover:bg-primary/90 transition-all disabled:opacity-50"
+          >
+            {saved ? "Saved!" : saving ? "Saving..." : "Save Settings"}
+          </button>
+        </div>
+      </div>
+    </div>
+  );
+}
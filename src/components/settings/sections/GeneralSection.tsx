@@ -35,9 +35,9 @@ export function GeneralSection() {
         openaiApiKey: openaiKey || null,
         googleApiKey: googleKey || null,
       };
-      await saveSettings(updated);
+      const stored = await saveSettings(updated);
       await storage.set("schema_version", 2);
-      useSettingsStore.getState().setSettings(updated);
+      useSettingsStore.getState().setSettings(stored);
       setSaved(true);
       setTimeout(() => setSaved(false), 2000);
     } finally {
@@ -0,0 +1,169 @@
+import { useState } from "react";
+import { saveSettings } from "@/lib/platform";
+import { useSettingsStore } from "@/stores/settingsStore";
+import type { AppSettings } from "@/types/settings";
+
+/** A generated credential with reveal, copy and regenerate controls. */
+function SecretField({ label, value, onRegenerate, disabled }: {
+  label: string;
+  value: string | null | undefined;
+  onRegenerate: () => void;
+  disabled: boolean;
+}) {
+  const [revealed, setRevealed] = useState(false);
+  const [copied, setCopied] = useState(false);
+
+  const copy = async () => {
+    if (!value) return;
+    await navigator.clipboard.writeText(value);
+    setCopied(true);
+    setTimeout(() => setCopied(false), 1500);
+  };
+
+  const buttonClass = "text-xs text-muted-foreground hover:text-foreground transition-colors disabled:opacity-50";
+
+  return (
+    <div>
+      <label className="block text-xs font-medium text-muted-foreground mb-1.5">{label}</label>
+      {value ? (
+        <div className="flex items-center gap-3">
+          <code className="flex-1 min-w-0 truncate bg-muted/40 border border-input rounded-lg px-3 py-2 text-xs font-mono text-foreground">
+            {revealed ? value : "•".repeat(24)}
+          </code>
+          <button onClick={() => setRevealed(!revealed)} className={buttonClass}>{revealed ? "Hide" : "Show"}</button>
+          <button onClick={copy} className={buttonClass}>{copied ? "Copied" : "Copy"}</button>
+          <button onClick={onRegenerate} disabled={disabled} className={buttonClass}>Regenerate</button>
+        </div>
+      ) : (
+        <p className="text-[11px] text-muted-foreground/70">Generated when you enable it and save.</p>
+      )}
+    </div>
+  );
+}
+
+export function LocalServicesSection() {
+  const settings = useSettingsStore((s) => s.settings);
+  const [localApiEnabled, setLocalApiEnabled] = useState(settings.localApiEnabled ?? false);
+  const [localApiPort, setLocalApiPort] = useState(String(settings.localApiPort ?? 7420));
+  const [webhookEnabled, setWebhookEnabled] = useState(settings.webhookEnabled ?? false);
+  const [webhookBind, setWebhookBind] = useState(settings.webhookBind ?? "127.0.0.1");
+  const [webhookPort, setWebhookPort] = useState(String(settings.webhookPort ?? 7421));
+  const [saving, setSaving] = useState(false);
+  const [saved, setSaved] = useState(false);
+  const [error, setError] = useState<string | null>(null);
+
+  /** Save the form; `extra` can blank a credential so the backend replaces it. */
+  const save = async (extra: Partial<AppSettings> = {}) => {
+    setSaving(true);
+    setError(null);
+    try {
+      const stored = await saveSettings({
+        ...settings,
+        localApiEnabled,
+        localApiPort: Number(localApiPort) || 7420,
+        webhookEnabled,
+        webhookBind: webhookBind.trim() || "127.0.0.1",
+        webhookPort: Number(webhookPort) || 7421,
+        ...extra,
+      });
+      useSettingsStore.getState().setSettings(stored);
+      setSaved(true);
+      setTimeout(() => setSaved(false), 2000);
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setSaving(false);
+    }
+  };
+
+  const inputClass = "w-full bg-muted/40 border border-input rounded-lg px-3 py-2 text-sm text-foreground placeholder:text-muted-foreground focus:outline-none focus:ring-1 focus:ring-primary/50";
+  const webhookHost = webhookBind === "0.0.0.0" ? "<this-machine>" : webhookBind;
+
+  return (
+    <div className="space-y-8">
+      <div className="border-b border-border pb-6">
+        <h2 className="text-lg font-semibold text-foreground mb-1">Local API</h2>
+        <p className="text-sm text-muted-foreground mb-4">
+          Start agent runs from scripts and editors on this machine over HTTP and WebSocket
+          (<code>http://127.0.0.1:{localApiPort}</code>), including the OpenAI-compatible <code>/v1</code> endpoints.
+          Requests must send the token as <code>Authorization: Bearer &lt;token&gt;</code>.
+        </p>
+        <div className="space-y-4 max-w-md">
+          <label className="flex items-center gap-2 text-sm text-foreground">
+            <input type="checkbox" checked={localApiEnabled} onChange={(e) => setLocalApiEnabled(e.target.checked)} />
+            Enable the local API
+          </label>
+          <div>
+            <label className="block text-xs font-medium text-muted-foreground mb-1.5">Port</label>
+            <input
+              type="number"
+              value={localApiPort}
+              onChange={(e) => setLocalApiPort(e.target.value)}
+              className={inputClass}
+            />
+          </div>
+          <SecretField
+            label="Token"
+            value={settings.localApiToken}
+            disabled={saving}
+            onRegenerate={() => save({ localApiToken: "" })}
+          />
+        </div>
+      </div>
+
+      <div className="border-b border-border pb-6">
+        <h2 className="text-lg font-semibold text-foreground mb-1">Inbound Webhooks</h2>
+        <p className="text-sm text-muted-foreground mb-4">
+          Let CI, issue trackers or Jaibber webhooks start agent runs at{" "}
+          <code>http://{webhookHost}:{webhookPort}/hooks/&lt;route&gt;</code>. Senders sign the body with the secret
+          (HMAC-SHA256 in <code>X-Jaibber-Signature</code>) and send <code>X-Jaibber-Timestamp</code>.
+        </p>
+        <div className="space-y-4 max-w-md">
+          <label className="flex items-center gap-2 text-sm text-foreground">
+            <input type="checkbox" checked={webhookEnabled} onChange={(e) => setWebhookEnabled(e.target.checked)} />
+            Accept webhooks
+          </label>
+          <div className="flex gap-3">
+            <div className="flex-1">
+              <label className="block text-xs font-medium text-muted-foreground mb-1.5">
+                Interface <span className="font-normal opacity-60">(0.0.0.0 to accept from the network)</span>
+              </label>
+              <input
+                type="text"
+                value={webhookBind}
+                onChange={(e) => setWebhookBind(e.target.value)}
+                className={inputClass}
+              />
+            </div>
+            <div className="w-28">
+              <label className="block text-xs font-medium text-muted-foreground mb-1.5">Port</label>
+              <input
+                type="number"
+                value={webhookPort}
+                onChange={(e) => setWebhookPort(e.target.value)}
+                className={inputClass}
+              />
+            </div>
+          </div>
+          <SecretField
+            label="Signing secret"
+            value={settings.webhookSecret}
+            disabled={saving}
+            onRegenerate={() => save({ webhookSecret: "" })}
+          />
+        </div>
+      </div>
+
+      <div className="space-y-2">
+        {error && <p className="text-xs text-destructive">{error}</p>}
+        <button
+          onClick={() => save()}
+          disabled={saving}
+          className="bg-primary text-primary-foreground rounded-lg px-4 py-2 text-sm font-medium hover:bg-primary/90 transition-all disabled:opacity-50"
+        >
+          {saved ? "Saved!" : saving ? "Saving..." : "Save Settings"}
+        </button>
+      </div>
+    </div>
+  );
+}
@@ -9,7 +9,7 @@ import { useProjectStore, type LocalProject } from "@/stores/projectStore";
 import { runAgentStream, listenEvent, isTauri } from "@/lib/platform";
 import { parseMentions, mentionsAgent } from "@/lib/mentions";
 import { persistMessage } from "@/lib/messageApi";
-import { updateTask, createTask } from "@/lib/taskApi";
+import { queueTaskUpdate, createTask } from "@/lib/taskApi";
 import { useTaskStore } from "@/stores/taskStore";
 import type { AblyMessage, ExecutionMode } from "@/types/message";
 import type { MessageAttachment } from "@/types/attachment";
@@ -76,15 +76,23 @@ async function respondToMessage(
     agentName,
   } satisfies AblyMessage);
 
-  // Build conversation context from recent chat history.
-  const recentMessages = (useChatStore.getState().messages[convId] ?? [])
+  // Recent chat history; the backend trims it to the token budget.
+  const contextMessages = (useChatStore.getState().messages[convId] ?? [])
     .filter((m) => m.status === "done" && m.id !== responseId)
-    .slice(-20)
-    .map((m) => {
-      const role = m.sender === "me" ? "User" : `Assistant (${m.senderName || "Agent"})`;
-      return `${role}: ${m.text}`;
-    })
-    .join("\n\n");
+    .slice(-100)
+    .map((m) => ({
+      author: m.sender === "me" ? "" : m.senderName || "Agent",
+      role: m.sender === "me" ? "user" as const : "assistant" as const,
+      text: m.text,
+      timestamp: m.timestamp,
+      attachments: m.attachments?.map(att => ({
+        id: att.id,
+        filename: att.filename,
+        mimeType: att.mimeType,
+        fileSize: att.fileSize,
+        blobUrl: att.blobUrl,
+      })),
+    }));
 
   // Ably chunk batching: accumulate chunks, flush every 200ms
   let chunkBuffer = "";
@@ -104,8 +112,10 @@ async function respondToMessage(
     } satisfies AblyMessage);
   };
 
-  // Cleanup helper — unlistens chunk, auth-fallback, and session events
-  const cleanup = () => { unlisten(); unlistenAuth(); unlistenSession(); };
+  // Cleanup helper — unlistens chunk, thinking, tool, auth-fallback, and session events
+  const cleanup = () => {
+    unlisten(); unlistenThinking(); unlistenToolUse(); unlistenToolResult(); unlistenAuth(); unlistenSession();
+  };
 
   // Listen for streaming events from Rust
   const unlisten = await listenEvent<{
@@ -193,6 +203,46 @@ async function respondToMessage(
     }
   });
 
+  // Listen for extended thinking — kept on the local message only, not published
+  const unlistenThinking = await listenEvent<{
+    responseId: string;
+    chunk: string;
+  }>("agent-thinking", (event) => {
+    if (event.responseId !== responseId) return;
+    useChatStore.getState().appendThinking(convId, responseId, event.chunk);
+  });
+
+  // Listen for local tool calls (Claude API runs) — shown on the local message only
+  const unlistenToolUse = await listenEvent<{
+    responseId: string;
+    toolUseId: string;
+    name: string;
+    input: Record<string, unknown>;
+  }>("agent-tool-use", (event) => {
+    if (event.responseId !== responseId) return;
+    useChatStore.getState().upsertToolCall(convId, responseId, {
+      id: event.toolUseId,
+      name: event.name,
+      input: event.input,
+      status: "running",
+    });
+  });
+  const unlistenToolResult = await listenEvent<{
+    responseId: string;
+    toolUseId: string;
+    name: string;
+    isError: boolean;
+    output: string;
+  }>("agent-tool-result", (event) => {
+    if (event.responseId !== responseId) return;
+    useChatStore.getState().upsertToolCall(convId, responseId, {
+      id: event.toolUseId,
+      name: event.name,
+      status: event.isError ? "error" : "done",
+      output: event.output,
+    });
+  });
+
   // Listen for auth fallback events — show a subtle notice in the chat bubble
   const unlistenAuth = await listenEvent<{
     responseId: string;
@@ -258,7 +308,11 @@ async function respondToMessage(
       projectDir: localProject.projectDir,
       responseId,
       systemPrompt,
-      conversationContext: recentMessages,
+      conversationContext: "",
+      contextMessages,
+      threadId: convId,
+      thinkingBudget: localProject.thinkingBudget,
+      modelConfig: localProject.modelConfig,
       agentProvider: localProject.agentProvider || "claude",
       attachments: attachments?.map(att => ({
         id: att.id,
@@ -268,6 +322,7 @@ async function respondToMessage(
         blobUrl: att.blobUrl,
       })),
       sessionId,
+      agentName,
     });
   } catch (err) {
     if (flushTimer) clearTimeout(flushTimer);
@@ -404,8 +459,10 @@ function subscribeToProjectChannel(
       }
 
       // Auto-execute: if this task is assigned to a local agent and is "submitted", pick it up
+      // (unless the backend task worker is handling tasks for this machine)
       if (
         isTauri &&
+        !useSettingsStore.getState().settings.taskWorkerEnabled &&
         (data.type === "task-created" || data.type === "task-updated") &&
         data.task.status === "submitted" &&
         data.task.assignedAgentName
@@ -420,7 +477,7 @@ function subscribeToProjectChannel(
 
           // Mark task as "working"
           if (tkn && base) {
-            updateTask(base, tkn, data.task.id, { status: "working" }).catch((e) => console.error('[useAbly] updateTask(working) failed:', e.message));
+            queueTaskUpdate(base, tkn, data.task.id, { status: "working" }).catch((e) => console.error('[useAbly] updateTask(working) failed:', e.message));
           }
 
           // Announce task pickup in chat so all members see it
@@ -458,7 +515,7 @@ function subscribeToProjectChannel(
               const { token: t3 } = useAuthStore.getState();
               const { apiBaseUrl: url3 } = useSettingsStore.getState().settings;
               if (t3 && url3) {
-                updateTask(url3, t3, data.task.id, {
+                queueTaskUpdate(url3, t3, data.task.id, {
                   status: success ? "completed" : "failed",
                 }).catch((e) => console.error('[useAbly] updateTask(result) failed:', e.message));
 
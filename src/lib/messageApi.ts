@@ -1,4 +1,5 @@
 import type { Message } from "@/types/message";
+import { isTauri } from "@/lib/platform";
 
 interface ServerMessage {
   id: string;
@@ -66,7 +67,7 @@ export async function fetchMessages(
   };
 }
 
-/** Fire-and-forget persist a message to the server. */
+/** Fire-and-forget persist a message to the server (via the durable outbox on desktop). */
 export function persistMessage(
   apiBaseUrl: string,
   token: string,
@@ -80,6 +81,13 @@ export function persistMessage(
     parentMessageId?: string;
   },
 ): void {
+  // On desktop, queue through the backend outbox so the write survives network drops
+  if (isTauri) {
+    import("@tauri-apps/api/core")
+      .then(({ invoke }) => invoke<void>("outbox_send_message", { projectId, message }))
+      .catch((e) => console.error('[persistMessage] Outbox enqueue failed:', e));
+    return;
+  }
   fetch(`${apiBaseUrl}/api/projects/${projectId}/messages`, {
     method: "POST",
     headers: {
@@ -90,13 +90,19 @@ export async function getSettings(): Promise<AppSettings> {
   return saved ?? { ...DEFAULT_SETTINGS };
 }
 
-export async function saveSettings(settings: AppSettings): Promise<void> {
+/**
+ * Save settings and return them as stored. On desktop the backend fills in
+ * generated values (local API token, webhook secret) and writes the store
+ * itself; keep the returned copy so later saves don't drop them.
+ */
+export async function saveSettings(settings: AppSettings): Promise<AppSettings> {
   if (isTauri) {
     const { invoke } = await import("@tauri-apps/api/core");
-    await invoke<void>("save_settings", { settings });
+    return invoke<AppSettings>("save_settings", { settings });
   }
-  // Always persist to storage as fallback (Tauri: JS store backup, Web: primary)
+  // Web: localStorage is the only copy
   await storage.set("app_settings", settings);
+  return settings;
 }
 
 // ── Agent execution ──────────────────────────────────────────────────
@@ -106,12 +112,35 @@ export async function runAgent(
   projectDir: string,
   agentProvider?: string,
   customCommand?: string,
+  model?: string,
 ): Promise<string> {
   if (!isTauri) {
     throw new Error("Agent execution is only available on desktop agent machines.");
   }
   const { invoke } = await import("@tauri-apps/api/core");
-  return invoke<string>("run_agent", { prompt, projectDir, agentProvider, customCommand });
+  return invoke<string>("run_agent", { prompt, projectDir, agentProvider, customCommand, model });
+}
+
+/** Model and sampling settings. Unset fields use the provider's defaults. */
+export interface ModelConfig {
+  model?: string;
+  maxTokens?: number;
+  temperature?: number;
+  topP?: number;
+  stopSequences?: string[];
+}
+
+export interface ProviderModels {
+  provider: string;
+  models: Array<{ id: string; displayName: string | null }>;
+  error: string | null;
+}
+
+/** Models each provider offers (or one provider when given). */
+export async function listModels(provider?: string): Promise<ProviderModels[]> {
+  if (!isTauri) return [];
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<ProviderModels[]>("list_models", { provider });
 }
 
 export async function runAgentStream(params: {
@@ -131,6 +160,24 @@ export async function runAgentStream(params: {
   }>;
   sessionId?: string;
   continueSession?: boolean;
+  agentName?: string;
+  contextMessages?: Array<{
+    author: string;
+    role: "user" | "assistant";
+    text: string;
+    timestamp?: string;
+    attachments?: Array<{
+      id: string;
+      filename: string;
+      mimeType: string;
+      fileSize: number;
+      blobUrl: string;
+    }>;
+  }>;
+  threadId?: string;
+  thinkingBudget?: number;
+  modelConfig?: ModelConfig;
+  modelOverride?: ModelConfig;
 }): Promise<void> {
   if (!isTauri) {
     throw new Error("Agent streaming is only available on desktop agent machines.");
@@ -139,6 +186,13 @@ export async function runAgentStream(params: {
   await invoke<void>("run_agent_stream", params);
 }
 
+/** Answer an `agent-tool-approval` prompt. False if the call stopped waiting. */
+export async function approveToolCall(responseId: string, toolUseId: string, approved: boolean): Promise<boolean> {
+  if (!isTauri) return false;
+  const { invoke } = await import("@tauri-apps/api/core");
+  return invoke<boolean>("approve_tool_call", { responseId, toolUseId, approved });
+}
+
 // ── Shell (open URL) ─────────────────────────────────────────────────
 
 export async function openUrl(url: string): Promise<void> {
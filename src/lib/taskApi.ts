@@ -1,4 +1,5 @@
 import type { Task, TaskStatus, TaskPriority } from "@/types/task";
+import { isTauri } from "@/lib/platform";
 
 interface ServerTaskPage {
   data: Task[];
@@ -89,6 +90,24 @@ export async function updateTask(
   return task;
 }
 
+/**
+ * Fire-and-forget task update. On desktop it goes through the backend outbox,
+ * so a status change made while offline is delivered once the network returns.
+ */
+export async function queueTaskUpdate(
+  apiBaseUrl: string,
+  token: string,
+  taskId: string,
+  updates: Partial<Pick<Task, "status" | "priority" | "assignedAgentName">>,
+): Promise<void> {
+  if (isTauri) {
+    const { invoke } = await import("@tauri-apps/api/core");
+    await invoke<void>("outbox_update_task", { taskId, updates });
+    return;
+  }
+  await updateTask(apiBaseUrl, token, taskId, updates);
+}
+
 /** Delete a task. */
 export async function deleteTask(
   apiBaseUrl: string,
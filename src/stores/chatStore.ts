@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import type { Message } from "@/types/message";
+import type { Message, ToolCall } from "@/types/message";
 import { scheduleSave } from "@/lib/chatPersistence";
 
 interface ChatStore {
@@ -8,6 +8,8 @@ interface ChatStore {
   addMessage: (msg: Message) => void;
   replaceMessage: (conversationId: string, messageId: string, text: string, status: Message["status"]) => void;
   appendChunk: (conversationId: string, messageId: string, chunk: string) => void;
+  appendThinking: (conversationId: string, messageId: string, chunk: string) => void;
+  upsertToolCall: (conversationId: string, messageId: string, call: Pick<ToolCall, "id" | "name" | "status"> & Partial<ToolCall>) => void;
   markDone: (conversationId: string, messageId: string) => void;
   updateStatus: (conversationId: string, messageId: string, status: Message["status"]) => void;
   mergeServerMessages: (conversationId: string, serverMessages: Message[]) => void;
@@ -56,6 +58,34 @@ export const useChatStore = create<ChatStore>((set) => ({
       };
       return { messages: withSave(updated) };
     }),
+  appendThinking: (conversationId, messageId, chunk) =>
+    set((s) => {
+      const msgs = s.messages[conversationId] ?? [];
+      const updated: Record<string, Message[]> = {
+        ...s.messages,
+        [conversationId]: msgs.map((m) =>
+          m.id === messageId ? { ...m, thinking: (m.thinking ?? "") + chunk } : m
+        ),
+      };
+      return { messages: withSave(updated) };
+    }),
+  upsertToolCall: (conversationId, messageId, call) =>
+    set((s) => {
+      const msgs = s.messages[conversationId] ?? [];
+      const updated: Record<string, Message[]> = {
+        ...s.messages,
+        [conversationId]: msgs.map((m) => {
+          if (m.id !== messageId) return m;
+          const calls = m.toolCalls ?? [];
+          const exists = calls.some((c) => c.id === call.id);
+          return {
+            ...m,
+            toolCalls: exists ? calls.map((c) => (c.id === call.id ? { ...c, ...call } : c)) : [...calls, { input: {}, ...call }],
+          };
+        }),
+      };
+      return { messages: withSave(updated) };
+    }),
   markDone: (conversationId, messageId) =>
     set((s) => {
       const msgs = s.messages[conversationId] ?? [];
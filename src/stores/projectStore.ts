@@ -1,4 +1,5 @@
 import { create } from "zustand";
+import type { ModelConfig } from "@/lib/platform";
 
 export interface LocalProject {
   projectId: string;        // UUID matching server project.id
@@ -10,6 +11,8 @@ export interface LocalProject {
   agentProvider: string;    // "claude" | "codex" | "gemini" | "custom"
   customCommand?: string;   // for "custom" provider: command template with {prompt} placeholder
   currentSessionId?: string; // Claude session ID for --resume (persisted across restarts)
+  thinkingBudget?: number;  // Claude extended thinking budget in tokens; unset = off
+  modelConfig?: ModelConfig; // model, max tokens, temperature, top_p, stop sequences
 }
 
 interface ProjectStore {
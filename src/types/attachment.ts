@@ -31,4 +31,6 @@ export interface MessageAttachment {
   mimeType: string;
   fileSize: number;
   blobUrl: string;
+  /** File on the agent machine, inside the project directory (blobUrl is then empty). */
+  localPath?: string;
 }
@@ -2,12 +2,23 @@ import type { MessageAttachment } from "./attachment";
 
 export type ExecutionMode = "auto" | "plan";
 
+/** A local tool call made by a Claude API run (read_file, run_command, ...). */
+export interface ToolCall {
+  id: string;
+  name: string;
+  input: Record<string, unknown>;
+  status: "running" | "done" | "error";
+  output?: string;         // preview of the result
+}
+
 export interface Message {
   id: string;
   conversationId: string;  // projectId UUID
   sender: "me" | "them";
   senderName?: string;     // display name for group chat attribution
   text: string;
+  thinking?: string;       // agent's extended thinking, shown collapsed (local only)
+  toolCalls?: ToolCall[];  // tools the agent used while answering (local only)
   timestamp: string;
   status: "sending" | "sent" | "streaming" | "done" | "error";
   executionMode?: ExecutionMode;  // "auto" or "plan" — affects agent behavior
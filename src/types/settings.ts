@@ -4,4 +4,24 @@ export interface AppSettings {
   googleApiKey: string | null;     // fallback API key for Gemini CLI
   machineName: string;             // cosmetic label for this device
   apiBaseUrl: string;              // "https://api.jaibber.com"
+  localApiEnabled?: boolean;       // expose agent runs on a localhost HTTP/WebSocket API
+  localApiPort?: number;           // default 7420
+  localApiToken?: string | null;   // bearer token, generated by the backend on first enable; null keeps it, "" regenerates it
+  taskWorkerEnabled?: boolean;     // execute assigned tasks in the Rust backend instead of the webview
+  taskWorkerPollSecs?: number;     // default 15
+  webhookEnabled?: boolean;        // accept HMAC-signed webhooks that start agent runs
+  webhookBind?: string;            // default "127.0.0.1"
+  webhookPort?: number;            // default 7421
+  webhookSecret?: string | null;   // shared HMAC secret, generated by the backend on first enable; null keeps it, "" regenerates it
+  contextTokenBudget?: number;     // token budget for chat history sent with each prompt (default 16000)
+  historySummaryEnabled?: boolean; // summarize history beyond the budget with a cheap model (default true)
+  summaryModel?: string;           // default "claude-3-5-haiku-20241022"
+  apiTools?: {                     // local tools for the Claude API provider
+    enabled: boolean;
+    read: "allow" | "ask" | "deny";        // read_file, list_dir, grep
+    write: "allow" | "ask" | "deny";       // write_file, apply_patch
+    runCommand: "allow" | "ask" | "deny";  // run_command
+    allowedCommands: string[];             // command prefixes that skip the approval prompt
+  };
+  attachmentRetentionHours?: number; // keep files saved for CLI agents this long; 0 = delete after the run (default 24)
 }